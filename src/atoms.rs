@@ -0,0 +1,214 @@
+//! Reading of ISO base media files.
+//!
+//! QuickTime and MP4 movies, HEIC images and Canon CR3 files are all made of
+//! nested atoms (boxes): a size, a four-letter type and a body that may hold
+//! more atoms. This module finds atoms by type, leaving the meaning of their
+//! contents to the callers.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Read the body of the first top-level atom of type `wanted`, at most
+/// `max_length` bytes of it, skipping over everything before it
+pub fn read_top_level_atom<R: Read + Seek>(
+    reader: &mut R,
+    wanted: &[u8; 4],
+    max_length: u64,
+) -> io::Result<Option<Vec<u8>>> {
+    loop {
+        let mut header = [0u8; 8];
+        if !read_header(reader, &mut header)? {
+            return Ok(None);
+        }
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_length = 8;
+        if size == 1 {
+            let mut large_size = [0u8; 8];
+            if !read_header(reader, &mut large_size)? {
+                return Ok(None);
+            }
+            size = u64::from_be_bytes(large_size);
+            header_length = 16;
+        }
+
+        if size == 0 && &header[4..8] != wanted {
+            // The last atom runs to the end of the file
+            return Ok(None);
+        }
+        if size != 0 && size < header_length {
+            return Ok(None);
+        }
+
+        if &header[4..8] == wanted {
+            let length = if size == 0 {
+                max_length
+            } else {
+                (size - header_length).min(max_length)
+            };
+            let mut body = Vec::new();
+            reader.take(length).read_to_end(&mut body)?;
+            return Ok(Some(body));
+        }
+
+        // A size past what a seek can reach is no real file
+        let Ok(skip) = i64::try_from(size - header_length) else {
+            return Ok(None);
+        };
+        reader.seek(SeekFrom::Current(skip))?;
+    }
+}
+
+/// Fill `buffer` from `reader`, or return false at the end of the file
+fn read_header(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// The child atoms of an atom body, as type and body
+pub fn atoms(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+
+    while let Some(header) = data.get(offset..offset + 8) {
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let atom_type = [header[4], header[5], header[6], header[7]];
+        let (body_start, end) = match size {
+            0 => (offset + 8, data.len()),
+            1 => {
+                let Some(large_size) = data.get(offset + 8..offset + 16) else {
+                    break;
+                };
+                let large_size = u64::from_be_bytes(large_size.try_into().unwrap_or_default());
+                (offset + 16, offset.saturating_add(large_size as usize))
+            }
+            _ => (offset + 8, offset + size),
+        };
+        if end < body_start || end > data.len() {
+            break;
+        }
+        found.push((atom_type, &data[body_start..end]));
+        offset = end;
+    }
+
+    found
+}
+
+/// The body of the first child atom of type `wanted`
+pub fn find_atom<'a>(data: &'a [u8], wanted: &[u8; 4]) -> Option<&'a [u8]> {
+    atoms(data)
+        .into_iter()
+        .find(|(atom_type, _)| atom_type == wanted)
+        .map(|(_, body)| body)
+}
+
+/// The value stored under `key` in the QuickTime metadata of a `moov` atom,
+/// in a `meta` atom of its own or of its `udta`
+pub fn quicktime_metadata_value<'a>(moov: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    atoms(moov)
+        .into_iter()
+        .find_map(|(atom_type, body)| match &atom_type {
+            b"meta" => meta_value(body, key),
+            b"udta" => quicktime_metadata_value(body, key),
+            _ => None,
+        })
+}
+
+/// Look a value up in a `meta` atom: `keys` lists the key names, and `ilst`
+/// holds the values under their one-based key index
+fn meta_value<'a>(meta: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    // QuickTime `meta` atoms start with their children, ISO ones with a
+    // version and flags first
+    let children = if meta.get(4..8) == Some(b"hdlr") {
+        meta
+    } else {
+        meta.get(4..)?
+    };
+
+    let mut index = None;
+    let mut item_list = None;
+    for (atom_type, body) in atoms(children) {
+        match &atom_type {
+            b"keys" => index = key_index(body, key),
+            b"ilst" => item_list = Some(body),
+            _ => {}
+        }
+    }
+    let (index, item_list) = (index?, item_list?);
+
+    let (_, item) = atoms(item_list)
+        .into_iter()
+        .find(|(atom_type, _)| u32::from_be_bytes(*atom_type) == index)?;
+    let data = find_atom(item, b"data")?;
+
+    // The value follows a type indicator and a locale
+    data.get(8..)
+}
+
+/// The one-based index of `key` in a `keys` atom
+fn key_index(keys: &[u8], key: &[u8]) -> Option<u32> {
+    let count = u32::from_be_bytes(keys.get(4..8)?.try_into().ok()?);
+    let mut offset = 8;
+    for index in 1..=count {
+        let size = u32::from_be_bytes(keys.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        if size < 8 {
+            return None;
+        }
+        // Each entry is a size, a namespace and the key name
+        if keys.get(offset + 8..offset + size)? == key {
+            return Some(index);
+        }
+        offset += size;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::atom;
+    use std::io::Cursor;
+
+    fn read(data: Vec<u8>, wanted: &[u8; 4]) -> Option<Vec<u8>> {
+        read_top_level_atom(&mut Cursor::new(data), wanted, 100).unwrap()
+    }
+
+    #[test]
+    fn atoms_are_found_after_the_ones_before_them() {
+        let data = [atom(b"ftyp", b"qt  "), atom(b"moov", b"movie")].concat();
+
+        assert_eq!(read(data.clone(), b"moov").as_deref(), Some(&b"movie"[..]));
+        assert_eq!(read(data, b"meta"), None);
+    }
+
+    #[test]
+    fn an_atom_of_size_zero_runs_to_the_end() {
+        let data = [&atom(b"ftyp", b"qt  ")[..], b"\0\0\0\0moovmovie"].concat();
+
+        assert_eq!(read(data.clone(), b"moov").as_deref(), Some(&b"movie"[..]));
+        assert_eq!(read(data, b"meta"), None);
+    }
+
+    #[test]
+    fn an_atom_of_size_one_has_a_large_size() {
+        let large = [&b"\0\0\0\x01mdat"[..], &21u64.to_be_bytes(), b"frame"].concat();
+        let data = [large, atom(b"moov", b"movie")].concat();
+
+        assert_eq!(read(data.clone(), b"mdat").as_deref(), Some(&b"frame"[..]));
+        assert_eq!(read(data, b"moov").as_deref(), Some(&b"movie"[..]));
+    }
+
+    #[test]
+    fn truncated_and_impossible_atoms_end_the_search() {
+        let truncated = b"\0\0\0\x10mdat".to_vec();
+        let too_short = [&b"\0\0\0\x04mdat"[..], &atom(b"moov", b"movie")].concat();
+        let huge = [&b"\0\0\0\x01mdat"[..], &u64::MAX.to_be_bytes()].concat();
+        let missing_large_size = b"\0\0\0\x01mdat\0\0".to_vec();
+
+        assert_eq!(read(truncated, b"moov"), None);
+        assert_eq!(read(too_short, b"moov"), None);
+        assert_eq!(read(huge, b"moov"), None);
+        assert_eq!(read(missing_large_size, b"moov"), None);
+    }
+}
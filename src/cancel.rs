@@ -0,0 +1,56 @@
+//! Cooperative cancellation of long-running operations.
+//!
+//! A [`CancellationToken`] is handed to the scan, copy, verify and delete
+//! functions. Cancelling it does not interrupt a file that is already being
+//! processed; it stops new files from being started, and the files left
+//! alone are reported as skipped with the reason [`CANCELLED`].
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Skip reason given to files that were not started because of cancellation
+pub const CANCELLED: &str = "cancelled";
+
+/// A shared flag that asks running operations to stop.
+///
+/// Clones share the same flag, so one clone can be given to a signal handler
+/// or another thread while the others are passed to the operations.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Ask every operation using this token to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fail with [`io::ErrorKind::Interrupted`] once the token is cancelled
+    pub fn check(&self) -> io::Result<()> {
+        if self.is_cancelled() {
+            Err(cancelled_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The error returned by operations that stop because they were cancelled
+pub fn cancelled_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "Operation cancelled")
+}
+
+/// Whether an error means the operation was cancelled
+pub fn is_cancelled_error(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::Interrupted
+}
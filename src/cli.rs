@@ -0,0 +1,482 @@
+//! Command-line argument parsing.
+//!
+//! This module turns the process arguments into the options for a run. When
+//! a source and destination are given the tool runs headless in the
+//! terminal; without them it falls back to the folder dialogs on Windows.
+
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::filter::PathFilter;
+use crate::layout::{CopyPlacement, DestinationLayout};
+use crate::media::{DetectionMode, KindFilter, MediaKind, PairMode, ScanOptions};
+use crate::rename::RenameTemplate;
+use crate::walk::SymlinkPolicy;
+
+/// Usage text printed for `--help` and after argument errors
+pub const USAGE: &str = "\
+Usage: image_mover [OPTIONS] [SOURCE DESTINATION]
+
+Copies media files from SOURCE to DESTINATION, then optionally verifies the
+copies and deletes the originals. Without SOURCE and DESTINATION the folders
+are chosen with dialogs on Windows and asked for in the terminal elsewhere.
+
+Options:
+  -n, --dry-run     Print the copy and delete plan without writing anything
+      --detect MODE How media files are recognised: 'extension' (the
+                    default), 'content' to read each file's magic bytes, or
+                    'both' to read the content and fall back to the
+                    extension for formats it cannot recognise
+      --kinds LIST  Only import these kinds of media file, e.g. 'raw,video'
+      --skip-kinds LIST
+                    Do not import these kinds, e.g. 'vector'. Kinds are
+                    image, raw, video, pro-video and vector (SVG and ICO)
+      --pairs MODE  What to import of a RAW file and the JPEG shot with it:
+                    'both' (the default), kept together under one name,
+                    'raw' or 'jpeg'. The other half stays in SOURCE
+      --include PATTERN
+                    Only import media files matching PATTERN; may be given
+                    more than once. Their sidecars come along regardless
+      --exclude PATTERN
+                    Skip files and folders matching PATTERN, e.g.
+                    '**/@eaDir/**' or '*_edited.*'; may be given more than once
+      --no-motion   Import only the still of a Live Photo; its movie stays
+                    in SOURCE. Live Photos are otherwise kept together.
+                    Google and Samsung motion photos, whose video is inside
+                    the image file, are copied without it, and their
+                    originals stay in SOURCE
+      --symlinks MODE
+                    What to do with symbolic links in SOURCE: 'skip' (the
+                    default), 'follow' or 'copy' the links themselves
+      --layout LAYOUT
+                    How copies are arranged in DESTINATION: 'mirror' (the
+                    default) recreates the source folders, 'flat' puts them
+                    all in DESTINATION itself, and a date pattern such as
+                    'YYYY/MM/DD' or 'YYYY/YYYY-MM-DD' files them by the day
+                    they were taken
+      --flatten     The same as --layout flat
+      --by-camera   Put copies in a folder per camera first, named after its
+                    make, model and serial number, e.g. 'Canon EOS R5'
+      --camera-fallback FOLDER
+                    Folder for files that do not say which camera took them,
+                    with --by-camera (default: 'unknown camera')
+      --rename TEMPLATE
+                    Name copies after TEMPLATE, e.g.
+                    '{date:%Y%m%d_%H%M%S}_{camera_model}_{seq:04}.{ext_lower}'
+  -y, --yes         Start copying without asking for confirmation
+      --stream      Start copying files as soon as they are found, instead
+                    of after the whole scan. The files are counted in a first
+                    pass for the confirmation. Ignored when --rename uses
+                    {seq}, which numbers files in scan order
+      --verify      Verify copies by content hash before deleting originals
+      --no-verify   Do not verify copies
+      --delete      Delete the originals after copying
+      --keep        Keep the originals
+      --resume      Resume an unfinished import in the destination
+      --no-resume   Start over even if an unfinished import is found
+      --config FILE Read settings from FILE instead of the default
+                    configuration file
+      --list-extensions
+                    Print the extensions that are recognised as media, after
+                    applying the configuration, and exit
+  -h, --help        Print this help
+
+Questions not answered by an option are asked in the terminal. When input
+is not a terminal they get the safe answer: no copy without --yes, verify,
+keep the originals and resume.
+
+Patterns use .gitignore syntax and are relative to SOURCE. A file named
+.image_mover_ignore in any folder of SOURCE adds exclude patterns for that
+folder, like a .gitignore.
+
+Date layouts take the capture time from EXIF DateTimeOriginal in JPEG,
+TIFF, RAW and HEIC files, and from the creation time recorded in MP4,
+QuickTime and Matroska videos. Other files fall back to the time they were
+last modified (in UTC). Companions such as sidecars go with their file.
+
+Rename templates fill in {date} or {date:FORMAT} (with %Y, %y, %m, %d,
+%H, %M and %S), {camera_model}, {orig_stem}, {ext}, {ext_lower}, {seq} or
+{seq:WIDTH} (the position in the import, from 1, at most 20 digits wide)
+and {hash8} (the start of the SHA-256 of the contents). Sidecars take the
+new name of their file, and names already taken still get a _1, _2, ...
+suffix. Such renames are listed at the end with the original they were
+copied from, which matters most with a flat layout, where files of different
+folders meet.
+
+Symbolic links are never followed unless asked for, and sockets, FIFOs and
+device files are always skipped. With '--symlinks follow' a folder reached
+twice is only walked once. Deleting an original found inside a linked folder
+deletes the file itself, wherever it is, while deleting one reached through a
+link to a file only removes the link.
+
+Sidecar files (.xmp, .thm, .aae, .lrv, .lrf, .srt and .json) named after a
+media file are copied and deleted together with it, and follow its renames.
+
+The configuration file (config.ini in %APPDATA%\\image_mover on Windows,
+~/.config/image_mover elsewhere) adds or removes extensions and kinds:
+
+  [extensions]
+  hif = image
+  ts = none
+
+  [kinds]
+  vector = off
+
+A streamed import that is stopped resumes the files it had found; run it
+again afterwards for the rest.
+
+Ctrl+C stops the run once the files in progress are done; the unfinished
+import can then be resumed. Press it again to exit at once.";
+
+/// Options for a single run of the tool
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CliOptions {
+    pub source: Option<PathBuf>,
+    pub destination: Option<PathBuf>,
+    pub dry_run: bool,
+    /// Skip the copy confirmation
+    pub assume_yes: bool,
+    /// Copy files while the scan is still running
+    pub stream: bool,
+    /// Answer to the verification question, if given
+    pub verify: Option<bool>,
+    /// Answer to the deletion question, if given
+    pub delete: Option<bool>,
+    /// Answer to the resume question, if given
+    pub resume: Option<bool>,
+    /// How the scan recognises media files
+    pub detection: DetectionMode,
+    /// The only kinds of media file to import, if restricted
+    pub only_kinds: Option<Vec<MediaKind>>,
+    /// Kinds of media file not to import
+    pub skip_kinds: Vec<MediaKind>,
+    /// What to import of RAW+JPEG pairs
+    pub pairs: PairMode,
+    /// Leave the movie of a Live Photo, and the video of a motion photo, out
+    pub drop_motion: bool,
+    /// Include and exclude patterns for the walk
+    pub filter: PathFilter,
+    /// What the walk does with symbolic links
+    pub symlinks: SymlinkPolicy,
+    /// Where copies go in the destination and what they are called
+    pub placement: CopyPlacement,
+    /// Configuration file to use instead of the default one
+    pub config: Option<PathBuf>,
+}
+
+impl CliOptions {
+    /// Whether the folders were given on the command line, so no dialogs are needed
+    pub fn is_headless(&self) -> bool {
+        self.source.is_some() && self.destination.is_some()
+    }
+
+    /// The settings for scanning the source folder, on top of `config`
+    pub fn scan_options(&self, config: &Config) -> ScanOptions {
+        let kinds = match &self.only_kinds {
+            Some(kinds) => KindFilter::only(kinds),
+            None => KindFilter::all(),
+        };
+        ScanOptions {
+            detection: self.detection,
+            kinds: kinds
+                .excluding(&config.skipped_kinds)
+                .excluding(&self.skip_kinds),
+            extensions: config.extension_table(),
+            pairs: self.pairs,
+            drop_motion: self.drop_motion,
+            filter: self.filter.clone(),
+            symlinks: self.symlinks,
+        }
+    }
+}
+
+/// What the command line asks the tool to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Run(CliOptions),
+    /// Print the effective extension table
+    ListExtensions(CliOptions),
+    Help,
+}
+
+/// Set a yes/no answer, refusing contradictory options
+fn set_answer(slot: &mut Option<bool>, value: bool, arg: &str) -> Result<(), String> {
+    match slot {
+        Some(existing) if *existing != value => {
+            Err(format!("'{}' conflicts with an earlier option", arg))
+        }
+        _ => {
+            *slot = Some(value);
+            Ok(())
+        }
+    }
+}
+
+/// The value of an option given as `--name value` or `--name=value`
+fn option_value(
+    name: &str,
+    inline: Option<&str>,
+    rest: &mut impl Iterator<Item = String>,
+) -> Result<String, String> {
+    match inline {
+        Some(value) => Ok(value.to_string()),
+        None => rest
+            .next()
+            .ok_or_else(|| format!("'{}' requires a value", name)),
+    }
+}
+
+/// Parse a comma-separated list of media kinds
+fn parse_kinds(value: &str) -> Result<Vec<MediaKind>, String> {
+    value
+        .split(',')
+        .map(|name| {
+            MediaKind::from_name(name.trim())
+                .ok_or_else(|| format!("Unknown media kind '{}'", name.trim()))
+        })
+        .collect()
+}
+
+/// Parse the arguments that follow the program name
+pub fn parse_args<I>(args: I) -> Result<Command, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut options = CliOptions::default();
+    let mut folders: Vec<PathBuf> = Vec::new();
+    let mut only_folders = false;
+    let mut list_extensions = false;
+    let mut include_patterns: Vec<String> = Vec::new();
+    let mut exclude_patterns: Vec<String> = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if only_folders || !arg.starts_with('-') || arg == "-" {
+            folders.push(PathBuf::from(arg));
+            continue;
+        }
+
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+
+        match name {
+            "--detect" => {
+                let value = option_value(name, inline_value, &mut args)?;
+                options.detection = DetectionMode::from_name(&value)
+                    .ok_or_else(|| format!("Unknown detection mode '{}'", value))?;
+                continue;
+            }
+            "--kinds" => {
+                let value = option_value(name, inline_value, &mut args)?;
+                options.only_kinds = Some(parse_kinds(&value)?);
+                continue;
+            }
+            "--skip-kinds" => {
+                let value = option_value(name, inline_value, &mut args)?;
+                options.skip_kinds.extend(parse_kinds(&value)?);
+                continue;
+            }
+            "--pairs" => {
+                let value = option_value(name, inline_value, &mut args)?;
+                options.pairs = PairMode::from_name(&value)
+                    .ok_or_else(|| format!("Unknown pair mode '{}'", value))?;
+                continue;
+            }
+            "--layout" => {
+                let value = option_value(name, inline_value, &mut args)?;
+                options.placement.layout = DestinationLayout::from_spec(&value)?;
+                continue;
+            }
+            "--camera-fallback" => {
+                let value = option_value(name, inline_value, &mut args)?;
+                options.placement.set_camera_fallback(&value)?;
+                options.placement.by_camera = true;
+                continue;
+            }
+            "--rename" => {
+                let value = option_value(name, inline_value, &mut args)?;
+                options.placement.rename = Some(RenameTemplate::from_spec(&value)?);
+                continue;
+            }
+            "--symlinks" => {
+                let value = option_value(name, inline_value, &mut args)?;
+                options.symlinks = SymlinkPolicy::from_name(&value)
+                    .ok_or_else(|| format!("Unknown symbolic link mode '{}'", value))?;
+                continue;
+            }
+            "--include" => {
+                include_patterns.push(option_value(name, inline_value, &mut args)?);
+                continue;
+            }
+            "--exclude" => {
+                exclude_patterns.push(option_value(name, inline_value, &mut args)?);
+                continue;
+            }
+            "--config" => {
+                let value = option_value(name, inline_value, &mut args)?;
+                options.config = Some(PathBuf::from(value));
+                continue;
+            }
+            _ if inline_value.is_some() => {
+                return Err(format!("'{}' does not take a value", name));
+            }
+            _ => {}
+        }
+
+        match name {
+            "--" => only_folders = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "-n" | "--dry-run" => options.dry_run = true,
+            "--list-extensions" => list_extensions = true,
+            "-y" | "--yes" => options.assume_yes = true,
+            "--no-motion" => options.drop_motion = true,
+            "--stream" => options.stream = true,
+            "--by-camera" => options.placement.by_camera = true,
+            "--flatten" => options.placement.layout = DestinationLayout::Flat,
+            "--verify" => set_answer(&mut options.verify, true, &arg)?,
+            "--no-verify" => set_answer(&mut options.verify, false, &arg)?,
+            "--delete" => set_answer(&mut options.delete, true, &arg)?,
+            "--keep" => set_answer(&mut options.delete, false, &arg)?,
+            "--resume" => set_answer(&mut options.resume, true, &arg)?,
+            "--no-resume" => set_answer(&mut options.resume, false, &arg)?,
+            _ => return Err(format!("Unknown option '{}'", arg)),
+        }
+    }
+
+    options.filter =
+        PathFilter::new(&include_patterns, &exclude_patterns).map_err(|e| e.to_string())?;
+
+    match folders.len() {
+        0 => {}
+        2 => {
+            options.destination = folders.pop();
+            options.source = folders.pop();
+        }
+        1 => return Err("A destination folder is required with a source folder".to_string()),
+        _ => return Err("Too many folders given".to_string()),
+    }
+
+    if list_extensions {
+        return Ok(Command::ListExtensions(options));
+    }
+
+    Ok(Command::Run(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn options(args: &[&str]) -> CliOptions {
+        match parse(args) {
+            Ok(Command::Run(options)) => options,
+            other => panic!("expected options for {:?}, got {:?}", args, other),
+        }
+    }
+
+    #[test]
+    fn folders_and_answers() {
+        let options = options(&["-y", "src", "--verify", "dst", "--keep", "--no-resume"]);
+        assert_eq!(options.source, Some(PathBuf::from("src")));
+        assert_eq!(options.destination, Some(PathBuf::from("dst")));
+        assert!(options.is_headless());
+        assert!(options.assume_yes);
+        assert_eq!(options.verify, Some(true));
+        assert_eq!(options.delete, Some(false));
+        assert_eq!(options.resume, Some(false));
+        assert!(!options.dry_run);
+    }
+
+    #[test]
+    fn no_folders_is_not_headless() {
+        let options = options(&["--dry-run"]);
+        assert!(options.dry_run);
+        assert!(!options.is_headless());
+    }
+
+    #[test]
+    fn values_are_taken_separately_or_inline() {
+        let options = options(&[
+            "--detect",
+            "both",
+            "--kinds=raw, video",
+            "--skip-kinds",
+            "vector",
+            "--pairs=raw",
+            "--symlinks",
+            "copy",
+            "--layout=YYYY/MM",
+            "--camera-fallback",
+            "other",
+            "--rename={orig_stem}.{ext}",
+            "--config",
+            "my.ini",
+            "src",
+            "dst",
+        ]);
+        assert_eq!(options.detection, DetectionMode::Both);
+        assert_eq!(
+            options.only_kinds,
+            Some(vec![MediaKind::Raw, MediaKind::Video])
+        );
+        assert_eq!(options.skip_kinds, [MediaKind::Vector]);
+        assert_eq!(options.pairs, PairMode::Raw);
+        assert_eq!(options.symlinks, SymlinkPolicy::Copy);
+        assert_eq!(
+            options.placement.layout,
+            DestinationLayout::Dated("YYYY/MM".to_string())
+        );
+        assert!(options.placement.by_camera);
+        assert_eq!(options.placement.camera_fallback, "other");
+        assert!(options.placement.rename.is_some());
+        assert_eq!(options.config, Some(PathBuf::from("my.ini")));
+    }
+
+    #[test]
+    fn everything_after_double_dash_is_a_folder() {
+        let options = options(&["--", "--yes", "-n"]);
+        assert_eq!(options.source, Some(PathBuf::from("--yes")));
+        assert_eq!(options.destination, Some(PathBuf::from("-n")));
+        assert!(!options.assume_yes);
+        assert!(!options.dry_run);
+    }
+
+    #[test]
+    fn help_and_list_extensions() {
+        assert_eq!(parse(&["-y", "--help"]), Ok(Command::Help));
+        assert!(matches!(
+            parse(&["--list-extensions"]),
+            Ok(Command::ListExtensions(_))
+        ));
+    }
+
+    #[test]
+    fn mistakes_are_reported() {
+        for (args, message) in [
+            (
+                &["--verify", "--no-verify"][..],
+                "'--no-verify' conflicts with an earlier option",
+            ),
+            (&["--bogus"], "Unknown option '--bogus'"),
+            (&["--yes=1"], "'--yes' does not take a value"),
+            (&["--layout"], "'--layout' requires a value"),
+            (&["--kinds", "raw,sound"], "Unknown media kind 'sound'"),
+            (
+                &["src"],
+                "A destination folder is required with a source folder",
+            ),
+            (&["a", "b", "c"], "Too many folders given"),
+        ] {
+            assert_eq!(parse(args), Err(message.to_string()), "{:?}", args);
+        }
+        assert!(parse(&["--camera-fallback", "../up"]).is_err());
+        assert!(parse(&["--rename", "{nope}"]).is_err());
+        assert!(parse(&["--include", "a/{b"]).is_err());
+    }
+}
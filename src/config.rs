@@ -0,0 +1,283 @@
+//! User configuration loaded from a file.
+//!
+//! The configuration adjusts the built-in extension table without a rebuild:
+//! extensions can be added, reclassified or removed, and whole kinds of media
+//! file can be left out of every import. The file is plain text:
+//!
+//! ```text
+//! # Lines starting with '#' are comments
+//! [extensions]
+//! hif = image      # add or reclassify an extension
+//! ts = none        # stop treating .ts files as media
+//!
+//! [kinds]
+//! vector = off     # never import SVG and ICO files
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::media::{ExtensionTable, MediaKind};
+
+/// File name of the configuration inside the configuration folder
+pub const CONFIG_FILE_NAME: &str = "config.ini";
+
+/// Settings read from a configuration file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Extensions added to the table or moved to another kind
+    pub added_extensions: BTreeMap<String, MediaKind>,
+    /// Extensions taken out of the table
+    pub removed_extensions: BTreeSet<String>,
+    /// Kinds of media file never imported
+    pub skipped_kinds: Vec<MediaKind>,
+    /// The file the configuration was read from, if any
+    pub path: Option<PathBuf>,
+}
+
+impl Config {
+    /// Read the configuration from `path`, or from the default location if
+    /// no path is given. A missing default file is not an error and gives
+    /// the built-in settings.
+    pub fn load(path: Option<&Path>) -> io::Result<Config> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_config_path() {
+                Some(path) if path.is_file() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+
+        let text = fs::read_to_string(&path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Cannot read configuration '{}': {}", path.display(), e),
+            )
+        })?;
+        let mut config = Config::parse(&text)
+            .map_err(|e| io::Error::new(e.kind(), format!("In '{}': {}", path.display(), e)))?;
+        config.path = Some(path);
+        Ok(config)
+    }
+
+    /// Parse the text of a configuration file
+    pub fn parse(text: &str) -> io::Result<Config> {
+        let mut config = Config::default();
+        let mut section = String::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let invalid = |message: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", index + 1, message),
+                )
+            };
+
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                section = name.trim().to_lowercase();
+                if section != "extensions" && section != "kinds" {
+                    return Err(invalid(format!("unknown section [{}]", section)));
+                }
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("expected 'name = value', found '{}'", line)))?;
+            let key = key.trim().trim_start_matches('.').to_lowercase();
+            let value = value.trim().to_lowercase();
+
+            match section.as_str() {
+                "extensions" => {
+                    if key.is_empty() {
+                        return Err(invalid("missing extension".to_string()));
+                    }
+                    if value == "none" {
+                        config.added_extensions.remove(&key);
+                        config.removed_extensions.insert(key);
+                    } else {
+                        let kind = MediaKind::from_name(&value)
+                            .ok_or_else(|| invalid(format!("unknown media kind '{}'", value)))?;
+                        config.removed_extensions.remove(&key);
+                        config.added_extensions.insert(key, kind);
+                    }
+                }
+                "kinds" => {
+                    let kind = MediaKind::from_name(&key)
+                        .ok_or_else(|| invalid(format!("unknown media kind '{}'", key)))?;
+                    config.skipped_kinds.retain(|skipped| *skipped != kind);
+                    match value.as_str() {
+                        "on" => {}
+                        "off" => config.skipped_kinds.push(kind),
+                        _ => {
+                            return Err(invalid(format!(
+                                "expected 'on' or 'off', found '{}'",
+                                value
+                            )))
+                        }
+                    }
+                }
+                _ => return Err(invalid("setting outside of a section".to_string())),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// The built-in extension table with this configuration applied
+    pub fn extension_table(&self) -> ExtensionTable {
+        let mut table = ExtensionTable::builtin();
+        for extension in &self.removed_extensions {
+            table.remove(extension);
+        }
+        for (extension, kind) in &self.added_extensions {
+            table.insert(extension, *kind);
+        }
+        table
+    }
+
+    /// The effective extension table as printable lines, noting where each
+    /// entry differs from the built-in table
+    pub fn describe_extension_table(&self) -> Vec<String> {
+        let table = self.extension_table();
+        let mut lines = vec![format!("{:<10} {:<10} Source", "Extension", "Kind")];
+
+        let mut extensions: BTreeSet<&str> =
+            table.entries().map(|(extension, _)| extension).collect();
+        extensions.extend(self.removed_extensions.iter().map(String::as_str));
+
+        for extension in extensions {
+            let kind = table.kind(extension);
+            let mut source = match kind {
+                Some(_) if self.added_extensions.contains_key(extension) => "config",
+                Some(_) => "built-in",
+                None => "removed by config",
+            }
+            .to_string();
+            if kind.is_some_and(|kind| self.skipped_kinds.contains(&kind)) {
+                source.push_str(", kind off");
+            }
+            let kind = kind.map_or("-", |kind| kind.name());
+            lines.push(format!("{:<10} {:<10} {}", extension, kind, source));
+        }
+
+        lines.push(String::new());
+        lines.push(match &self.path {
+            Some(path) => format!("Configuration: {}", path.display()),
+            None => "Configuration: none, built-in table".to_string(),
+        });
+        if !self.skipped_kinds.is_empty() {
+            let names: Vec<&str> = self.skipped_kinds.iter().map(MediaKind::name).collect();
+            lines.push(format!("Kinds not imported: {}", names.join(", ")));
+        }
+
+        lines
+    }
+}
+
+/// Where the configuration file is looked for when none is given:
+/// `%APPDATA%\image_mover` on Windows, `$XDG_CONFIG_HOME/image_mover` or
+/// `~/.config/image_mover` elsewhere
+pub fn default_config_path() -> Option<PathBuf> {
+    let config_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    }?;
+
+    Some(config_dir.join("image_mover").join(CONFIG_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn settings_adjust_the_builtin_table() {
+        let config = Config::parse(
+            "# my camera\n\
+             [Extensions]\n\
+             .HIF = image   # new\n\
+             ts = none\n\
+             mts = none\n\
+             mts = pro-video\n\
+             \n\
+             [kinds]\n\
+             vector = off\n\
+             raw = off\n\
+             raw = on\n",
+        )
+        .unwrap();
+        let table = config.extension_table();
+
+        assert_eq!(table.kind("hif"), Some(MediaKind::Image));
+        assert_eq!(table.kind("ts"), None);
+        assert_eq!(table.kind("mts"), Some(MediaKind::ProVideo));
+        assert_eq!(table.kind("jpg"), Some(MediaKind::Image));
+        assert_eq!(config.skipped_kinds, [MediaKind::Vector]);
+        assert!(config
+            .describe_extension_table()
+            .contains(&format!("{:<10} {:<10} removed by config", "ts", "-")));
+    }
+
+    #[test]
+    fn mistakes_are_reported_with_their_line() {
+        for (text, message) in [
+            ("[colours]", "line 1: unknown section [colours]"),
+            ("hif = image", "line 1: setting outside of a section"),
+            (
+                "[extensions]\nhif",
+                "line 2: expected 'name = value', found 'hif'",
+            ),
+            ("[extensions]\n= image", "line 2: missing extension"),
+            (
+                "[extensions]\nhif = photo",
+                "line 2: unknown media kind 'photo'",
+            ),
+            ("[kinds]\nsound = off", "line 2: unknown media kind 'sound'"),
+            (
+                "[kinds]\nvideo = no",
+                "line 2: expected 'on' or 'off', found 'no'",
+            ),
+        ] {
+            let error = Config::parse(text).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(error.to_string(), message, "{}", text);
+        }
+    }
+
+    #[test]
+    fn a_given_file_must_exist_and_names_itself_in_errors() {
+        let dir = TempDir::new();
+        let path = dir.write("config.ini", "[kinds]\nvector = maybe\n");
+
+        let error = Config::load(Some(&path)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "In '{}': line 2: expected 'on' or 'off', found 'maybe'",
+                path.display()
+            )
+        );
+        assert!(Config::load(Some(&dir.path().join("missing.ini"))).is_err());
+
+        fs::write(&path, "[kinds]\nvector = off\n").unwrap();
+        let config = Config::load(Some(&path)).unwrap();
+        assert_eq!(config.path, Some(path));
+        assert_eq!(config.skipped_kinds, [MediaKind::Vector]);
+    }
+}
@@ -1,14 +1,19 @@
 //! Windows dialog functionality for the image mover application.
 //!
 //! This module provides functions for displaying Windows native dialogs,
-//! including folder selection and user confirmation dialogs.
+//! including folder selection and user confirmation dialogs, and the
+//! [`DialogInteraction`] front-end built on them.
 
+use std::io;
 use std::path::PathBuf;
 use windows::{
     core::*, Win32::Foundation::*, Win32::System::Com::*, Win32::UI::Shell::*,
     Win32::UI::WindowsAndMessaging::*,
 };
 
+use crate::file_ops::format_bytes;
+use crate::interaction::{CopySummary, UserInteraction};
+
 pub fn select_folder(title: &str) -> Result<Option<PathBuf>> {
     unsafe {
         // Create the file dialog
@@ -61,6 +66,58 @@ pub fn show_deletion_prompt(file_count: usize) -> Result<bool> {
     }
 }
 
+pub fn show_resume_prompt(remaining_count: usize, total_count: usize) -> Result<bool> {
+    unsafe {
+        let title = HSTRING::from("Resume Unfinished Import");
+        let message = HSTRING::from(&format!(
+            "The destination folder contains an unfinished import of {} files from this source folder, {} of which still need attention.\n\nWould you like to resume it where it stopped?\n\nChoosing No starts a new import from scratch.",
+            total_count, remaining_count
+        ));
+
+        let result = MessageBoxW(
+            None,
+            &message,
+            &title,
+            MB_YESNO | MB_ICONQUESTION | MB_DEFBUTTON1,
+        );
+
+        Ok(result == IDYES)
+    }
+}
+
+pub fn show_verification_prompt(file_count: usize) -> Result<bool> {
+    unsafe {
+        let title = HSTRING::from("Verify Copied Files");
+        let message = HSTRING::from(&format!(
+            "Would you like to verify the {} copied files against the originals?\n\nEach copy is read back and compared by content hash. Originals whose copies do not match will never be offered for deletion.",
+            file_count
+        ));
+
+        let result = MessageBoxW(
+            None,
+            &message,
+            &title,
+            MB_YESNO | MB_ICONQUESTION | MB_DEFBUTTON1, // Default to "Yes" for safety
+        );
+
+        Ok(result == IDYES)
+    }
+}
+
+pub fn show_verification_failure_dialog(failed_count: usize) -> Result<()> {
+    unsafe {
+        let title = HSTRING::from("Verification Failed");
+        let message = HSTRING::from(&format!(
+            "{} copied files do not match their originals or could not be read back.\n\nThose originals will be kept in the source folder. See the console output for details.",
+            failed_count
+        ));
+
+        MessageBoxW(None, &message, &title, MB_OK | MB_ICONWARNING);
+
+        Ok(())
+    }
+}
+
 pub fn show_completion_dialog() -> Result<()> {
     unsafe {
         let title = HSTRING::from("Process Complete");
@@ -76,6 +133,7 @@ pub fn show_copy_confirmation_dialog(
     file_count: usize,
     total_size: u64,
     available_space: u64,
+    formatted_kinds: &str,
     formatted_total_size: &str,
     formatted_available_space: &str,
 ) -> Result<bool> {
@@ -89,8 +147,9 @@ pub fn show_copy_confirmation_dialog(
         };
 
         let message = HSTRING::from(&format!(
-            "Ready to copy {} media files\n\nTotal size to copy: {}\nAvailable space on destination: {}{}\n\nDo you want to proceed with the copy operation?",
+            "Ready to copy {} media files\n{}\n\nTotal size to copy: {}\nAvailable space on destination: {}{}\n\nDo you want to proceed with the copy operation?",
             file_count,
+            formatted_kinds,
             formatted_total_size,
             formatted_available_space,
             space_warning
@@ -112,3 +171,65 @@ pub fn show_copy_confirmation_dialog(
         Ok(result == IDYES)
     }
 }
+
+/// Asks the user through Windows dialogs.
+///
+/// COM is initialized for the calling thread while this value is alive.
+pub struct DialogInteraction {
+    _private: (),
+}
+
+impl DialogInteraction {
+    pub fn new() -> Result<DialogInteraction> {
+        unsafe {
+            CoInitializeEx(None, COINIT_APARTMENTTHREADED).ok()?;
+        }
+        Ok(DialogInteraction { _private: () })
+    }
+}
+
+impl Drop for DialogInteraction {
+    fn drop(&mut self) {
+        unsafe {
+            CoUninitialize();
+        }
+    }
+}
+
+impl UserInteraction for DialogInteraction {
+    fn select_folder(&self, title: &str) -> io::Result<Option<PathBuf>> {
+        select_folder(title).map_err(io::Error::other)
+    }
+
+    fn confirm_copy(&self, summary: &CopySummary) -> io::Result<bool> {
+        show_copy_confirmation_dialog(
+            summary.file_count,
+            summary.total_size,
+            summary.available_space,
+            &summary.describe_kinds(),
+            &format_bytes(summary.total_size),
+            &format_bytes(summary.available_space),
+        )
+        .map_err(io::Error::other)
+    }
+
+    fn confirm_resume(&self, remaining_count: usize, total_count: usize) -> io::Result<bool> {
+        show_resume_prompt(remaining_count, total_count).map_err(io::Error::other)
+    }
+
+    fn confirm_verify(&self, file_count: usize) -> io::Result<bool> {
+        show_verification_prompt(file_count).map_err(io::Error::other)
+    }
+
+    fn report_verification_failures(&self, failed_count: usize) -> io::Result<()> {
+        show_verification_failure_dialog(failed_count).map_err(io::Error::other)
+    }
+
+    fn confirm_delete(&self, file_count: usize) -> io::Result<bool> {
+        show_deletion_prompt(file_count).map_err(io::Error::other)
+    }
+
+    fn notify_completion(&self) -> io::Result<()> {
+        show_completion_dialog().map_err(io::Error::other)
+    }
+}
@@ -2,11 +2,22 @@ use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 
-pub fn cleanup_empty_directories(source_path: &PathBuf) -> io::Result<()> {
+use crate::cancel::CancellationToken;
+use crate::progress::{ProgressEvent, ProgressListener};
+use crate::walk::{walk, WalkOptions};
+
+/// Remove the empty folders below `source_path`, leaving out the folder at
+/// `exclude_path` and everything in it, such as a destination inside the
+/// source
+pub fn cleanup_empty_directories(
+    source_path: &PathBuf,
+    exclude_path: Option<&Path>,
+    progress: &dyn ProgressListener,
+) -> io::Result<()> {
     // Get all directories in reverse order (deepest first)
     let mut directories = Vec::new();
-    collect_directories(source_path, &mut directories)?;
-    directories.sort_by(|a, b| b.components().count().cmp(&a.components().count()));
+    collect_directories(source_path, exclude_path, &mut directories, progress)?;
+    directories.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
 
     for dir in directories {
         // Skip the root source directory
@@ -16,7 +27,7 @@ pub fn cleanup_empty_directories(source_path: &PathBuf) -> io::Result<()> {
 
         // Try to remove directory if it's empty
         match fs::remove_dir(&dir) {
-            Ok(()) => println!("Removed empty directory: {}", dir.display()),
+            Ok(()) => progress.on_event(&ProgressEvent::DirectoryRemoved(dir)),
             Err(e) if e.kind() == io::ErrorKind::Other => {
                 // Directory not empty or other non-critical error, continue
             }
@@ -29,48 +40,28 @@ pub fn cleanup_empty_directories(source_path: &PathBuf) -> io::Result<()> {
     Ok(())
 }
 
+/// Collect every folder below `current_dir`, not counting itself or the
+/// folder at `exclude_path` and what is in it. Links are never followed, so
+/// only folders inside it are ever cleaned up.
 pub fn collect_directories(
     current_dir: &PathBuf,
+    exclude_path: Option<&Path>,
     directories: &mut Vec<PathBuf>,
+    progress: &dyn ProgressListener,
 ) -> io::Result<()> {
-    let entries = match fs::read_dir(current_dir) {
-        Ok(entries) => entries,
-        Err(e) => {
-            eprintln!(
-                "Warning: Cannot access directory '{}': {}",
-                current_dir.display(),
-                e
-            );
-            return Ok(()); // Continue processing other directories
-        }
+    let options = WalkOptions {
+        exclude_path,
+        quiet: true,
+        ..WalkOptions::default()
     };
-
-    for entry in entries {
-        let entry = match entry {
-            Ok(entry) => entry,
-            Err(e) => {
-                eprintln!(
-                    "Warning: Cannot read directory entry in '{}': {}",
-                    current_dir.display(),
-                    e
-                );
-                continue; // Skip this entry and continue with others
-            }
-        };
-        let path = entry.path();
-
-        if path.is_dir() {
-            directories.push(path.clone());
-            if let Err(e) = collect_directories(&path, directories) {
-                eprintln!(
-                    "Warning: Cannot access subdirectory '{}': {}",
-                    path.display(),
-                    e
-                );
-                // Continue processing other directories
-            }
-        }
-    }
+    let found = walk(
+        current_dir,
+        options,
+        &CancellationToken::new(),
+        progress,
+        |folder| Ok(folder.path),
+    )?;
+    directories.extend(found.into_iter().filter(|path| path != current_dir));
 
     Ok(())
 }
@@ -78,17 +69,7 @@ pub fn collect_directories(
 pub fn create_unique_directory_structure(dest_root: &PathBuf, target_dir: &Path) -> io::Result<()> {
     // If target directory doesn't exist, create it normally
     if !target_dir.exists() {
-        return match fs::create_dir_all(target_dir) {
-            Ok(()) => Ok(()),
-            Err(e) => {
-                eprintln!(
-                    "Warning: Cannot create directory '{}': {}",
-                    target_dir.display(),
-                    e
-                );
-                Err(e)
-            }
-        };
+        return fs::create_dir_all(target_dir);
     }
 
     // If it exists, we need to create the path with potential renames
@@ -108,20 +89,33 @@ pub fn create_unique_directory_structure(dest_root: &PathBuf, target_dir: &Path)
                 current_path = next_path;
             } else {
                 // Create the directory
-                match fs::create_dir(&next_path) {
-                    Ok(()) => current_path = next_path,
-                    Err(e) => {
-                        eprintln!(
-                            "Warning: Cannot create directory '{}': {}",
-                            next_path.display(),
-                            e
-                        );
-                        return Err(e);
-                    }
-                }
+                fs::create_dir(&next_path)?;
+                current_path = next_path;
             }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn cleanup_leaves_a_destination_inside_the_source_alone() {
+        let dir = TempDir::new();
+        let source = dir.path().to_path_buf();
+        fs::create_dir_all(source.join("trip/day1")).unwrap();
+        fs::create_dir_all(source.join("imported/empty")).unwrap();
+        dir.write("kept/a.jpg", "a");
+
+        cleanup_empty_directories(&source, Some(&source.join("imported")), &NoProgress).unwrap();
+
+        assert!(!source.join("trip").exists());
+        assert!(source.join("imported/empty").is_dir());
+        assert!(source.join("kept/a.jpg").is_file());
+    }
+}
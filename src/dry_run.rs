@@ -0,0 +1,195 @@
+//! Dry-run reporting of a copy plan.
+//!
+//! This module works out what a copy plan would do to the destination -
+//! folders created, files renamed, copies that would fail and originals that
+//! would then be offered for deletion - without writing anything to disk.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::file_ops::{format_bytes, PlannedCopy};
+use crate::progress::ProgressListener;
+
+/// What would happen to a single planned copy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannedOutcome {
+    /// Copied under its original name
+    Copy,
+    /// Copied under a new name because the original name is taken
+    Rename,
+    /// The destination folder cannot be created because this path exists
+    /// and is not a folder
+    Blocked(PathBuf),
+}
+
+/// The simulated result of a copy plan
+#[derive(Debug, Default)]
+pub struct DryRunReport {
+    /// Outcome of each planned copy, in plan order
+    pub outcomes: Vec<(PlannedCopy, PlannedOutcome)>,
+    /// Folders that would be created in the destination
+    pub directories_to_create: BTreeSet<PathBuf>,
+}
+
+impl DryRunReport {
+    /// Number of copies that would succeed
+    pub fn copy_count(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|(_, outcome)| !matches!(outcome, PlannedOutcome::Blocked(_)))
+            .count()
+    }
+
+    /// Number of copies that would be renamed
+    pub fn rename_count(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|(_, outcome)| *outcome == PlannedOutcome::Rename)
+            .count()
+    }
+}
+
+/// Check how the folder holding a copy would be created, the same way
+/// `create_unique_directory_structure` walks it: existing folders are reused,
+/// missing ones created, and anything else in the way is an error.
+fn simulate_directory(
+    destination: &Path,
+    target_dir: &Path,
+    to_create: &mut BTreeSet<PathBuf>,
+) -> Result<(), PathBuf> {
+    let relative_path = target_dir.strip_prefix(destination).unwrap_or(target_dir);
+    let mut current_path = destination.to_path_buf();
+
+    for component in relative_path.components() {
+        if let std::path::Component::Normal(name) = component {
+            current_path.push(name);
+
+            if to_create.contains(&current_path) {
+                continue;
+            }
+
+            match current_path.symlink_metadata() {
+                Ok(_) if current_path.is_dir() => {}
+                Ok(_) => return Err(current_path),
+                Err(_) => {
+                    to_create.insert(current_path.clone());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Work out what a copy plan would do without touching the file system
+pub fn simulate_copy_plan(destination: &Path, plan: &[PlannedCopy]) -> DryRunReport {
+    let mut report = DryRunReport::default();
+    let mut folder_results: HashMap<PathBuf, Result<(), PathBuf>> = HashMap::new();
+
+    for planned in plan {
+        let folder_result = match planned.destination.parent() {
+            Some(dest_dir) => folder_results
+                .entry(dest_dir.to_path_buf())
+                .or_insert_with(|| {
+                    simulate_directory(destination, dest_dir, &mut report.directories_to_create)
+                })
+                .clone(),
+            None => Ok(()),
+        };
+
+        let outcome = match folder_result {
+            Err(blocker) => PlannedOutcome::Blocked(blocker),
+            Ok(()) if planned.renamed_from.is_some() => PlannedOutcome::Rename,
+            Ok(()) => PlannedOutcome::Copy,
+        };
+
+        report.outcomes.push((planned.clone(), outcome));
+    }
+
+    report
+}
+
+/// Report the full copy and delete plan, one message per line. `file_count`
+/// is the number of media files found, counted as a real run counts them
+/// for its confirmation.
+pub fn report_copy_plan(
+    report: &DryRunReport,
+    file_count: usize,
+    total_size: u64,
+    progress: &dyn ProgressListener,
+) {
+    progress.message("Dry run - nothing will be written.".to_string());
+    progress.message(String::new());
+
+    for directory in &report.directories_to_create {
+        progress.message(format!("MKDIR   {}", directory.display()));
+    }
+
+    for (planned, outcome) in &report.outcomes {
+        let link_note = if planned.copy_links && planned.source.is_symlink() {
+            " (symbolic link)"
+        } else {
+            ""
+        };
+        match outcome {
+            PlannedOutcome::Copy => progress.message(format!(
+                "COPY    {} -> {}{}",
+                planned.source.display(),
+                planned.destination.display(),
+                link_note
+            )),
+            PlannedOutcome::Rename => progress.message(format!(
+                "RENAME  {} -> {} (name already taken){}",
+                planned.source.display(),
+                planned.destination.display(),
+                link_note
+            )),
+            PlannedOutcome::Blocked(blocker) => progress.message(format!(
+                "FAIL    {} -> {} ('{}' exists and is not a folder)",
+                planned.source.display(),
+                planned.destination.display(),
+                blocker.display()
+            )),
+        }
+        for companion in &planned.companions {
+            progress.message(format!(
+                "  +     {} -> {}",
+                companion.source.display(),
+                companion.destination.display()
+            ));
+        }
+    }
+
+    // Only originals whose copies succeed are ever offered for deletion
+    for (planned, outcome) in &report.outcomes {
+        if !matches!(outcome, PlannedOutcome::Blocked(_)) {
+            progress.message(format!(
+                "DELETE  {} (if confirmed)",
+                planned.source.display()
+            ));
+            for companion in &planned.companions {
+                progress.message(format!("  +     {}", companion.source.display()));
+            }
+        }
+    }
+
+    let failed = report.outcomes.len() - report.copy_count();
+    progress.message(String::new());
+    progress.message(format!(
+        "{} media files found ({}).",
+        file_count,
+        format_bytes(total_size)
+    ));
+    progress.message(format!(
+        "{} files would be copied, {} of them renamed.",
+        report.copy_count(),
+        report.rename_count()
+    ));
+    progress.message(format!(
+        "{} folders would be created.",
+        report.directories_to_create.len()
+    ));
+    if failed > 0 {
+        progress.message(format!("{} files could not be copied.", failed));
+    }
+}
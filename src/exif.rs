@@ -0,0 +1,743 @@
+//! EXIF metadata of still images.
+//!
+//! EXIF is a TIFF structure: a header giving the byte order, followed by
+//! directories (IFDs) of tagged values. JPEG files carry it in an APP1
+//! segment, TIFF-based RAW files (CR2, NEF, ARW, DNG, ORF, RW2, ...) are a
+//! TIFF structure themselves, HEIC files store it as an `Exif` item, and
+//! Canon CR3 files keep its directories in `CMT` atoms inside `moov`. This
+//! module finds the structure in any of these and reads the tags the import
+//! uses. Cameras that leave the standard serial number tag out often keep
+//! it in their MakerNote, which is read for Canon and Nikon cameras.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::atoms::{atoms, find_atom, read_top_level_atom};
+
+/// Number of bytes at the start of a file searched for EXIF data. The APP1
+/// segment of a JPEG and the directories of a TIFF-based RAW file are well
+/// within this.
+const HEADER_LENGTH: u64 = 512 * 1024;
+
+/// Largest `meta` or `moov` atom read from a HEIC or CR3 file
+const MAX_CONTAINER_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// Largest `Exif` item read from a HEIC file
+const MAX_EXIF_ITEM_LENGTH: u64 = 1024 * 1024;
+
+/// UUID of the Canon atom holding the metadata of a CR3 file
+const CANON_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+
+/// IFD0 tag of the camera maker
+const MAKE_TAG: u16 = 0x010F;
+/// IFD0 tag of the camera model
+const MODEL_TAG: u16 = 0x0110;
+/// IFD0 tag of the date and time the file was last changed
+const DATE_TIME_TAG: u16 = 0x0132;
+/// IFD0 tag pointing at the EXIF directory
+const EXIF_IFD_TAG: u16 = 0x8769;
+/// EXIF tag of the date and time the picture was taken
+const DATE_TIME_ORIGINAL_TAG: u16 = 0x9003;
+/// EXIF tag of the date and time the picture was digitised
+const DATE_TIME_DIGITIZED_TAG: u16 = 0x9004;
+/// EXIF tag of the camera's own, maker-specific directory
+const MAKER_NOTE_TAG: u16 = 0x927C;
+/// EXIF tag of the serial number of the camera body
+const BODY_SERIAL_NUMBER_TAG: u16 = 0xA431;
+
+/// Canon MakerNote tag of the serial number, as a number
+const CANON_SERIAL_NUMBER_TAG: u16 = 0x000C;
+/// Nikon MakerNote tag of the serial number, as text
+const NIKON_SERIAL_NUMBER_TAG: u16 = 0x001D;
+/// Start of the MakerNote written by Nikon cameras, which is followed by a
+/// version and a TIFF structure of its own
+const NIKON_MAKER_NOTE_SIGNATURE: &[u8] = b"Nikon\0";
+
+/// The EXIF tags the import uses, as recorded in the file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExifData {
+    /// When the picture was taken, as `YYYY:MM:DD HH:MM:SS`
+    pub date_time_original: Option<String>,
+    /// When the picture was digitised, the same as above for a camera
+    pub date_time_digitized: Option<String>,
+    /// When the file was last changed
+    pub date_time: Option<String>,
+    /// Maker of the camera
+    pub make: Option<String>,
+    /// Model of the camera, as its maker names it
+    pub model: Option<String>,
+    /// Serial number of the camera body
+    pub serial_number: Option<String>,
+}
+
+impl ExifData {
+    /// The best record of when the picture was taken
+    pub fn capture_date_time(&self) -> Option<&str> {
+        self.date_time_original
+            .as_deref()
+            .or(self.date_time_digitized.as_deref())
+            .or(self.date_time.as_deref())
+    }
+
+    /// Take the tags of a TIFF structure, keeping values already found
+    fn merge_tiff(&mut self, tiff: &[u8]) {
+        let Some(tiff) = Tiff::new(tiff) else {
+            return;
+        };
+        let Some(ifd0) = tiff.first_ifd() else {
+            return;
+        };
+
+        let mut directories = vec![ifd0];
+        if let Some(exif_ifd) = tiff.entry(ifd0, EXIF_IFD_TAG).and_then(|e| tiff.long(&e)) {
+            directories.push(exif_ifd as usize);
+        }
+
+        // A CR3 keeps the EXIF directory as the first one of its own TIFF
+        // structure, so the EXIF tags are looked for in every directory
+        for &ifd in &directories {
+            let read = |tag: u16, value: &mut Option<String>| {
+                if value.is_none() {
+                    *value = tiff.entry(ifd, tag).and_then(|e| tiff.ascii(&e));
+                }
+            };
+            read(DATE_TIME_ORIGINAL_TAG, &mut self.date_time_original);
+            read(DATE_TIME_DIGITIZED_TAG, &mut self.date_time_digitized);
+            read(DATE_TIME_TAG, &mut self.date_time);
+            read(MAKE_TAG, &mut self.make);
+            read(MODEL_TAG, &mut self.model);
+            read(BODY_SERIAL_NUMBER_TAG, &mut self.serial_number);
+        }
+
+        if self.serial_number.is_none() {
+            self.serial_number = directories
+                .iter()
+                .find_map(|&ifd| tiff.entry(ifd, MAKER_NOTE_TAG))
+                .and_then(|entry| tiff.maker_note_serial(&entry, self.make.as_deref()));
+        }
+    }
+}
+
+/// Read the EXIF data of the image at `path`, if it has any
+pub fn read_exif(path: &Path) -> io::Result<Option<ExifData>> {
+    let mut file = File::open(path)?;
+    let mut header = Vec::new();
+    (&mut file).take(HEADER_LENGTH).read_to_end(&mut header)?;
+
+    let mut exif = ExifData::default();
+    if header.starts_with(&[0xFF, 0xD8]) {
+        match jpeg_exif_segment(&header) {
+            Some(tiff) => exif.merge_tiff(tiff),
+            None => return Ok(None),
+        }
+    } else if Tiff::new(&header).is_some() {
+        exif.merge_tiff(&header);
+    } else if header.get(4..8) == Some(b"ftyp") {
+        file.seek(SeekFrom::Start(0))?;
+        if header.get(8..12) == Some(b"crx ") {
+            read_cr3_exif(&mut file, &mut exif)?;
+        } else {
+            read_heif_exif(&mut file, &mut exif)?;
+        }
+    } else {
+        return Ok(None);
+    }
+
+    Ok((exif != ExifData::default()).then_some(exif))
+}
+
+/// The TIFF structure in the APP1 segment of a JPEG
+fn jpeg_exif_segment(jpeg: &[u8]) -> Option<&[u8]> {
+    let mut offset = 2;
+    loop {
+        let marker = jpeg.get(offset..offset + 2)?;
+        if marker[0] != 0xFF {
+            return None;
+        }
+        // Start of scan: the metadata segments are all before it
+        if marker[1] == 0xDA {
+            return None;
+        }
+        let length = u16::from_be_bytes(jpeg.get(offset + 2..offset + 4)?.try_into().ok()?);
+        let segment = jpeg.get(offset + 4..offset + 2 + length as usize)?;
+        if marker[1] == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return Some(&segment[6..]);
+        }
+        offset += 2 + length as usize;
+    }
+}
+
+/// Read the EXIF directories of a CR3 file from the Canon atom in `moov`
+fn read_cr3_exif(file: &mut File, exif: &mut ExifData) -> io::Result<()> {
+    let Some(moov) = read_top_level_atom(file, b"moov", MAX_CONTAINER_LENGTH)? else {
+        return Ok(());
+    };
+    let canon = atoms(&moov)
+        .into_iter()
+        .filter(|(atom_type, _)| atom_type == b"uuid")
+        .find_map(|(_, body)| body.strip_prefix(&CANON_UUID[..]));
+
+    if let Some(canon) = canon {
+        // CMT1 holds IFD0, CMT2 the EXIF directory
+        for atom_type in [b"CMT2", b"CMT1"] {
+            if let Some(tiff) = find_atom(canon, atom_type) {
+                exif.merge_tiff(tiff);
+            }
+        }
+        // and CMT3 the MakerNote, as the first directory of its own TIFF
+        if exif.serial_number.is_none() {
+            exif.serial_number = find_atom(canon, b"CMT3")
+                .and_then(Tiff::new)
+                .and_then(|tiff| tiff.canon_serial(tiff.first_ifd()?));
+        }
+    }
+    Ok(())
+}
+
+/// Read the `Exif` item of a HEIC or other HEIF file
+fn read_heif_exif(file: &mut File, exif: &mut ExifData) -> io::Result<()> {
+    let Some(meta) = read_top_level_atom(file, b"meta", MAX_CONTAINER_LENGTH)? else {
+        return Ok(());
+    };
+    // `meta` has a version and flags before its children
+    let Some(children) = meta.get(4..) else {
+        return Ok(());
+    };
+    let Some((offset, length)) = exif_item_location(children) else {
+        return Ok(());
+    };
+
+    let mut item = Vec::new();
+    file.seek(SeekFrom::Start(offset))?;
+    file.take(length.min(MAX_EXIF_ITEM_LENGTH))
+        .read_to_end(&mut item)?;
+
+    // The item starts with the offset of the TIFF header after this field
+    let Some(skip) = item.get(0..4) else {
+        return Ok(());
+    };
+    let skip = u32::from_be_bytes([skip[0], skip[1], skip[2], skip[3]]) as usize;
+    if let Some(tiff) = item.get(4 + skip..) {
+        exif.merge_tiff(tiff);
+    }
+    Ok(())
+}
+
+/// Where in the file the `Exif` item listed in a `meta` atom is, as offset
+/// and length
+fn exif_item_location(meta: &[u8]) -> Option<(u64, u64)> {
+    let item_id = exif_item_id(find_atom(meta, b"iinf")?)?;
+    let iloc = find_atom(meta, b"iloc")?;
+
+    let mut reader = ByteReader::new(iloc);
+    let version = reader.u8()?;
+    reader.skip(3)?;
+    let sizes = reader.u16()?;
+    let offset_size = (sizes >> 12) as usize;
+    let length_size = ((sizes >> 8) & 0xF) as usize;
+    let base_offset_size = ((sizes >> 4) & 0xF) as usize;
+    let index_size = if version >= 1 {
+        (sizes & 0xF) as usize
+    } else {
+        0
+    };
+    let item_count = if version < 2 {
+        reader.u16()? as u32
+    } else {
+        reader.u32()?
+    };
+
+    for _ in 0..item_count {
+        let id = if version < 2 {
+            reader.u16()? as u32
+        } else {
+            reader.u32()?
+        };
+        let construction_method = if version >= 1 { reader.u16()? & 0xF } else { 0 };
+        reader.skip(2)?; // data reference index
+        let base_offset = reader.sized(base_offset_size)?;
+        let extent_count = reader.u16()?;
+
+        let mut first_extent = None;
+        for _ in 0..extent_count {
+            reader.skip(index_size)?;
+            let offset = reader.sized(offset_size)?;
+            let length = reader.sized(length_size)?;
+            first_extent.get_or_insert((offset, length));
+        }
+
+        // Only items stored at an offset in the file itself are read
+        if id == item_id && construction_method == 0 {
+            let (offset, length) = first_extent?;
+            let length = if length == 0 {
+                MAX_EXIF_ITEM_LENGTH
+            } else {
+                length
+            };
+            return Some((base_offset.checked_add(offset)?, length));
+        }
+    }
+    None
+}
+
+/// The ID of the `Exif` item listed in an `iinf` atom
+fn exif_item_id(iinf: &[u8]) -> Option<u32> {
+    let version = *iinf.first()?;
+    let entries = if version == 0 {
+        iinf.get(6..)?
+    } else {
+        iinf.get(8..)?
+    };
+
+    for (atom_type, infe) in atoms(entries) {
+        if &atom_type != b"infe" {
+            continue;
+        }
+        // Item types are only given from version 2 of the entry on
+        let mut reader = ByteReader::new(infe);
+        let version = reader.u8()?;
+        reader.skip(3)?;
+        let id = match version {
+            2 => reader.u16()? as u32,
+            3 => reader.u32()?,
+            _ => continue,
+        };
+        reader.skip(2)?; // protection index
+        if reader.bytes(4)? == b"Exif" {
+            return Some(id);
+        }
+    }
+    None
+}
+
+/// Reads big-endian numbers from the start of a byte slice onwards
+struct ByteReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> ByteReader<'a> {
+        ByteReader { data, offset: 0 }
+    }
+
+    fn bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset.checked_add(length)?)?;
+        self.offset += length;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, length: usize) -> Option<()> {
+        self.bytes(length).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    /// A number stored in 0, 4 or 8 bytes, as `iloc` sizes its fields
+    fn sized(&mut self, size: usize) -> Option<u64> {
+        match size {
+            0 => Some(0),
+            4 => self.u32().map(u64::from),
+            8 => Some(u64::from_be_bytes(self.bytes(8)?.try_into().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+/// A directory entry of a TIFF structure
+struct TiffEntry {
+    field_type: u16,
+    count: u32,
+    /// Offset of the value, or of the pointer to it, from the TIFF header
+    value_offset: usize,
+}
+
+/// A TIFF structure, with offsets counted from its header
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    /// Recognise a TIFF header. The magic number after the byte order is
+    /// not checked, as ORF and RW2 files use their own.
+    fn new(data: &'a [u8]) -> Option<Tiff<'a>> {
+        let big_endian = match data.get(0..2)? {
+            b"MM" => true,
+            b"II" => false,
+            _ => return None,
+        };
+        let tiff = Tiff { data, big_endian };
+        tiff.first_ifd()?;
+        Some(tiff)
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes = self
+            .data
+            .get(offset..offset.checked_add(2)?)?
+            .try_into()
+            .ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes = self
+            .data
+            .get(offset..offset.checked_add(4)?)?
+            .try_into()
+            .ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        let offset = self.u32_at(4)? as usize;
+        (offset >= 8 && offset < self.data.len()).then_some(offset)
+    }
+
+    /// The entry for `tag` in the directory at `ifd`
+    fn entry(&self, ifd: usize, tag: u16) -> Option<TiffEntry> {
+        let count = self.u16_at(ifd)? as usize;
+        (0..count).find_map(|index| {
+            let entry = ifd + 2 + index * 12;
+            (self.u16_at(entry)? == tag).then_some(())?;
+            Some(TiffEntry {
+                field_type: self.u16_at(entry + 2)?,
+                count: self.u32_at(entry + 4)?,
+                value_offset: entry + 8,
+            })
+        })
+    }
+
+    /// A LONG (or SHORT) value, such as a directory pointer
+    fn long(&self, entry: &TiffEntry) -> Option<u32> {
+        match entry.field_type {
+            3 => self.u16_at(entry.value_offset).map(u32::from),
+            4 | 13 => self.u32_at(entry.value_offset),
+            _ => None,
+        }
+    }
+
+    /// An ASCII value, without its trailing NULs and padding
+    fn ascii(&self, entry: &TiffEntry) -> Option<String> {
+        if entry.field_type != 2 {
+            return None;
+        }
+        let text = String::from_utf8_lossy(self.bytes(entry)?);
+        let text = text.trim_end_matches('\0').trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+
+    /// The bytes of an ASCII or UNDEFINED value
+    fn bytes(&self, entry: &TiffEntry) -> Option<&'a [u8]> {
+        let length = entry.count as usize;
+        // Values of up to four bytes are stored in the entry itself
+        let start = if length <= 4 {
+            entry.value_offset
+        } else {
+            self.u32_at(entry.value_offset)? as usize
+        };
+        self.data.get(start..start.checked_add(length)?)
+    }
+
+    /// The serial number in a Canon or Nikon MakerNote. `make` is the
+    /// camera maker, as Canon MakerNotes have no signature.
+    fn maker_note_serial(&self, entry: &TiffEntry, make: Option<&str>) -> Option<String> {
+        let maker_note = self.bytes(entry)?;
+        if let Some(nikon) = maker_note.strip_prefix(NIKON_MAKER_NOTE_SIGNATURE) {
+            let nikon = Tiff::new(nikon.get(4..)?)?;
+            let entry = nikon.entry(nikon.first_ifd()?, NIKON_SERIAL_NUMBER_TAG)?;
+            return nikon.ascii(&entry);
+        }
+        if make?.starts_with("Canon") {
+            // A directory whose offsets count from the TIFF header
+            return self.canon_serial(self.u32_at(entry.value_offset)? as usize);
+        }
+        None
+    }
+
+    /// The serial number in a Canon MakerNote directory at `ifd`
+    fn canon_serial(&self, ifd: usize) -> Option<String> {
+        let entry = self.entry(ifd, CANON_SERIAL_NUMBER_TAG)?;
+        let serial = self.long(&entry).filter(|&serial| serial != 0)?;
+        Some(format!("{:010}", serial))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    enum Value<'a> {
+        Ascii(&'a str),
+        Long(u32),
+        Undefined(Vec<u8>),
+    }
+
+    /// A TIFF structure with `ifd0` as its first directory, pointing at an
+    /// EXIF directory holding `exif` if there are any
+    fn tiff(big_endian: bool, ifd0: &[(u16, Value)], exif: &[(u16, Value)]) -> Vec<u8> {
+        let u16_bytes = |value: u16| {
+            if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        let u32_bytes = |value: u32| {
+            if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        let ifd0_length = ifd0.len() + usize::from(!exif.is_empty());
+        let exif_offset = 8 + 2 + 12 * ifd0_length + 4;
+        let data_offset = if exif.is_empty() {
+            exif_offset
+        } else {
+            exif_offset + 2 + 12 * exif.len() + 4
+        };
+
+        let mut data = Vec::new();
+        let mut directory = |entries: &[(u16, Value)], exif_pointer: Option<usize>| {
+            let mut encoded: Vec<(u16, u16, u32, [u8; 4])> = entries
+                .iter()
+                .map(|(tag, value)| {
+                    let (field_type, bytes) = match value {
+                        Value::Long(long) => return (*tag, 4, 1, u32_bytes(*long)),
+                        Value::Ascii(text) => (2, [text.as_bytes(), b"\0"].concat()),
+                        Value::Undefined(bytes) => (7, bytes.clone()),
+                    };
+                    let value = if bytes.len() <= 4 {
+                        let mut inline = [0; 4];
+                        inline[..bytes.len()].copy_from_slice(&bytes);
+                        inline
+                    } else {
+                        let offset = u32_bytes((data_offset + data.len()) as u32);
+                        data.extend_from_slice(&bytes);
+                        offset
+                    };
+                    (*tag, field_type, bytes.len() as u32, value)
+                })
+                .collect();
+            if let Some(pointer) = exif_pointer {
+                encoded.push((EXIF_IFD_TAG, 4, 1, u32_bytes(pointer as u32)));
+            }
+
+            let mut bytes = u16_bytes(encoded.len() as u16).to_vec();
+            for (tag, field_type, count, value) in encoded {
+                bytes.extend_from_slice(&u16_bytes(tag));
+                bytes.extend_from_slice(&u16_bytes(field_type));
+                bytes.extend_from_slice(&u32_bytes(count));
+                bytes.extend_from_slice(&value);
+            }
+            bytes.extend_from_slice(&[0; 4]);
+            bytes
+        };
+
+        let first = directory(ifd0, (!exif.is_empty()).then_some(exif_offset));
+        let second = if exif.is_empty() {
+            Vec::new()
+        } else {
+            directory(exif, None)
+        };
+        let mut tiff = if big_endian { b"MM\0*" } else { b"II*\0" }.to_vec();
+        tiff.extend_from_slice(&u32_bytes(8));
+        tiff.extend(first);
+        tiff.extend(second);
+        tiff.extend(data);
+        tiff
+    }
+
+    /// A JPEG with `tiff` in its APP1 segment, after an APP0 segment
+    fn jpeg(tiff: &[u8]) -> Vec<u8> {
+        let app1 = [b"Exif\0\0", tiff].concat();
+        [
+            &b"\xFF\xD8\xFF\xE0\x00\x06JFIF"[..],
+            b"\xFF\xE1",
+            &((app1.len() + 2) as u16).to_be_bytes(),
+            &app1,
+            b"\xFF\xDA\x00\x02image data",
+        ]
+        .concat()
+    }
+
+    fn atom(atom_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        [
+            &((body.len() + 8) as u32).to_be_bytes()[..],
+            atom_type,
+            body,
+        ]
+        .concat()
+    }
+
+    fn exif_of(file: &[u8]) -> Option<ExifData> {
+        let dir = TempDir::new();
+        read_exif(&dir.write("file", file)).unwrap()
+    }
+
+    fn camera_tiff(big_endian: bool) -> Vec<u8> {
+        tiff(
+            big_endian,
+            &[
+                (MAKE_TAG, Value::Ascii("Canon")),
+                (MODEL_TAG, Value::Ascii("Canon EOS R5")),
+                (DATE_TIME_TAG, Value::Ascii("2024:06:01 08:00:00")),
+            ],
+            &[
+                (DATE_TIME_ORIGINAL_TAG, Value::Ascii("2024:05:17 14:03:22")),
+                (BODY_SERIAL_NUMBER_TAG, Value::Ascii("0123456789")),
+            ],
+        )
+    }
+
+    fn camera_exif() -> ExifData {
+        ExifData {
+            date_time_original: Some("2024:05:17 14:03:22".to_string()),
+            date_time_digitized: None,
+            date_time: Some("2024:06:01 08:00:00".to_string()),
+            make: Some("Canon".to_string()),
+            model: Some("Canon EOS R5".to_string()),
+            serial_number: Some("0123456789".to_string()),
+        }
+    }
+
+    #[test]
+    fn tiff_in_either_byte_order() {
+        assert_eq!(exif_of(&camera_tiff(false)), Some(camera_exif()));
+        assert_eq!(exif_of(&camera_tiff(true)), Some(camera_exif()));
+        assert_eq!(
+            camera_exif().capture_date_time(),
+            Some("2024:05:17 14:03:22")
+        );
+    }
+
+    #[test]
+    fn jpeg_app1_segment() {
+        assert_eq!(exif_of(&jpeg(&camera_tiff(false))), Some(camera_exif()));
+        assert_eq!(exif_of(b"\xFF\xD8\xFF\xDA\x00\x02"), None);
+        assert_eq!(exif_of(b"plain text"), None);
+    }
+
+    #[test]
+    fn nikon_maker_note_serial() {
+        let maker_note_tiff = tiff(
+            true,
+            &[(NIKON_SERIAL_NUMBER_TAG, Value::Ascii("7654321"))],
+            &[],
+        );
+        let maker_note = [
+            NIKON_MAKER_NOTE_SIGNATURE,
+            b"\x02\x10\x00\x00",
+            &maker_note_tiff,
+        ]
+        .concat();
+        let file = tiff(
+            false,
+            &[(MAKE_TAG, Value::Ascii("NIKON CORPORATION"))],
+            &[(MAKER_NOTE_TAG, Value::Undefined(maker_note))],
+        );
+
+        let exif = exif_of(&jpeg(&file)).unwrap();
+        assert_eq!(exif.serial_number.as_deref(), Some("7654321"));
+    }
+
+    #[test]
+    fn heif_exif_item() {
+        let mut infe = vec![2, 0, 0, 0, 0, 1, 0, 0];
+        infe.extend_from_slice(b"Exif\0");
+        let iinf = atom(
+            b"iinf",
+            &[&[0, 0, 0, 0, 0, 1][..], &atom(b"infe", &infe)].concat(),
+        );
+        let item = [&6u32.to_be_bytes()[..], b"Exif\0\0", &camera_tiff(true)].concat();
+
+        let ftyp = atom(b"ftyp", b"heic\0\0\0\0mif1heic");
+        let iloc_length = 8 + 4 + 2 + 2 + 2 + 2 + 2 + 8;
+        let meta_length = 8 + 4 + iinf.len() + iloc_length;
+        let item_offset = (ftyp.len() + meta_length + 8) as u32;
+        let iloc = atom(
+            b"iloc",
+            &[
+                &[0, 0, 0, 0, 0x44, 0x00, 0, 1, 0, 1, 0, 0, 0, 1][..],
+                &item_offset.to_be_bytes(),
+                &(item.len() as u32).to_be_bytes(),
+            ]
+            .concat(),
+        );
+        let meta = atom(b"meta", &[&[0, 0, 0, 0][..], &iinf, &iloc].concat());
+        assert_eq!(meta.len(), meta_length);
+
+        let file = [ftyp, meta, atom(b"mdat", &item)].concat();
+        assert_eq!(exif_of(&file), Some(camera_exif()));
+    }
+
+    #[test]
+    fn cr3_canon_atoms() {
+        let cmt1 = tiff(
+            false,
+            &[
+                (MAKE_TAG, Value::Ascii("Canon")),
+                (MODEL_TAG, Value::Ascii("Canon EOS R6")),
+            ],
+            &[],
+        );
+        let cmt2 = tiff(
+            false,
+            &[(DATE_TIME_ORIGINAL_TAG, Value::Ascii("2023:12:31 23:59:59"))],
+            &[],
+        );
+        let cmt3 = tiff(false, &[(CANON_SERIAL_NUMBER_TAG, Value::Long(42))], &[]);
+        let canon = [
+            &CANON_UUID[..],
+            &atom(b"CMT1", &cmt1),
+            &atom(b"CMT2", &cmt2),
+            &atom(b"CMT3", &cmt3),
+        ]
+        .concat();
+        let file = [
+            atom(b"ftyp", b"crx \0\0\0\x01crx isom"),
+            atom(b"moov", &atom(b"uuid", &canon)),
+        ]
+        .concat();
+
+        let exif = exif_of(&file).unwrap();
+        assert_eq!(exif.make.as_deref(), Some("Canon"));
+        assert_eq!(exif.model.as_deref(), Some("Canon EOS R6"));
+        assert_eq!(exif.capture_date_time(), Some("2023:12:31 23:59:59"));
+        assert_eq!(exif.serial_number.as_deref(), Some("0000000042"));
+    }
+
+    #[test]
+    fn truncated_structures_give_nothing() {
+        let mut file = camera_tiff(false);
+        file.truncate(20);
+        assert_eq!(exif_of(&file), None);
+        assert_eq!(exif_of(b"II*\0\xFF\xFF\xFF\xFF"), None);
+    }
+}
@@ -4,33 +4,169 @@
 //! deletion of original files, path validation, and handling file name conflicts.
 
 use rayon::prelude::*;
+use std::borrow::Borrow;
+use std::collections::HashSet;
 use std::fs;
-use std::io;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
+use crate::cancel::{is_cancelled_error, CancellationToken, CANCELLED};
 use crate::directory::{cleanup_empty_directories, create_unique_directory_structure};
-use crate::media::{collect_media_files, collect_media_files_with_size_and_progress};
+use crate::journal::{record_or_warn, FileState, Journal};
+use crate::layout::CopyPlacement;
+use crate::live_photo::{is_motion_photo_still, motion_photo_still_length};
+use crate::media::{
+    collect_media_files_with_size_and_progress, scan_media_folders, MediaFile, ScanOptions,
+    ScanTotals,
+};
+use crate::metadata::{import_metadata, MediaMetadata};
+use crate::progress::{ProgressEvent, ProgressListener, Stage};
+use crate::rename::NameFields;
+use crate::report::{FileOutcome, FileStatus, Operation, OperationReport};
+use crate::verify::hash_file;
+use crate::walk::copy_link;
 
 #[cfg(windows)]
 use std::os::windows::ffi::OsStrExt;
 #[cfg(windows)]
 use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
 
-pub fn validate_folder_paths(source: &PathBuf, destination: &PathBuf) -> io::Result<()> {
+/// A media file together with the destination it is going to be copied to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedCopy {
+    /// Full path of the original file in the source folder
+    pub source: PathBuf,
+    /// Full path the file will be copied to, after any conflict renames
+    pub destination: PathBuf,
+    /// The path the copy would have had without a conflict rename
+    pub renamed_from: Option<PathBuf>,
+    /// Sidecars and paired files copied along with it
+    pub companions: Vec<CompanionCopy>,
+    /// Whether symbolic links among the file and its companions are copied
+    /// as links
+    pub copy_links: bool,
+    /// Whether motion photos among the file and its companions are copied
+    /// without their video
+    pub drop_motion: bool,
+}
+
+/// A media file that was copied successfully.
+///
+/// The list of these in a copy report's manifest is what
+/// [`delete_original_files`] works from, so only originals that are known to
+/// have reached the destination are ever removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopiedFile {
+    /// Full path of the original file in the source folder
+    pub source: PathBuf,
+    /// Full path the file was copied to, after any conflict renames
+    pub destination: PathBuf,
+    /// Sidecars and paired files copied along with it
+    pub companions: Vec<CompanionCopy>,
+}
+
+/// A file travelling with a media file: one of its sidecars, the other half
+/// of a RAW+JPEG pair, or the movie of a Live Photo, along with their own
+/// sidecars.
+///
+/// It is copied next to the media file under the same final name, so a
+/// `_N` conflict rename applies to both, and its original is deleted
+/// together with the media file's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompanionCopy {
+    /// Full path of the original companion in the source folder
+    pub source: PathBuf,
+    /// Full path the companion is copied to
+    pub destination: PathBuf,
+}
+
+/// Where a companion goes when its media file is copied from `media_source` to
+/// `media_destination`: the part of the companion's name that matches the
+/// media file is replaced with the media file's new name, so
+/// `IMG_0001.xmp` follows `IMG_0001.CR3` to `IMG_0001_1.xmp` and
+/// `IMG_0001.CR3.xmp` becomes `IMG_0001_1.CR3.xmp`. A companion named
+/// differently, such as a Live Photo movie matched by its content
+/// identifier, keeps its name unless the media file is renamed, and then
+/// takes the media file's new name with its own extension.
+pub fn companion_destination(
+    media_source: &Path,
+    media_destination: &Path,
+    companion_source: &Path,
+) -> PathBuf {
+    let file_name = |path: &Path| {
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    let file_stem = |path: &Path| {
+        path.file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    let companion_name = file_name(companion_source);
+    if file_name(media_source) == file_name(media_destination) {
+        return media_destination.with_file_name(companion_name);
+    }
+
+    let new_name = [
+        (file_name(media_source), file_name(media_destination)),
+        (file_stem(media_source), file_stem(media_destination)),
+    ]
+    .into_iter()
+    .find_map(|(old, new)| {
+        let prefix = companion_name.get(..old.len())?;
+        prefix
+            .eq_ignore_ascii_case(&old)
+            .then(|| format!("{}{}", new, &companion_name[old.len()..]))
+    })
+    .unwrap_or_else(|| match companion_source.extension() {
+        Some(extension) => format!(
+            "{}.{}",
+            file_stem(media_destination),
+            extension.to_string_lossy()
+        ),
+        None => file_stem(media_destination),
+    });
+
+    media_destination.with_file_name(new_name)
+}
+
+/// Whether the copy of a media file at `candidate`, or of any of its
+/// companions, would land on a name for which `is_taken` is true
+fn copy_is_taken(
+    media_source: &Path,
+    companion_sources: &[PathBuf],
+    candidate: &Path,
+    is_taken: impl Fn(&Path) -> bool,
+) -> bool {
+    is_taken(candidate)
+        || companion_sources
+            .iter()
+            .any(|companion| is_taken(&companion_destination(media_source, candidate, companion)))
+}
+
+pub fn validate_folder_paths(
+    source: &Path,
+    destination: &Path,
+    progress: &dyn ProgressListener,
+) -> io::Result<()> {
     // Canonicalize paths to resolve any symbolic links and get absolute paths
     let canonical_source = match source.canonicalize() {
         Ok(path) => path,
         Err(e) => {
-            eprintln!(
-                "Warning: Cannot access source folder '{}': {}",
-                source.display(),
-                e
-            );
             return Err(io::Error::new(
                 io::ErrorKind::NotFound,
-                "Unable to access source folder",
+                format!(
+                    "Unable to access source folder '{}': {}",
+                    source.display(),
+                    e
+                ),
             ));
         }
     };
@@ -38,14 +174,13 @@ pub fn validate_folder_paths(source: &PathBuf, destination: &PathBuf) -> io::Res
     let canonical_dest = match destination.canonicalize() {
         Ok(path) => path,
         Err(e) => {
-            eprintln!(
-                "Warning: Cannot access destination folder '{}': {}",
-                destination.display(),
-                e
-            );
             return Err(io::Error::new(
                 io::ErrorKind::NotFound,
-                "Unable to access destination folder",
+                format!(
+                    "Unable to access destination folder '{}': {}",
+                    destination.display(),
+                    e
+                ),
             ));
         }
     };
@@ -68,9 +203,8 @@ pub fn validate_folder_paths(source: &PathBuf, destination: &PathBuf) -> io::Res
 
     // Check if destination is within source - allow this but warn the user
     if canonical_dest.starts_with(&canonical_source) {
-        println!("Warning: Destination folder is within the source folder.");
-        println!(
-            "Files from the destination folder will be skipped to prevent infinite recursion."
+        progress.warn(
+            "Destination folder is within the source folder. Files from the destination folder will be skipped to prevent infinite recursion.".to_string(),
         );
     }
 
@@ -79,7 +213,7 @@ pub fn validate_folder_paths(source: &PathBuf, destination: &PathBuf) -> io::Res
 
 /// Get available disk space for a given path in bytes
 #[cfg(windows)]
-pub fn get_available_disk_space(path: &PathBuf) -> io::Result<u64> {
+pub fn get_available_disk_space(path: &Path) -> io::Result<u64> {
     // Get the root of the drive for the path
     let root = if let Some(root) = path.ancestors().last() {
         root
@@ -102,45 +236,150 @@ pub fn get_available_disk_space(path: &PathBuf) -> io::Result<u64> {
         if result.is_ok() {
             Ok(free_bytes_available)
         } else {
-            Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Failed to get disk space information",
-            ))
+            Err(io::Error::other("Failed to get disk space information"))
         }
     }
 }
 
-/// Get available disk space for a given path in bytes (non-Windows fallback)
-#[cfg(not(windows))]
-pub fn get_available_disk_space(_path: &PathBuf) -> io::Result<u64> {
-    // For non-Windows platforms, we could use statvfs or similar
-    // For now, return an error indicating this feature is Windows-only
+/// Get the space available to the current user on the filesystem holding
+/// `path`, in bytes. A destination that does not exist yet is measured on
+/// the filesystem of its nearest existing ancestor.
+#[cfg(unix)]
+pub fn get_available_disk_space(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let existing = nearest_existing_ancestor(path);
+    let c_path = CString::new(existing.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains a NUL byte"))?;
+
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: c_path is NUL-terminated and stats is only read after statvfs succeeds
+    let stats = unsafe {
+        if libc::statvfs(c_path.as_ptr(), stats.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stats.assume_init()
+    };
+
+    // f_bavail excludes blocks reserved for the superuser; the field widths
+    // differ between platforms, hence the casts
+    #[allow(clippy::unnecessary_cast)]
+    let available = (stats.f_bavail as u64).saturating_mul(stats.f_frsize as u64);
+    Ok(available)
+}
+
+/// Find the deepest ancestor of `path` (including itself) that exists
+#[cfg(unix)]
+fn nearest_existing_ancestor(path: &Path) -> &Path {
+    path.ancestors()
+        .find(|ancestor| !ancestor.as_os_str().is_empty() && ancestor.exists())
+        .unwrap_or_else(|| Path::new("."))
+}
+
+/// Get available disk space for a given path in bytes (fallback for other platforms)
+#[cfg(not(any(windows, unix)))]
+pub fn get_available_disk_space(_path: &Path) -> io::Result<u64> {
     Err(io::Error::new(
         io::ErrorKind::Unsupported,
-        "Disk space calculation is only supported on Windows",
+        "Disk space calculation is not supported on this platform",
     ))
 }
 
 /// Calculate total size of all media files in bytes and collect them in one pass
-/// Returns a tuple of (media_files, total_size_bytes)
+/// Returns a tuple of (media_files, total_size_bytes), or an
+/// [`io::ErrorKind::Interrupted`] error if the scan was cancelled
 pub fn collect_media_files_and_calculate_size(
-    source: &PathBuf,
+    source: &Path,
     exclude_path: Option<&PathBuf>,
-) -> io::Result<(Vec<PathBuf>, u64)> {
+    options: &ScanOptions,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+) -> io::Result<(Vec<MediaFile>, u64)> {
     let mut media_files = Vec::new();
     let mut total_size = 0u64;
 
-    collect_media_files_with_size_and_progress(
-        source,
+    progress.on_event(&ProgressEvent::StageStarted {
+        stage: Stage::Scan,
+        total_files: 0,
+        total_bytes: 0,
+    });
+
+    if let Err(e) = collect_media_files_with_size_and_progress(
         source,
         &mut media_files,
         &mut total_size,
-        exclude_path,
-    )?;
+        exclude_path.map(PathBuf::as_path),
+        options,
+        cancel,
+        progress,
+    ) {
+        if is_cancelled_error(&e) {
+            progress.on_event(&ProgressEvent::Cancelled(Stage::Scan));
+        }
+        return Err(e);
+    }
+
+    progress.on_event(&ProgressEvent::StageFinished {
+        stage: Stage::Scan,
+        succeeded: media_files.len(),
+        failed: 0,
+    });
 
     Ok((media_files, total_size))
 }
 
+/// Count the media files in `source` and their total size without keeping
+/// the files themselves, reporting the scan as
+/// [`collect_media_files_and_calculate_size`] does
+pub fn count_media_files_and_calculate_size(
+    source: &Path,
+    exclude_path: Option<&PathBuf>,
+    options: &ScanOptions,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+) -> io::Result<ScanTotals> {
+    let totals = Mutex::new(ScanTotals::default());
+
+    progress.on_event(&ProgressEvent::StageStarted {
+        stage: Stage::Scan,
+        total_files: 0,
+        total_bytes: 0,
+    });
+
+    if let Err(e) = scan_media_folders(
+        source,
+        exclude_path.map(PathBuf::as_path),
+        options,
+        cancel,
+        progress,
+        |media_files, size| {
+            match totals.lock() {
+                Ok(mut totals) => totals.add(&media_files, size),
+                Err(poisoned) => poisoned.into_inner().add(&media_files, size),
+            }
+            Ok(())
+        },
+    ) {
+        if is_cancelled_error(&e) {
+            progress.on_event(&ProgressEvent::Cancelled(Stage::Scan));
+        }
+        return Err(e);
+    }
+
+    let totals = match totals.into_inner() {
+        Ok(totals) => totals,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    progress.on_event(&ProgressEvent::StageFinished {
+        stage: Stage::Scan,
+        succeeded: totals.copy_count,
+        failed: 0,
+    });
+
+    Ok(totals)
+}
+
 /// Format bytes into human readable string
 pub fn format_bytes(bytes: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
@@ -162,9 +401,14 @@ pub fn format_bytes(bytes: u64) -> String {
     }
 }
 
-pub fn get_unique_file_path(original_path: &PathBuf) -> io::Result<PathBuf> {
-    if !original_path.exists() {
-        return Ok(original_path.clone());
+pub fn get_unique_file_path(original_path: &Path) -> io::Result<PathBuf> {
+    find_unique_path(original_path, |path| path.exists())
+}
+
+/// Find the first of `name`, `name_1`, `name_2`, ... for which `is_taken` is false
+fn find_unique_path(original_path: &Path, is_taken: impl Fn(&Path) -> bool) -> io::Result<PathBuf> {
+    if !is_taken(original_path) {
+        return Ok(original_path.to_path_buf());
     }
 
     let mut counter = 1;
@@ -188,7 +432,7 @@ pub fn get_unique_file_path(original_path: &PathBuf) -> io::Result<PathBuf> {
 
         let new_path = parent.join(new_name);
 
-        if !new_path.exists() {
+        if !is_taken(&new_path) {
             return Ok(new_path);
         }
 
@@ -196,178 +440,1155 @@ pub fn get_unique_file_path(original_path: &PathBuf) -> io::Result<PathBuf> {
 
         // Prevent infinite loops by limiting attempts
         if counter > 10000 {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
+            return Err(io::Error::other(
                 "Could not find unique filename after 10000 attempts",
             ));
         }
     }
 }
 
+/// Work out the final destination of every media file before anything is copied.
+///
+/// Files go where `placement` puts them in `destination`. Names that
+/// already exist on disk, or that an earlier file in the same plan will take,
+/// get the usual `_N` suffix, so the plan is stable no matter in which order
+/// the copies later run. A name is only used if every companion of the file is
+/// free under it too.
+pub fn plan_copies(
+    source: &Path,
+    destination: &Path,
+    placement: &CopyPlacement,
+    media_files: &[MediaFile],
+) -> io::Result<Vec<PlannedCopy>> {
+    let planner = CopyPlanner::new(source, destination, placement);
+
+    // The placement may read each file's metadata or hash, which is done in
+    // parallel; names are then handed out in order, so the plan stays the same
+    let details: Vec<FileDetails> = media_files
+        .par_iter()
+        .map(|media_file| planner.read_details(media_file))
+        .collect();
+    media_files
+        .iter()
+        .zip(details)
+        .map(|(media_file, details)| planner.plan_with(media_file, details))
+        .collect()
+}
+
+/// What is read from a media file to place its copy
+struct FileDetails {
+    metadata: MediaMetadata,
+    hash: Option<[u8; 32]>,
+}
+
+/// Chooses destinations for media files one at a time, as [`plan_copies`]
+/// does for a whole list, remembering the names already handed out.
+/// Sequence numbers follow the order of the calls, so a planner shared by
+/// several threads numbers files differently from run to run.
+pub struct CopyPlanner<'a> {
+    source: &'a Path,
+    destination: &'a Path,
+    placement: &'a CopyPlacement,
+    reserved: Mutex<HashSet<PathBuf>>,
+    sequence: AtomicU64,
+}
+
+impl<'a> CopyPlanner<'a> {
+    pub fn new(
+        source: &'a Path,
+        destination: &'a Path,
+        placement: &'a CopyPlacement,
+    ) -> CopyPlanner<'a> {
+        CopyPlanner {
+            source,
+            destination,
+            placement,
+            reserved: Mutex::default(),
+            sequence: AtomicU64::new(0),
+        }
+    }
+
+    /// Plan the copy of one media file, given relative to the source folder
+    pub fn plan(&self, media_file: &MediaFile) -> io::Result<PlannedCopy> {
+        self.plan_with(media_file, self.read_details(media_file))
+    }
+
+    /// Read what the placement needs to know about a media file
+    fn read_details(&self, media_file: &MediaFile) -> FileDetails {
+        let source_file = self.source.join(&media_file.path);
+        FileDetails {
+            metadata: if self.placement.uses_metadata() {
+                import_metadata(&source_file)
+            } else {
+                MediaMetadata::default()
+            },
+            // A file that cannot be read fails to copy later on
+            hash: if self.placement.uses_hash() {
+                hash_file(&source_file).ok()
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Plan the copy of one media file to where the placement puts it, or
+    /// the first free name after that
+    fn plan_with(&self, media_file: &MediaFile, details: FileDetails) -> io::Result<PlannedCopy> {
+        let source_file = self.source.join(&media_file.path);
+        let fields = NameFields {
+            relative_path: &media_file.path,
+            metadata: &details.metadata,
+            hash: details.hash,
+            sequence: self.sequence.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+        let requested = self
+            .destination
+            .join(self.placement.relative_destination(&fields)?);
+        let companion_sources: Vec<PathBuf> = media_file
+            .companion_paths()
+            .map(|companion| self.source.join(companion))
+            .collect();
+
+        // Held until the names are reserved, so two files planned at the same
+        // time cannot pick the same one
+        let mut reserved = match self.reserved.lock() {
+            Ok(reserved) => reserved,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let dest_file = find_unique_path(&requested, |candidate| {
+            copy_is_taken(&source_file, &companion_sources, candidate, |path| {
+                reserved.contains(path) || path.exists()
+            })
+        })?;
+
+        let companions: Vec<CompanionCopy> = companion_sources
+            .into_iter()
+            .map(|companion| CompanionCopy {
+                destination: companion_destination(&source_file, &dest_file, &companion),
+                source: companion,
+            })
+            .collect();
+
+        reserved.insert(dest_file.clone());
+        reserved.extend(
+            companions
+                .iter()
+                .map(|companion| companion.destination.clone()),
+        );
+        Ok(PlannedCopy {
+            source: source_file,
+            renamed_from: (dest_file != requested).then_some(requested),
+            destination: dest_file,
+            companions,
+            copy_links: media_file.copy_links,
+            drop_motion: media_file.drop_motion,
+        })
+    }
+}
+
+/// Flush a written file to the storage device
+pub fn flush_to_disk(path: &Path) -> io::Result<()> {
+    // Opened for writing because Windows refuses to flush read-only handles
+    fs::OpenOptions::new().write(true).open(path)?.sync_all()
+}
+
+/// Copy the given media files (relative to `source`) into `destination`,
+/// recreating their folder structure and renaming on conflicts.
+///
+/// Returns a report with the outcome of every file; its
+/// [`manifest`](OperationReport::manifest) lists the successful copies.
 pub fn copy_media_files(
-    source: &PathBuf,
+    source: &Path,
     destination: &PathBuf,
-    media_files: &Vec<PathBuf>,
-) -> io::Result<usize> {
-    println!("Scanning for media files...");
+    media_files: &[MediaFile],
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+) -> io::Result<OperationReport> {
+    let plan = plan_copies(source, destination, &CopyPlacement::default(), media_files)?;
+    copy_planned_files(destination, &plan, None, cancel, progress)
+}
 
-    if media_files.is_empty() {
-        println!("No media files found in the source directory.");
-        return Ok(0);
+/// Carry out a copy plan, recording each file in the journal if one is given.
+///
+/// A planned destination that has been taken since the plan was made is
+/// renamed again rather than overwritten. Once `cancel` is cancelled no new
+/// copies are started; copies already running are finished, and the rest are
+/// reported as skipped and stay pending in the journal.
+pub fn copy_planned_files(
+    destination: &PathBuf,
+    plan: &[PlannedCopy],
+    journal: Option<&Journal>,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+) -> io::Result<OperationReport> {
+    if plan.is_empty() {
+        return Ok(OperationReport::new(Operation::Copy));
     }
 
+    run_copies(
+        destination,
+        plan.par_iter(),
+        plan.len(),
+        journal,
+        cancel,
+        progress,
+    )
+}
+
+/// Carry out planned copies as they arrive on `queue`, until it is closed.
+///
+/// This is [`copy_planned_files`] for a plan that is still being made while
+/// the first files are copied. `expected_count` is how many copies are
+/// expected, and is only used to report progress.
+pub fn copy_queued_files(
+    destination: &PathBuf,
+    queue: Receiver<PlannedCopy>,
+    expected_count: usize,
+    journal: Option<&Journal>,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+) -> io::Result<OperationReport> {
+    run_copies(
+        destination,
+        queue.into_iter().par_bridge(),
+        expected_count,
+        journal,
+        cancel,
+        progress,
+    )
+}
+
+/// The copy stage shared by [`copy_planned_files`] and [`copy_queued_files`]
+fn run_copies<I>(
+    destination: &PathBuf,
+    plan: I,
+    total: usize,
+    journal: Option<&Journal>,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+) -> io::Result<OperationReport>
+where
+    I: ParallelIterator,
+    I::Item: Borrow<PlannedCopy>,
+{
+    let started = Instant::now();
+    let mut report = OperationReport::new(Operation::Copy);
+
+    progress.on_event(&ProgressEvent::StageStarted {
+        stage: Stage::Copy,
+        total_files: total,
+        total_bytes: 0,
+    });
+
     // Use atomic counter for thread-safe counting
     let copied_count = Arc::new(AtomicUsize::new(0));
 
     // Create a custom thread pool to ensure proper cleanup
-    let pool = rayon::ThreadPoolBuilder::new().build().map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to create thread pool: {}", e),
-        )
-    })?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .build()
+        .map_err(|e| io::Error::other(format!("Failed to create thread pool: {}", e)))?;
 
     // Process files in parallel using the custom thread pool
-    let results: Vec<io::Result<()>> = pool.install(|| {
-        media_files
-            .par_iter()
-            .map(|relative_path| {
-                let source_file = source.join(relative_path);
-                let mut dest_file = destination.join(relative_path);
-
-                // Create destination directory structure if it doesn't exist, handling collisions
-                if let Some(dest_dir) = dest_file.parent() {
-                    if let Err(e) = create_unique_directory_structure(destination, dest_dir) {
-                        eprintln!(
-                            "Warning: Cannot create directory structure for '{}': {}",
-                            dest_dir.display(),
-                            e
-                        );
-                        return Err(e);
-                    }
+    report.outcomes = pool.install(|| {
+        plan.map(|planned| {
+            let planned = planned.borrow();
+            let source_file = &planned.source;
+            if cancel.is_cancelled() {
+                return FileOutcome::skipped(
+                    source_file.clone(),
+                    Some(planned.destination.clone()),
+                    CANCELLED,
+                );
+            }
 
-                    // The directory structure is now created, but we still need to check
-                    // if the final file would collide and get a unique name for it
-                }
+            let outcome = copy_planned_file(destination, planned, journal, progress);
 
-                // Get unique file path to avoid overwriting existing files
-                dest_file = match get_unique_file_path(&dest_file) {
-                    Ok(path) => path,
-                    Err(e) => {
-                        eprintln!(
-                            "Warning: Cannot determine unique file path for '{}': {}",
-                            dest_file.display(),
-                            e
-                        );
-                        return Err(e);
-                    }
-                };
-
-                // Copy the file
-                match fs::copy(&source_file, &dest_file) {
-                    Ok(_) => {
-                        // Thread-safe increment
-                        let count = copied_count.fetch_add(1, Ordering::Relaxed) + 1;
-                        println!(
-                            "({}/{}) Copied: {} -> {}",
-                            count,
-                            media_files.len(),
-                            source_file.display(),
-                            dest_file.display()
-                        );
-                        Ok(())
+            match (&outcome.status, &outcome.destination) {
+                (FileStatus::Done, Some(dest_file)) => {
+                    if let Some(journal) = journal {
+                        let copied = CopiedFile {
+                            source: source_file.clone(),
+                            destination: dest_file.clone(),
+                            companions: outcome.companions.clone(),
+                        };
+                        if let Err(e) = journal.record_copy(&copied) {
+                            progress.warn(format!(
+                                "Cannot update journal for '{}': {}",
+                                source_file.display(),
+                                e
+                            ));
+                        }
                     }
-                    Err(e) => {
-                        eprintln!(
-                            "Warning: Cannot copy file '{}' to '{}': {}",
-                            source_file.display(),
-                            dest_file.display(),
-                            e
-                        );
-                        Err(e)
+
+                    // Thread-safe increment
+                    let count = copied_count.fetch_add(1, Ordering::Relaxed) + 1;
+                    progress.on_event(&ProgressEvent::FileCopied {
+                        source: source_file.clone(),
+                        destination: dest_file.clone(),
+                        bytes: outcome.bytes,
+                        completed: count,
+                        // An expected total may be overtaken by files added since
+                        total: total.max(count),
+                    });
+                }
+                (status, _) => {
+                    if let FileStatus::Failed { message, .. } = status {
+                        progress.on_event(&ProgressEvent::FileFailed {
+                            stage: Stage::Copy,
+                            path: source_file.clone(),
+                            message: message.clone(),
+                        });
                     }
+                    record_or_warn(journal, source_file, FileState::CopyFailed, progress);
                 }
-            })
-            .collect()
+            }
+
+            outcome
+        })
+        .collect()
+    });
+    report.elapsed = started.elapsed();
+    report.cancelled = cancel.is_cancelled();
+
+    if report.cancelled {
+        progress.on_event(&ProgressEvent::Cancelled(Stage::Copy));
+    }
+
+    // Continue if some files failed, they are listed in the report
+    if report.failure_count() > 0 {
+        progress.warn(format!(
+            "{} files could not be copied due to access issues",
+            report.failure_count()
+        ));
+    }
+
+    progress.on_event(&ProgressEvent::StageFinished {
+        stage: Stage::Copy,
+        succeeded: report.success_count(),
+        failed: report.failure_count(),
     });
 
-    // Check for any errors - but continue if some files failed
-    let mut _successful_copies = 0;
-    let mut failed_copies = 0;
+    Ok(report)
+}
 
-    for result in results {
-        match result {
-            Ok(()) => _successful_copies += 1,
-            Err(_) => failed_copies += 1,
+fn copy_planned_file(
+    destination: &PathBuf,
+    planned: &PlannedCopy,
+    journal: Option<&Journal>,
+    progress: &dyn ProgressListener,
+) -> FileOutcome {
+    let started = Instant::now();
+    let source_file = &planned.source;
+    let mut dest_file = planned.destination.clone();
+
+    // Create destination directory structure if it doesn't exist, handling collisions
+    if let Some(dest_dir) = dest_file.parent() {
+        if let Err(e) = create_unique_directory_structure(destination, dest_dir) {
+            let e = io::Error::new(
+                e.kind(),
+                format!(
+                    "Cannot create directory structure for '{}': {}",
+                    dest_dir.display(),
+                    e
+                ),
+            );
+            return FileOutcome::failed(source_file.clone(), Some(dest_file), &e);
         }
     }
 
-    if failed_copies > 0 {
-        println!(
-            "Warning: {} files could not be copied due to access issues",
-            failed_copies
-        );
+    // The plan reserved this name, but something may have created it since.
+    // Names are claimed by creating the files, which fails rather than
+    // replacing anything, so a name taken by another copy or program in the
+    // meantime moves on to the next free one. Each name goes into the
+    // journal before it is claimed, so a resumed run can remove the empty
+    // files of a claim that was interrupted.
+    let companion_sources: Vec<PathBuf> = planned
+        .companions
+        .iter()
+        .map(|companion| companion.source.clone())
+        .collect();
+    let mut claimed_elsewhere: HashSet<PathBuf> = HashSet::new();
+    let (companions, claims) = loop {
+        dest_file = match find_unique_path(&planned.destination, |candidate| {
+            copy_is_taken(source_file, &companion_sources, candidate, |path| {
+                claimed_elsewhere.contains(path) || path.exists()
+            })
+        }) {
+            Ok(path) => path,
+            Err(e) => {
+                let e = io::Error::new(
+                    e.kind(),
+                    format!(
+                        "Cannot determine unique file path for '{}': {}",
+                        planned.destination.display(),
+                        e
+                    ),
+                );
+                return FileOutcome::failed(source_file.clone(), Some(dest_file), &e);
+            }
+        };
+
+        let companions: Vec<CompanionCopy> = companion_sources
+            .iter()
+            .map(|companion| CompanionCopy {
+                destination: companion_destination(source_file, &dest_file, companion),
+                source: companion.clone(),
+            })
+            .collect();
+
+        let copy = CopiedFile {
+            source: source_file.clone(),
+            destination: dest_file.clone(),
+            companions: companions.clone(),
+        };
+        record_destinations(journal, &copy, Journal::record_claiming, progress);
+        match claim_copy(source_file, &dest_file, &companions, planned.copy_links) {
+            Ok(claims) => break (companions, claims),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                claimed_elsewhere.insert(dest_file.clone());
+            }
+            Err(e) => return FileOutcome::failed(source_file.clone(), Some(dest_file), &e),
+        }
+    };
+
+    let renamed_from = if dest_file != planned.destination {
+        Some(
+            planned
+                .renamed_from
+                .clone()
+                .unwrap_or_else(|| planned.destination.clone()),
+        )
+    } else {
+        planned.renamed_from.clone()
+    };
+
+    // The files now exist and are this import's, so a resumed run removes
+    // whatever a write interrupted from here on leaves in them
+    let copying = CopiedFile {
+        source: source_file.clone(),
+        destination: dest_file.clone(),
+        companions: companions.clone(),
+    };
+    record_destinations(journal, &copying, Journal::record_copying, progress);
+
+    // The companions go with the file or not at all, so any failure removes
+    // everything this copy created and fails the whole file
+    let sources = std::iter::once(source_file).chain(companions.iter().map(|c| &c.source));
+    let destinations = std::iter::once(&dest_file).chain(companions.iter().map(|c| &c.destination));
+    let mut bytes = 0;
+    for ((source, destination), claim) in sources.zip(destinations.clone()).zip(claims) {
+        let Some(file) = claim else {
+            // A link, already complete when it was claimed
+            continue;
+        };
+        match write_copy(source, file, planned.drop_motion) {
+            Ok(written) => bytes += written,
+            Err(e) => {
+                for path in destinations {
+                    let _ = fs::remove_file(path);
+                }
+                let e = io::Error::new(
+                    e.kind(),
+                    if source == source_file {
+                        format!("Cannot copy file to '{}': {}", destination.display(), e)
+                    } else {
+                        format!(
+                            "Cannot copy companion '{}' to '{}': {}",
+                            source.display(),
+                            destination.display(),
+                            e
+                        )
+                    },
+                );
+                return FileOutcome::failed(source_file.clone(), Some(dest_file), &e);
+            }
+        }
     }
 
-    Ok(copied_count.load(Ordering::Relaxed))
+    FileOutcome {
+        source: source_file.clone(),
+        destination: Some(dest_file),
+        renamed_from,
+        companions,
+        status: FileStatus::Done,
+        bytes,
+        elapsed: started.elapsed(),
+    }
 }
 
-pub fn delete_original_files(source_path: &PathBuf) -> io::Result<usize> {
-    // First, collect all media files again (same as copy operation)
-    let mut media_files = Vec::new();
-    collect_media_files(source_path, source_path, &mut media_files, None)?;
+/// Record the destinations of a copy with `record`, warning instead of
+/// failing the file if the journal cannot be written
+fn record_destinations(
+    journal: Option<&Journal>,
+    copy: &CopiedFile,
+    record: fn(&Journal, &CopiedFile) -> io::Result<()>,
+    progress: &dyn ProgressListener,
+) {
+    if let Some(journal) = journal {
+        if let Err(e) = record(journal, copy) {
+            progress.warn(format!(
+                "Cannot update journal for '{}': {}",
+                copy.source.display(),
+                e
+            ));
+        }
+    }
+}
+
+/// Create the destination of a file and of each of its companions, failing
+/// with `AlreadyExists` instead of replacing anything. Files are returned
+/// open for writing; symbolic links copied as links are complete already
+/// and give `None`. On failure, whatever was created is removed again.
+fn claim_copy(
+    source: &Path,
+    destination: &Path,
+    companions: &[CompanionCopy],
+    copy_links: bool,
+) -> io::Result<Vec<Option<fs::File>>> {
+    let copies = std::iter::once((source, destination)).chain(
+        companions
+            .iter()
+            .map(|companion| (companion.source.as_path(), companion.destination.as_path())),
+    );
+
+    let mut claims = Vec::new();
+    let mut created: Vec<&Path> = Vec::new();
+    for (source, destination) in copies {
+        let claim = if copy_links && source.is_symlink() {
+            copy_link(source, destination).map(|()| None)
+        } else {
+            fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(destination)
+                .map(Some)
+        };
+        match claim {
+            Ok(claim) => {
+                claims.push(claim);
+                created.push(destination);
+            }
+            Err(e) => {
+                for path in created {
+                    let _ = fs::remove_file(path);
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(claims)
+}
 
-    if media_files.is_empty() {
-        return Ok(0);
+/// Write the contents and permissions of `source` into a claimed
+/// destination, and make sure the copy has reached the device. With
+/// `drop_motion`, only the still of a motion photo is written.
+fn write_copy(source: &Path, mut destination: fs::File, drop_motion: bool) -> io::Result<u64> {
+    let length = if drop_motion {
+        motion_photo_still_length(source)?
+    } else {
+        None
+    };
+    let original = fs::File::open(source)?;
+    let permissions = original.metadata()?.permissions();
+    let bytes = io::copy(
+        &mut original.take(length.unwrap_or(u64::MAX)),
+        &mut destination,
+    )?;
+    destination.set_permissions(permissions)?;
+    destination.sync_all()?;
+    Ok(bytes)
+}
+
+/// Delete the originals listed in a copy manifest from the source folder.
+///
+/// Only files in `manifest` are touched. An entry is skipped if its copy is
+/// no longer present at the destination, so a file is never removed without
+/// a copy to fall back on, and if its copy left out the video of a motion
+/// photo. Empty directories left behind in `source_path`
+/// are cleaned up afterwards, except in `destination_path` when it is inside
+/// the source. Each deletion is recorded in the journal if one
+/// is given. Once `cancel` is cancelled no more originals are deleted, the
+/// rest are reported as skipped, and the cleanup is left out.
+pub fn delete_original_files(
+    source_path: &PathBuf,
+    destination_path: &Path,
+    manifest: &[CopiedFile],
+    journal: Option<&Journal>,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+) -> io::Result<OperationReport> {
+    let started = Instant::now();
+    let mut report = OperationReport::new(Operation::Delete);
+
+    if manifest.is_empty() {
+        return Ok(report);
     }
 
+    progress.on_event(&ProgressEvent::StageStarted {
+        stage: Stage::Delete,
+        total_files: manifest.len(),
+        total_bytes: 0,
+    });
+
     let deleted_count = Arc::new(AtomicUsize::new(0));
 
     // Delete files in parallel
-    let results: Vec<io::Result<()>> = media_files
+    report.outcomes = manifest
         .par_iter()
-        .map(|relative_path| {
-            let file_path = source_path.join(relative_path);
-
-            match fs::remove_file(&file_path) {
-                Ok(()) => {
-                    let count = deleted_count.fetch_add(1, Ordering::Relaxed) + 1;
-                    println!(
-                        "({}/{}) Deleted: {}",
-                        count,
-                        media_files.len(),
-                        file_path.display()
-                    );
-                    Ok(())
-                }
+        .map(|copied| {
+            let file_started = Instant::now();
+            let file_path = &copied.source;
+            let destination = Some(copied.destination.clone());
+
+            if cancel.is_cancelled() {
+                return FileOutcome::skipped(file_path.clone(), destination, CANCELLED);
+            }
+
+            let missing_copy = std::iter::once(&copied.destination)
+                .chain(
+                    copied
+                        .companions
+                        .iter()
+                        .map(|companion| &companion.destination),
+                )
+                .find(|copy| !copy.is_file() && !copy.is_symlink());
+            if let Some(missing_copy) = missing_copy {
+                progress.on_event(&ProgressEvent::Skipped {
+                    path: file_path.clone(),
+                    reason: format!("its copy '{}' is missing", missing_copy.display()),
+                });
+                return FileOutcome::skipped(
+                    file_path.clone(),
+                    destination,
+                    "copy is missing from the destination",
+                );
+            }
+
+            // The video of a motion photo copied as a still only exists in
+            // the original
+            let still_only = std::iter::once((&copied.source, &copied.destination))
+                .chain(
+                    copied
+                        .companions
+                        .iter()
+                        .map(|companion| (&companion.source, &companion.destination)),
+                )
+                .any(|(original, copy)| is_motion_photo_still(original, copy));
+            if still_only {
+                progress.on_event(&ProgressEvent::Skipped {
+                    path: file_path.clone(),
+                    reason: "its copy leaves out the video of a motion photo".to_string(),
+                });
+                return FileOutcome::skipped(
+                    file_path.clone(),
+                    destination,
+                    "motion photo video not copied",
+                );
+            }
+
+            // The file goes first, so a failure never leaves it in the source
+            // without its companions. Only once the companions are gone too
+            // is it recorded as deleted; until then a resumed run retries
+            // them, finding the file already removed.
+            let removed = match fs::remove_file(file_path) {
+                Ok(()) => true,
+                // Already removed, e.g. by a run that stopped before recording it
+                Err(e) if e.kind() == io::ErrorKind::NotFound => false,
                 Err(e) => {
-                    eprintln!("Warning: Failed to delete '{}': {}", file_path.display(), e);
-                    Err(e)
+                    progress.on_event(&ProgressEvent::FileFailed {
+                        stage: Stage::Delete,
+                        path: file_path.clone(),
+                        message: format!("Failed to delete: {}", e),
+                    });
+                    return FileOutcome::failed(file_path.clone(), destination, &e);
                 }
+            };
+
+            if let Err(e) = delete_companions(copied) {
+                let e = io::Error::new(
+                    e.kind(),
+                    format!("The file was deleted, but not its companion {}", e),
+                );
+                progress.on_event(&ProgressEvent::FileFailed {
+                    stage: Stage::Delete,
+                    path: file_path.clone(),
+                    message: e.to_string(),
+                });
+                return FileOutcome::failed(file_path.clone(), destination, &e);
+            }
+
+            record_or_warn(journal, file_path, FileState::Deleted, progress);
+            if !removed {
+                return FileOutcome::skipped(file_path.clone(), destination, "already removed");
+            }
+            let count = deleted_count.fetch_add(1, Ordering::Relaxed) + 1;
+            progress.on_event(&ProgressEvent::FileDeleted {
+                source: file_path.clone(),
+                completed: count,
+                total: manifest.len(),
+            });
+            FileOutcome {
+                source: file_path.clone(),
+                destination,
+                renamed_from: None,
+                companions: copied.companions.clone(),
+                status: FileStatus::Done,
+                bytes: 0,
+                elapsed: file_started.elapsed(),
             }
         })
         .collect();
+    report.cancelled = cancel.is_cancelled();
+
+    if report.cancelled {
+        progress.on_event(&ProgressEvent::Cancelled(Stage::Delete));
+    }
+
+    // Continue if some files failed, they are listed in the report
+    if report.failure_count() > 0 {
+        progress.warn(format!(
+            "{} files could not be deleted due to access issues",
+            report.failure_count()
+        ));
+    }
 
-    // Check for any errors - but continue if some files failed
-    let mut _successful_deletions = 0;
-    let mut failed_deletions = 0;
+    // Clean up empty directories, unless asked to stop
+    if !report.cancelled {
+        cleanup_empty_directories(source_path, Some(destination_path), progress)?;
+    }
+    report.elapsed = started.elapsed();
+
+    progress.on_event(&ProgressEvent::StageFinished {
+        stage: Stage::Delete,
+        succeeded: report.success_count(),
+        failed: report.failure_count(),
+    });
+
+    Ok(report)
+}
 
-    for result in results {
-        match result {
-            Ok(()) => _successful_deletions += 1,
-            Err(_) => failed_deletions += 1,
+/// Delete the original companions of a copied file, ignoring any already gone
+fn delete_companions(copied: &CopiedFile) -> io::Result<()> {
+    for companion in &copied.companions {
+        match fs::remove_file(&companion.source) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("'{}': {}", companion.source.display(), e),
+                ))
+            }
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::CliOptions;
+    use crate::config::Config;
+    use crate::layout::DestinationLayout;
+    use crate::media::PairMode;
+    use crate::progress::NoProgress;
+    use crate::test_support::TempDir;
+    use std::sync::mpsc;
+
+    fn planned(source: PathBuf, destination: PathBuf) -> PlannedCopy {
+        PlannedCopy {
+            source,
+            destination,
+            renamed_from: None,
+            companions: Vec::new(),
+            copy_links: false,
+            drop_motion: false,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn free_space_of_a_missing_destination_is_that_of_its_drive() {
+        let dir = TempDir::new();
+
+        let space = get_available_disk_space(dir.path()).unwrap();
+        let missing = get_available_disk_space(&dir.path().join("new/folder")).unwrap();
+
+        assert!(space > 0);
+        // Other processes may write in between, so only roughly the same
+        assert!(missing.abs_diff(space) < 64 * 1024 * 1024);
+    }
+
+    #[test]
+    fn unique_path_counts_up_from_the_first_free_suffix() {
+        let taken = [Path::new("d/a.jpg"), Path::new("d/a_1.jpg")];
+        let unique = |path: &str| {
+            find_unique_path(Path::new(path), |candidate| taken.contains(&candidate)).unwrap()
+        };
+        assert_eq!(unique("d/b.jpg"), Path::new("d/b.jpg"));
+        assert_eq!(unique("d/a.jpg"), Path::new("d/a_2.jpg"));
+
+        let without_extension = find_unique_path(Path::new("d/README"), |candidate| {
+            candidate.ends_with("README")
+        })
+        .unwrap();
+        assert_eq!(without_extension, Path::new("d/README_1"));
+
+        assert!(find_unique_path(Path::new("d/a.jpg"), |_| true).is_err());
+    }
+
+    #[test]
+    fn sidecars_follow_the_name_of_their_file() {
+        let renamed = |sidecar: &str| {
+            companion_destination(
+                Path::new("src/IMG_1.JPG"),
+                Path::new("dst/IMG_1_1.JPG"),
+                Path::new(sidecar),
+            )
+        };
+        assert_eq!(renamed("src/IMG_1.xmp"), Path::new("dst/IMG_1_1.xmp"));
+        assert_eq!(
+            renamed("src/IMG_1.JPG.xmp"),
+            Path::new("dst/IMG_1_1.JPG.xmp")
+        );
+        assert_eq!(renamed("src/img_1.aae"), Path::new("dst/IMG_1_1.aae"));
+        assert_eq!(
+            renamed("src/IMG_1.JPG.supplemental.json"),
+            Path::new("dst/IMG_1_1.JPG.supplemental.json")
+        );
+
+        // Kept under its own name when the file keeps its name
+        assert_eq!(
+            companion_destination(
+                Path::new("src/IMG_1.JPG"),
+                Path::new("dst/trip/IMG_1.JPG"),
+                Path::new("src/img_1.XMP"),
+            ),
+            Path::new("dst/trip/img_1.XMP")
+        );
+    }
+
+    #[test]
+    fn a_taken_sidecar_name_moves_its_file_on() {
+        let sidecars = [PathBuf::from("src/a.xmp")];
+        let taken = |path: &Path| path == Path::new("dst/a.xmp");
+        let unique = find_unique_path(Path::new("dst/a.jpg"), |candidate| {
+            copy_is_taken(Path::new("src/a.jpg"), &sidecars, candidate, taken)
+        })
+        .unwrap();
+        assert_eq!(unique, Path::new("dst/a_1.jpg"));
+    }
+
+    fn scan(source: &Path, pairs: PairMode) -> Vec<MediaFile> {
+        let mut options = CliOptions::default().scan_options(&Config::default());
+        options.pairs = pairs;
+        collect_media_files_and_calculate_size(
+            source,
+            None,
+            &options,
+            &CancellationToken::new(),
+            &NoProgress,
+        )
+        .unwrap()
+        .0
+    }
+
+    #[test]
+    fn raw_and_jpeg_are_renamed_together() {
+        let dir = TempDir::new();
+        dir.write("src/IMG_1.CR2", "raw");
+        dir.write("src/IMG_1.JPG", "jpeg");
+        dir.write("src/IMG_1.xmp", "sidecar");
+        dir.write("dst/IMG_1.JPG", "an earlier import");
+        let (source, destination) = (dir.path().join("src"), dir.path().join("dst"));
+
+        let media_files = scan(&source, PairMode::Both);
+        assert_eq!(media_files.len(), 1);
+        let plan = plan_copies(
+            &source,
+            &destination,
+            &CopyPlacement::default(),
+            &media_files,
+        )
+        .unwrap();
+
+        let mut names: Vec<PathBuf> = std::iter::once(&plan[0].destination)
+            .chain(plan[0].companions.iter().map(|c| &c.destination))
+            .map(|path| path.strip_prefix(&destination).unwrap().to_path_buf())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["IMG_1_1.CR2", "IMG_1_1.JPG", "IMG_1_1.xmp"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn a_flat_layout_renames_files_of_the_same_name_from_other_folders() {
+        let dir = TempDir::new();
+        dir.write("src/x.jpg", "root");
+        dir.write("src/a/x.jpg", "a");
+        dir.write("src/a/x.xmp", "sidecar a");
+        dir.write("src/b/c/x.jpg", "c");
+        let (source, destination) = (dir.path().join("src"), dir.path().join("dst"));
+        let placement = CopyPlacement {
+            layout: DestinationLayout::Flat,
+            ..CopyPlacement::default()
+        };
+
+        let plan = plan_copies(
+            &source,
+            &destination,
+            &placement,
+            &scan(&source, PairMode::Both),
+        )
+        .unwrap();
+        let report = copy_planned_files(
+            &destination,
+            &plan,
+            None,
+            &CancellationToken::new(),
+            &NoProgress,
+        )
+        .unwrap();
+
+        assert_eq!(report.success_count(), 3);
+        assert_eq!(
+            fs::read_to_string(destination.join("x.jpg")).unwrap(),
+            "root"
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("x_1.jpg")).unwrap(),
+            "a"
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("x_1.xmp")).unwrap(),
+            "sidecar a"
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("x_2.jpg")).unwrap(),
+            "c"
+        );
+        let renamed: Vec<_> = report
+            .renamed()
+            .map(|outcome| (outcome.source.clone(), outcome.renamed_from.clone()))
+            .collect();
+        assert_eq!(
+            renamed,
+            [
+                (source.join("a/x.jpg"), Some(destination.join("x.jpg"))),
+                (source.join("b/c/x.jpg"), Some(destination.join("x.jpg"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn pair_mode_keeps_one_half() {
+        let dir = TempDir::new();
+        dir.write("src/IMG_1.CR2", "raw");
+        dir.write("src/IMG_1.jpg", "jpeg");
+        dir.write("src/IMG_2.jpg", "jpeg without raw");
+        let source = dir.path().join("src");
+        let paths = |pairs| {
+            let mut paths: Vec<PathBuf> = scan(&source, pairs)
+                .iter()
+                .flat_map(MediaFile::with_companions)
+                .map(|file| file.path.clone())
+                .collect();
+            paths.sort();
+            paths
+        };
 
-    if failed_deletions > 0 {
-        println!(
-            "Warning: {} files could not be deleted due to access issues",
-            failed_deletions
+        assert_eq!(
+            paths(PairMode::Raw),
+            ["IMG_1.CR2", "IMG_2.jpg"].map(PathBuf::from)
+        );
+        assert_eq!(
+            paths(PairMode::Jpeg),
+            ["IMG_1.jpg", "IMG_2.jpg"].map(PathBuf::from)
+        );
+        assert_eq!(
+            paths(PairMode::Both),
+            ["IMG_1.CR2", "IMG_1.jpg", "IMG_2.jpg"].map(PathBuf::from)
         );
     }
 
-    // Clean up empty directories
-    cleanup_empty_directories(source_path)?;
+    #[test]
+    fn copy_never_replaces_a_file_created_after_planning() {
+        let dir = TempDir::new();
+        let source = dir.write("src/a.jpg", "new");
+        let destination = dir.path().join("dst");
+        let existing = dir.write("dst/a.jpg", "someone else's");
 
-    Ok(deleted_count.load(Ordering::Relaxed))
+        let outcome = copy_planned_file(
+            &destination,
+            &planned(source, existing.clone()),
+            None,
+            &NoProgress,
+        );
+
+        assert!(outcome.is_done());
+        assert_eq!(fs::read(&existing).unwrap(), b"someone else's");
+        assert_eq!(outcome.destination, Some(destination.join("a_1.jpg")));
+        assert_eq!(outcome.renamed_from, Some(existing));
+        assert_eq!(fs::read(destination.join("a_1.jpg")).unwrap(), b"new");
+    }
+
+    #[test]
+    fn copies_planned_to_the_same_name_all_get_their_own() {
+        let dir = TempDir::new();
+        let destination = dir.path().join("dst");
+        fs::create_dir_all(&destination).unwrap();
+        let plan: Vec<PlannedCopy> = (0..8)
+            .map(|i| {
+                planned(
+                    dir.write(&format!("src/{}/a.jpg", i), i.to_string()),
+                    destination.join("a.jpg"),
+                )
+            })
+            .collect();
+
+        let report = copy_planned_files(
+            &destination,
+            &plan,
+            None,
+            &CancellationToken::new(),
+            &NoProgress,
+        )
+        .unwrap();
+
+        assert_eq!(report.success_count(), 8);
+        let mut contents: Vec<String> = report
+            .manifest()
+            .iter()
+            .map(|copied| fs::read_to_string(&copied.destination).unwrap())
+            .collect();
+        contents.sort();
+        assert_eq!(contents, ["0", "1", "2", "3", "4", "5", "6", "7"]);
+    }
+
+    #[test]
+    fn delete_removes_the_file_before_its_companions() {
+        let dir = TempDir::new();
+        let source = dir.write("src/a.jpg", "a");
+        // A folder in place of the sidecar, which remove_file cannot delete
+        let sidecar = dir.path().join("src/a.xmp");
+        dir.write("src/a.xmp/inside", "");
+        let copied = CopiedFile {
+            source: source.clone(),
+            destination: dir.write("dst/a.jpg", "a"),
+            companions: vec![CompanionCopy {
+                source: sidecar.clone(),
+                destination: dir.write("dst/a.xmp", ""),
+            }],
+        };
+        let delete = |manifest: &[CopiedFile]| {
+            delete_original_files(
+                &dir.path().join("src"),
+                &dir.path().join("dst"),
+                manifest,
+                None,
+                &CancellationToken::new(),
+                &NoProgress,
+            )
+            .unwrap()
+        };
+
+        let report = delete(std::slice::from_ref(&copied));
+        assert_eq!(report.failure_count(), 1);
+        assert!(!source.exists());
+        assert!(sidecar.exists());
+
+        // A second attempt finishes the companions
+        fs::remove_dir_all(&sidecar).unwrap();
+        fs::write(&sidecar, "").unwrap();
+        let report = delete(std::slice::from_ref(&copied));
+        assert_eq!(report.skipped_count(), 1);
+        assert!(!sidecar.exists());
+    }
+
+    /// A source folder with two files, their plan and a cancelled token
+    fn cancelled_import(dir: &TempDir) -> (PathBuf, PathBuf, Vec<PlannedCopy>, CancellationToken) {
+        dir.write("src/a.jpg", "image a");
+        dir.write("src/trip/b.jpg", "image b");
+        fs::create_dir_all(dir.path().join("dst")).unwrap();
+        let (source, destination) = (dir.path().join("src"), dir.path().join("dst"));
+        let plan = ["a.jpg", "trip/b.jpg"]
+            .map(|name| planned(source.join(name), destination.join(name)))
+            .to_vec();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        (source, destination, plan, cancel)
+    }
+
+    #[test]
+    fn a_cancelled_scan_stops() {
+        let dir = TempDir::new();
+        let (source, _, _, cancel) = cancelled_import(&dir);
+        let options = CliOptions::default().scan_options(&Config::default());
+
+        let scanned =
+            collect_media_files_and_calculate_size(&source, None, &options, &cancel, &NoProgress);
+
+        assert!(is_cancelled_error(&scanned.unwrap_err()));
+    }
+
+    #[test]
+    fn cancelled_copies_are_not_started_and_stay_in_the_journal() {
+        let dir = TempDir::new();
+        let (source, destination, plan, cancel) = cancelled_import(&dir);
+        let journal = Journal::create(&source, &destination, &plan).unwrap();
+
+        let planned_report =
+            copy_planned_files(&destination, &plan, Some(&journal), &cancel, &NoProgress).unwrap();
+        let (queue, queued) = mpsc::sync_channel(plan.len());
+        for planned in &plan {
+            queue.send(planned.clone()).unwrap();
+        }
+        drop(queue);
+        let queued_report = copy_queued_files(
+            &destination,
+            queued,
+            plan.len(),
+            Some(&journal),
+            &cancel,
+            &NoProgress,
+        )
+        .unwrap();
+        drop(journal);
+
+        for report in [planned_report, queued_report] {
+            assert_eq!(report.not_started().count(), 2);
+            assert_eq!(report.success_count(), 0);
+        }
+        assert_eq!(fs::read_dir(&destination).unwrap().count(), 1);
+        let (_, state) = Journal::open_existing(&destination).unwrap().unwrap();
+        assert_eq!(state.pending_copies(), plan);
+        assert!(state.interrupted_copies().is_empty());
+    }
+
+    #[test]
+    fn cancelled_deletion_keeps_the_originals() {
+        let dir = TempDir::new();
+        let (source, destination, plan, cancel) = cancelled_import(&dir);
+        let manifest: Vec<CopiedFile> = plan
+            .iter()
+            .map(|planned| {
+                fs::create_dir_all(planned.destination.parent().unwrap()).unwrap();
+                fs::copy(&planned.source, &planned.destination).unwrap();
+                CopiedFile {
+                    source: planned.source.clone(),
+                    destination: planned.destination.clone(),
+                    companions: Vec::new(),
+                }
+            })
+            .collect();
+
+        let report =
+            delete_original_files(&source, &destination, &manifest, None, &cancel, &NoProgress)
+                .unwrap();
+
+        assert_eq!(report.not_started().count(), 2);
+        assert!(plan.iter().all(|planned| planned.source.exists()));
+    }
 }
@@ -0,0 +1,314 @@
+//! Include and exclude patterns for the source walk.
+//!
+//! Patterns use gitignore syntax and are matched against paths relative to
+//! the source folder: `*_edited.*` matches at any depth, `DCIM/**` only under
+//! the top-level `DCIM` folder, a trailing `/` only matches folders, and a
+//! leading `!` re-includes what an earlier pattern excluded.
+//!
+//! Besides the patterns given for a run, every folder of the source tree may
+//! hold an ignore file with more exclude patterns, relative to that folder,
+//! just like a `.gitignore`. Excluded folders are never descended, nor are
+//! folders whose whole contents are excluded, as with `**/@eaDir/**`, or
+//! folders no include pattern can match anything inside of.
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::progress::ProgressListener;
+
+/// Name of the ignore file honoured in every folder of the source tree
+pub const IGNORE_FILE_NAME: &str = ".image_mover_ignore";
+
+/// Decides which files and folders of the source tree a scan looks at
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include_patterns: Vec<String>,
+    exclude_patterns: Vec<String>,
+    include: Option<Gitignore>,
+    exclude: Option<Gitignore>,
+    /// The literal leading folders of every include pattern, or `None` if
+    /// some pattern can match at any depth
+    include_prefixes: Option<Vec<Vec<String>>>,
+    /// Ignore files already read, by folder relative to the source root
+    ignore_files: Arc<RwLock<HashMap<PathBuf, Option<Gitignore>>>>,
+}
+
+impl PartialEq for PathFilter {
+    fn eq(&self, other: &PathFilter) -> bool {
+        self.include_patterns == other.include_patterns
+            && self.exclude_patterns == other.exclude_patterns
+    }
+}
+
+impl Eq for PathFilter {}
+
+impl PathFilter {
+    /// A filter that only collects files matching one of `include`, if any
+    /// are given, and skips everything matching `exclude`
+    pub fn new(include: &[String], exclude: &[String]) -> io::Result<PathFilter> {
+        Ok(PathFilter {
+            include_patterns: include.to_vec(),
+            exclude_patterns: exclude.to_vec(),
+            include: build_matcher(include)?,
+            exclude: build_matcher(exclude)?,
+            include_prefixes: include_prefixes(include),
+            ignore_files: Arc::default(),
+        })
+    }
+
+    /// Whether a file or folder, given relative to `source_root`, is excluded
+    /// by the patterns or by an ignore file in one of the folders above it
+    pub fn is_excluded(
+        &self,
+        source_root: &Path,
+        relative_path: &Path,
+        is_dir: bool,
+        progress: &dyn ProgressListener,
+    ) -> bool {
+        if let Some(exclude) = &self.exclude {
+            if is_ignored(exclude, relative_path, is_dir) == Some(true) {
+                return true;
+            }
+        }
+
+        // The ignore file nearest to the path decides, as with .gitignore
+        for folder in relative_path.ancestors().skip(1) {
+            let Some(ignore_file) = self.ignore_file(source_root, folder, progress) else {
+                continue;
+            };
+            if let Some(ignored) = is_ignored(&ignore_file, relative_path, is_dir) {
+                return ignored;
+            }
+        }
+
+        false
+    }
+
+    /// Whether a file, given relative to the source root, matches the
+    /// include patterns. Without include patterns every file does.
+    pub fn is_included(&self, relative_path: &Path) -> bool {
+        match &self.include {
+            Some(include) => include.matched(relative_path, false).is_ignore(),
+            None => true,
+        }
+    }
+
+    /// Whether a folder, given relative to the source root, may hold files
+    /// matching the include patterns at any depth. Without include patterns
+    /// every folder may.
+    pub fn may_include_below(&self, relative_dir: &Path) -> bool {
+        let Some(prefixes) = &self.include_prefixes else {
+            return true;
+        };
+        let folders: Vec<_> = relative_dir
+            .components()
+            .map(|component| component.as_os_str())
+            .collect();
+        prefixes.iter().any(|prefix| {
+            prefix
+                .iter()
+                .zip(&folders)
+                .all(|(literal, folder)| literal.as_str() == *folder)
+        })
+    }
+
+    /// The ignore file of a folder, read once and then remembered. Folders
+    /// already seen only take a shared lock, so parallel walks of the tree
+    /// do not wait on each other.
+    fn ignore_file(
+        &self,
+        source_root: &Path,
+        folder: &Path,
+        progress: &dyn ProgressListener,
+    ) -> Option<Gitignore> {
+        let known = match self.ignore_files.read() {
+            Ok(ignore_files) => ignore_files.get(folder).cloned(),
+            Err(poisoned) => poisoned.into_inner().get(folder).cloned(),
+        };
+        if let Some(ignore_file) = known {
+            return ignore_file;
+        }
+
+        let ignore_file = read_ignore_file(source_root, folder, progress);
+        let mut ignore_files = match self.ignore_files.write() {
+            Ok(ignore_files) => ignore_files,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        // Another thread may have read it meanwhile; its copy is kept
+        ignore_files
+            .entry(folder.to_path_buf())
+            .or_insert(ignore_file)
+            .clone()
+    }
+}
+
+/// Name standing for any file in a folder: no file can be called this, so
+/// only patterns matching every name inside the folder match it
+const ANY_NAME: &str = "\0";
+
+/// Whether `matcher` excludes (`Some(true)`) or re-includes (`Some(false)`)
+/// a path, or has no pattern for it. A folder none of the patterns names is
+/// still excluded if they exclude everything inside it, as `dir/**` does,
+/// unless some pattern re-includes files.
+fn is_ignored(matcher: &Gitignore, path: &Path, is_dir: bool) -> Option<bool> {
+    match matcher.matched(path, is_dir) {
+        Match::Ignore(_) => Some(true),
+        Match::Whitelist(_) => Some(false),
+        Match::None if is_dir && matcher.num_whitelists() == 0 => matcher
+            .matched(path.join(ANY_NAME), false)
+            .is_ignore()
+            .then_some(true),
+        Match::None => None,
+    }
+}
+
+/// Read the ignore file of a folder, if it has one
+fn read_ignore_file(
+    source_root: &Path,
+    folder: &Path,
+    progress: &dyn ProgressListener,
+) -> Option<Gitignore> {
+    let path = source_root.join(folder).join(IGNORE_FILE_NAME);
+    if !path.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(folder);
+    if let Some(e) = builder.add(&path) {
+        progress.warn(format!("In '{}': {}", path.display(), e));
+    }
+    match builder.build() {
+        Ok(ignore_file) => Some(ignore_file),
+        Err(e) => {
+            progress.warn(format!("Cannot use '{}': {}", path.display(), e));
+            None
+        }
+    }
+}
+
+/// The folders every include pattern starts with that contain no wildcards.
+/// A pattern only matches below its folders if it has a `/` before its end;
+/// one without, such as `*.jpg`, matches at any depth, and `None` is
+/// returned. Re-including `!` patterns only narrow the match and are passed
+/// over.
+fn include_prefixes(patterns: &[String]) -> Option<Vec<Vec<String>>> {
+    if patterns.is_empty() {
+        return None;
+    }
+
+    let mut prefixes = Vec::new();
+    for pattern in patterns {
+        if pattern.starts_with('!') || pattern.starts_with('#') || pattern.trim().is_empty() {
+            continue;
+        }
+        let pattern = pattern.trim_end().trim_end_matches('/');
+        if !pattern.contains('/') {
+            return None;
+        }
+
+        let folders: Vec<&str> = pattern.trim_start_matches('/').split('/').collect();
+        let prefix = folders[..folders.len() - 1]
+            .iter()
+            .take_while(|folder| !folder.contains(['*', '?', '[', '\\']))
+            .map(|folder| folder.to_string())
+            .collect();
+        prefixes.push(prefix);
+    }
+    Some(prefixes)
+}
+
+/// Compile patterns given for a run, relative to the source root
+fn build_matcher(patterns: &[String]) -> io::Result<Option<Gitignore>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in patterns {
+        builder.add_line(None, pattern).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid pattern '{}': {}", pattern, e),
+            )
+        })?;
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+    use crate::test_support::TempDir;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> PathFilter {
+        let patterns =
+            |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        PathFilter::new(&patterns(include), &patterns(exclude)).unwrap()
+    }
+
+    #[test]
+    fn folders_outside_anchored_include_patterns_are_pruned() {
+        let filter = filter(&["DCIM/100*/*.jpg", "/Camera/**"], &[]);
+        assert!(filter.may_include_below(Path::new("")));
+        assert!(filter.may_include_below(Path::new("DCIM")));
+        assert!(filter.may_include_below(Path::new("DCIM/100APPLE")));
+        assert!(filter.may_include_below(Path::new("Camera/2024")));
+        assert!(!filter.may_include_below(Path::new("Documents")));
+        assert!(!filter.may_include_below(Path::new("Backup/DCIM")));
+
+        assert!(filter.is_included(Path::new("DCIM/100APPLE/a.jpg")));
+        assert!(!filter.is_included(Path::new("DCIM/200APPLE/a.jpg")));
+    }
+
+    #[test]
+    fn include_patterns_matching_at_any_depth_prune_nothing() {
+        let filter = filter(&["DCIM/**", "*.jpg"], &[]);
+        assert!(filter.may_include_below(Path::new("Documents/deep")));
+        assert!(filter.is_included(Path::new("Documents/deep/a.jpg")));
+        assert!(!filter.is_included(Path::new("Documents/deep/a.png")));
+    }
+
+    #[test]
+    fn nearest_ignore_file_decides() {
+        let dir = TempDir::new();
+        dir.write(IGNORE_FILE_NAME, "*.png\nraw/\n");
+        dir.write(&format!("keep/{}", IGNORE_FILE_NAME), "!*.png\n");
+        let filter = filter(&[], &["*_edited.*"]);
+        let excluded = |path: &str, is_dir| {
+            filter.is_excluded(dir.path(), Path::new(path), is_dir, &NoProgress)
+        };
+
+        assert!(excluded("a.png", false));
+        assert!(excluded("trip/raw", true));
+        assert!(excluded("trip/a_edited.jpg", false));
+        assert!(!excluded("trip/a.jpg", false));
+        assert!(!excluded("keep/a.png", false));
+    }
+
+    #[test]
+    fn patterns_excluding_everything_inside_a_folder_exclude_the_folder() {
+        let dir = TempDir::new();
+        dir.write(IGNORE_FILE_NAME, "cache/**\n");
+        let keep = filter(&[], &["DCIM/*", "!DCIM/*.jpg"]);
+        let filter = filter(&[], &["**/@eaDir/**"]);
+        let excluded = |path: &str, is_dir| {
+            filter.is_excluded(dir.path(), Path::new(path), is_dir, &NoProgress)
+        };
+
+        assert!(excluded("DCIM/@eaDir", true));
+        assert!(excluded("@eaDir", true));
+        assert!(excluded("cache", true));
+        assert!(!excluded("DCIM", true));
+        assert!(!excluded("DCIM/@eaDir", false));
+        assert!(!excluded("trip/cache", true));
+
+        assert!(!keep.is_excluded(dir.path(), Path::new("DCIM"), true, &NoProgress));
+    }
+}
@@ -0,0 +1,155 @@
+//! User interaction behind a common trait.
+//!
+//! The import pipeline asks the user for folders and confirmations through
+//! [`UserInteraction`], so the same flow can be driven by Windows dialogs,
+//! by terminal prompts, or by a scripted set of answers in tests.
+
+use std::cell::RefCell;
+use std::io;
+use std::path::PathBuf;
+
+use crate::media::{describe_kind_counts, MediaKind};
+
+/// What is about to be copied, shown before asking to proceed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopySummary {
+    pub file_count: usize,
+    /// Number of files of each kind, listing only the kinds that occur
+    pub by_kind: Vec<(MediaKind, usize)>,
+    pub total_size: u64,
+    pub available_space: u64,
+}
+
+impl CopySummary {
+    /// Whether the files will not fit in the available space
+    pub fn exceeds_available_space(&self) -> bool {
+        self.total_size > self.available_space
+    }
+
+    /// The per-kind counts as text, e.g. "3 images, 2 videos"
+    pub fn describe_kinds(&self) -> String {
+        describe_kind_counts(&self.by_kind)
+    }
+}
+
+/// The questions and notices the import pipeline needs from the user
+pub trait UserInteraction {
+    /// Choose a folder, returning `None` if the user cancels
+    fn select_folder(&self, title: &str) -> io::Result<Option<PathBuf>>;
+
+    /// Confirm that the copy should start
+    fn confirm_copy(&self, summary: &CopySummary) -> io::Result<bool>;
+
+    /// Confirm that an unfinished import found in the destination should be resumed
+    fn confirm_resume(&self, remaining_count: usize, total_count: usize) -> io::Result<bool>;
+
+    /// Confirm that copies should be verified by content hash
+    fn confirm_verify(&self, file_count: usize) -> io::Result<bool>;
+
+    /// Tell the user that some copies failed verification
+    fn report_verification_failures(&self, failed_count: usize) -> io::Result<()>;
+
+    /// Consent to deleting the originals of copied files
+    fn confirm_delete(&self, file_count: usize) -> io::Result<bool>;
+
+    /// Tell the user that the run has finished
+    fn notify_completion(&self) -> io::Result<()>;
+}
+
+/// A question or notice shown through [`ScriptedInteraction`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prompt {
+    SelectFolder(String),
+    ConfirmCopy(CopySummary),
+    ConfirmResume {
+        remaining_count: usize,
+        total_count: usize,
+    },
+    ConfirmVerify(usize),
+    VerificationFailures(usize),
+    ConfirmDelete(usize),
+    Completion,
+}
+
+/// Answers every question from a fixed script and records what was asked.
+///
+/// Folders are handed out in order: the first call to `select_folder` gets
+/// the first folder, and so on; running out counts as cancelling.
+#[derive(Debug, Default)]
+pub struct ScriptedInteraction {
+    pub folders: Vec<PathBuf>,
+    pub copy: bool,
+    pub resume: bool,
+    pub verify: bool,
+    pub delete: bool,
+    prompts: RefCell<Vec<Prompt>>,
+}
+
+impl ScriptedInteraction {
+    /// A script that selects the given folders and answers yes to everything
+    pub fn approve_all(source: PathBuf, destination: PathBuf) -> ScriptedInteraction {
+        ScriptedInteraction {
+            folders: vec![source, destination],
+            copy: true,
+            resume: true,
+            verify: true,
+            delete: true,
+            prompts: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Everything that was asked or shown so far, in order
+    pub fn prompts(&self) -> Vec<Prompt> {
+        self.prompts.borrow().clone()
+    }
+
+    fn record(&self, prompt: Prompt) {
+        self.prompts.borrow_mut().push(prompt);
+    }
+}
+
+impl UserInteraction for ScriptedInteraction {
+    fn select_folder(&self, title: &str) -> io::Result<Option<PathBuf>> {
+        let index = self
+            .prompts
+            .borrow()
+            .iter()
+            .filter(|prompt| matches!(prompt, Prompt::SelectFolder(_)))
+            .count();
+        self.record(Prompt::SelectFolder(title.to_string()));
+        Ok(self.folders.get(index).cloned())
+    }
+
+    fn confirm_copy(&self, summary: &CopySummary) -> io::Result<bool> {
+        self.record(Prompt::ConfirmCopy(summary.clone()));
+        Ok(self.copy)
+    }
+
+    fn confirm_resume(&self, remaining_count: usize, total_count: usize) -> io::Result<bool> {
+        self.record(Prompt::ConfirmResume {
+            remaining_count,
+            total_count,
+        });
+        Ok(self.resume)
+    }
+
+    fn confirm_verify(&self, file_count: usize) -> io::Result<bool> {
+        self.record(Prompt::ConfirmVerify(file_count));
+        Ok(self.verify)
+    }
+
+    fn report_verification_failures(&self, failed_count: usize) -> io::Result<()> {
+        self.record(Prompt::VerificationFailures(failed_count));
+        Ok(())
+    }
+
+    fn confirm_delete(&self, file_count: usize) -> io::Result<bool> {
+        self.record(Prompt::ConfirmDelete(file_count));
+        Ok(self.delete)
+    }
+
+    fn notify_completion(&self) -> io::Result<()> {
+        self.record(Prompt::Completion);
+        Ok(())
+    }
+}
@@ -0,0 +1,811 @@
+//! Crash-safe operation journal.
+//!
+//! This module records the progress of an import in a journal file kept in
+//! the destination folder. Every file is written to the journal when it is
+//! planned, and again as it is copied, verified and deleted, so a run that
+//! is interrupted can be resumed from exactly where it stopped instead of
+//! starting over and creating `_1` duplicates.
+//!
+//! The journal is a plain text file with one tab-separated record per line.
+//! It is removed once a run finishes normally; finding one at the start of
+//! a run therefore means the previous run did not complete.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::file_ops::{CompanionCopy, CopiedFile, PlannedCopy};
+use crate::progress::ProgressListener;
+
+/// Name of the journal file inside the destination folder
+pub const JOURNAL_FILE_NAME: &str = ".image_mover_journal";
+
+/// First line of every journal file
+const JOURNAL_HEADER: &str = "image_mover journal v1";
+
+/// Progress of a single file through the import
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+    /// Destination chosen, copy not yet started
+    Planned,
+    /// About to create the files at the recorded destination. Something
+    /// else may have taken the name first, so only an empty file found
+    /// there is taken to be this import's.
+    Claiming,
+    /// Copy started at the recorded destination, which this import created
+    Copying,
+    /// The copy could not be made and will be retried on resume
+    CopyFailed,
+    /// Copied and flushed to the destination
+    Copied,
+    /// Copy confirmed identical to the original
+    Verified,
+    /// Copy did not match the original; the original must be kept
+    VerifyFailed,
+    /// Original removed from the source folder
+    Deleted,
+}
+
+impl FileState {
+    fn as_str(self) -> &'static str {
+        match self {
+            FileState::Planned => "planned",
+            FileState::Claiming => "claiming",
+            FileState::Copying => "copying",
+            FileState::CopyFailed => "copy-failed",
+            FileState::Copied => "copied",
+            FileState::Verified => "verified",
+            FileState::VerifyFailed => "verify-failed",
+            FileState::Deleted => "deleted",
+        }
+    }
+
+    fn parse(value: &str) -> Option<FileState> {
+        match value {
+            "planned" => Some(FileState::Planned),
+            "claiming" => Some(FileState::Claiming),
+            "copying" => Some(FileState::Copying),
+            "copy-failed" => Some(FileState::CopyFailed),
+            "copied" => Some(FileState::Copied),
+            "verified" => Some(FileState::Verified),
+            "verify-failed" => Some(FileState::VerifyFailed),
+            "deleted" => Some(FileState::Deleted),
+            _ => None,
+        }
+    }
+}
+
+/// Pipeline stages that the user opted into, recorded so a resumed run
+/// carries on with the same choices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Verify,
+    Delete,
+}
+
+impl Stage {
+    fn as_str(self) -> &'static str {
+        match self {
+            Stage::Verify => "verify",
+            Stage::Delete => "delete",
+        }
+    }
+}
+
+/// The last known state of one planned file
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    /// The path the copy would have had without a conflict rename
+    pub renamed_from: Option<PathBuf>,
+    /// Companion files travelling with the file
+    pub companions: Vec<CompanionCopy>,
+    /// Whether symbolic links are copied as links
+    pub copy_links: bool,
+    /// Whether motion photos are copied without their video
+    pub drop_motion: bool,
+    pub state: FileState,
+}
+
+impl JournalEntry {
+    fn as_copied(&self) -> CopiedFile {
+        CopiedFile {
+            source: self.source.clone(),
+            destination: self.destination.clone(),
+            companions: self.companions.clone(),
+        }
+    }
+
+    fn as_planned(&self) -> PlannedCopy {
+        PlannedCopy {
+            source: self.source.clone(),
+            destination: self.destination.clone(),
+            renamed_from: self.renamed_from.clone(),
+            companions: self.companions.clone(),
+            copy_links: self.copy_links,
+            drop_motion: self.drop_motion,
+        }
+    }
+}
+
+/// Everything recorded by an unfinished run
+#[derive(Debug, Clone)]
+pub struct JournalState {
+    pub source_root: PathBuf,
+    pub destination_root: PathBuf,
+    /// Entries in the order they were planned
+    pub entries: Vec<JournalEntry>,
+    pub verify_started: bool,
+    pub delete_started: bool,
+}
+
+impl JournalState {
+    /// Files that still need to be copied, including failed and
+    /// interrupted attempts
+    pub fn pending_copies(&self) -> Vec<PlannedCopy> {
+        self.entries
+            .iter()
+            .filter(|entry| {
+                matches!(
+                    entry.state,
+                    FileState::Planned
+                        | FileState::Claiming
+                        | FileState::Copying
+                        | FileState::CopyFailed
+                )
+            })
+            .map(JournalEntry::as_planned)
+            .collect()
+    }
+
+    /// Files whose copy was started but not finished. What is at their
+    /// destinations was written by the interrupted run.
+    pub fn interrupted_copies(&self) -> Vec<PlannedCopy> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.state == FileState::Copying)
+            .map(JournalEntry::as_planned)
+            .collect()
+    }
+
+    /// Files whose destinations were about to be created when the run
+    /// stopped. Empty files at their destinations may be left over from it.
+    pub fn claimed_copies(&self) -> Vec<PlannedCopy> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.state == FileState::Claiming)
+            .map(JournalEntry::as_planned)
+            .collect()
+    }
+
+    /// Files that were copied but not yet verified
+    pub fn unverified_copies(&self) -> Vec<CopiedFile> {
+        self.entries_in_state(FileState::Copied)
+    }
+
+    /// Files whose copies have been verified
+    pub fn verified_copies(&self) -> Vec<CopiedFile> {
+        self.entries_in_state(FileState::Verified)
+    }
+
+    /// Files whose copies failed verification
+    pub fn failed_verifications(&self) -> Vec<CopiedFile> {
+        self.entries_in_state(FileState::VerifyFailed)
+    }
+
+    /// Number of originals already deleted
+    pub fn deleted_count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.state == FileState::Deleted)
+            .count()
+    }
+
+    fn entries_in_state(&self, state: FileState) -> Vec<CopiedFile> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.state == state)
+            .map(JournalEntry::as_copied)
+            .collect()
+    }
+}
+
+/// An open journal that records progress as files move through the import
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+/// Get the journal path for a destination folder
+pub fn journal_path(destination_root: &Path) -> PathBuf {
+    destination_root.join(JOURNAL_FILE_NAME)
+}
+
+/// Write a path as journal text, exactly: bytes that are not UTF-8, as
+/// well as `%` and the characters that would break the line and field
+/// structure, are written as `%` and two hex digits
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for chunk in path_bytes(path).utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '%' | '\t' | '\n' | '\r' => encoded.push_str(&format!("%{:02X}", c as u8)),
+                _ => encoded.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn decode_path(value: &str) -> PathBuf {
+    let value = value.as_bytes();
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut i = 0;
+    while i < value.len() {
+        let escaped = (value[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(value[i]);
+                i += 1;
+            }
+        }
+    }
+    path_from_bytes(bytes)
+}
+
+/// The bytes of a path as the operating system has them
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> &[u8] {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes()
+}
+
+/// The bytes of a path, WTF-8 encoded where it is not valid Unicode
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> &[u8] {
+    path.as_os_str().as_encoded_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    std::ffi::OsString::from_vec(bytes).into()
+}
+
+/// Paths that are not valid Unicode were written as WTF-8, which encodes
+/// unpaired surrogates like any other UTF-16 code unit
+#[cfg(windows)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::windows::ffi::OsStringExt;
+    let bytes = match String::from_utf8(bytes) {
+        Ok(text) => return text.into(),
+        Err(e) => e.into_bytes(),
+    };
+
+    let mut wide = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let first = bytes[i] as u32;
+        let (length, initial) = match first {
+            0x00..=0x7F => (1, first),
+            0xC0..=0xDF => (2, first & 0x1F),
+            0xE0..=0xEF => (3, first & 0x0F),
+            _ => (4, first & 0x07),
+        };
+        let code = bytes
+            .get(i + 1..i + length)
+            .unwrap_or_default()
+            .iter()
+            .fold(initial, |code, byte| code << 6 | (*byte as u32 & 0x3F));
+        if code >= 0x10000 {
+            let code = code - 0x10000;
+            wide.push(0xD800 | (code >> 10) as u16);
+            wide.push(0xDC00 | (code & 0x3FF) as u16);
+        } else {
+            wide.push(code as u16);
+        }
+        i += length;
+    }
+    std::ffi::OsString::from_wide(&wide).into()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
+
+/// The records of a planned copy: the file itself, followed by its link and
+/// motion photo settings and its companions
+fn planned_records(planned: &PlannedCopy) -> String {
+    let mut records = format!(
+        "{}\t{}\t{}",
+        FileState::Planned.as_str(),
+        encode_path(&planned.source),
+        encode_path(&planned.destination)
+    );
+    if let Some(renamed_from) = &planned.renamed_from {
+        records.push('\t');
+        records.push_str(&encode_path(renamed_from));
+    }
+    records.push('\n');
+    if planned.copy_links {
+        records.push_str(&format!("links\t{}\n", encode_path(&planned.source)));
+    }
+    if planned.drop_motion {
+        records.push_str(&format!("no-motion\t{}\n", encode_path(&planned.source)));
+    }
+    records.push_str(&companion_records(&planned.source, &planned.companions));
+    records
+}
+
+/// One record per companion of the file at `source`
+fn companion_records(source: &Path, companions: &[CompanionCopy]) -> String {
+    companions
+        .iter()
+        .map(|companion| {
+            format!(
+                "companion\t{}\t{}\t{}\n",
+                encode_path(source),
+                encode_path(&companion.source),
+                encode_path(&companion.destination)
+            )
+        })
+        .collect()
+}
+
+fn invalid_data(line_number: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Journal line {}: {}", line_number, message),
+    )
+}
+
+impl Journal {
+    /// Start a new journal for a run, recording every planned copy.
+    ///
+    /// Any journal already present in the destination is replaced.
+    pub fn create(
+        source_root: &Path,
+        destination_root: &Path,
+        plan: &[PlannedCopy],
+    ) -> io::Result<Journal> {
+        let path = journal_path(destination_root);
+        let mut contents = format!(
+            "{}\nsource\t{}\ndestination\t{}\n",
+            JOURNAL_HEADER,
+            encode_path(source_root),
+            encode_path(destination_root)
+        );
+        for planned in plan {
+            contents.push_str(&planned_records(planned));
+        }
+
+        // Write the complete plan before anything is copied
+        let mut file = File::create(&path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+
+        Ok(Journal {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Open the unfinished journal in a destination folder, if there is one
+    pub fn open_existing(destination_root: &Path) -> io::Result<Option<(Journal, JournalState)>> {
+        let path = journal_path(destination_root);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let state = Self::parse(&contents)?;
+        let file = OpenOptions::new().append(true).open(&path)?;
+
+        Ok(Some((
+            Journal {
+                path,
+                file: Mutex::new(file),
+            },
+            state,
+        )))
+    }
+
+    fn parse(contents: &str) -> io::Result<JournalState> {
+        // A crash can leave a partly written last line; only complete lines count
+        let complete = match contents.rfind('\n') {
+            Some(end) => &contents[..end],
+            None => "",
+        };
+        let mut lines = complete.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+        match lines.next() {
+            Some((_, JOURNAL_HEADER)) => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Not an image mover journal",
+                ))
+            }
+        }
+
+        let mut source_root = None;
+        let mut destination_root = None;
+        let mut entries: Vec<JournalEntry> = Vec::new();
+        let mut index_by_source: HashMap<PathBuf, usize> = HashMap::new();
+        let mut verify_started = false;
+        let mut delete_started = false;
+
+        for (line_number, line) in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["source", path] => source_root = Some(decode_path(path)),
+                ["destination", path] => destination_root = Some(decode_path(path)),
+                ["stage", "verify"] => verify_started = true,
+                ["stage", "delete"] => delete_started = true,
+                ["links", source] => {
+                    let index = *index_by_source
+                        .get(&decode_path(source))
+                        .ok_or_else(|| invalid_data(line_number, "file was never planned"))?;
+                    entries[index].copy_links = true;
+                }
+                ["no-motion", source] => {
+                    let index = *index_by_source
+                        .get(&decode_path(source))
+                        .ok_or_else(|| invalid_data(line_number, "file was never planned"))?;
+                    entries[index].drop_motion = true;
+                }
+                ["companion", source, companion, destination] => {
+                    let index = *index_by_source
+                        .get(&decode_path(source))
+                        .ok_or_else(|| invalid_data(line_number, "file was never planned"))?;
+                    let companion = CompanionCopy {
+                        source: decode_path(companion),
+                        destination: decode_path(destination),
+                    };
+                    // A later record for the same companion follows a rename
+                    let companions = &mut entries[index].companions;
+                    match companions
+                        .iter_mut()
+                        .find(|known| known.source == companion.source)
+                    {
+                        Some(known) => *known = companion,
+                        None => companions.push(companion),
+                    }
+                }
+                [state, source, rest @ ..] => {
+                    let state = FileState::parse(state)
+                        .ok_or_else(|| invalid_data(line_number, "unknown record"))?;
+                    let source = decode_path(source);
+
+                    match (state, rest) {
+                        (FileState::Planned, [destination, renamed_from @ ..])
+                            if renamed_from.len() <= 1 =>
+                        {
+                            index_by_source.insert(source.clone(), entries.len());
+                            entries.push(JournalEntry {
+                                source,
+                                destination: decode_path(destination),
+                                renamed_from: renamed_from.first().map(|path| decode_path(path)),
+                                companions: Vec::new(),
+                                copy_links: false,
+                                drop_motion: false,
+                                state,
+                            });
+                        }
+                        (_, rest) if rest.len() <= 1 => {
+                            let index = *index_by_source.get(&source).ok_or_else(|| {
+                                invalid_data(line_number, "file was never planned")
+                            })?;
+                            let entry = &mut entries[index];
+                            entry.state = state;
+                            // A copy may have landed on a different name than planned
+                            if let [destination] = rest {
+                                let destination = decode_path(destination);
+                                if destination != entry.destination && entry.renamed_from.is_none()
+                                {
+                                    entry.renamed_from = Some(entry.destination.clone());
+                                }
+                                entry.destination = destination;
+                            }
+                        }
+                        _ => return Err(invalid_data(line_number, "malformed record")),
+                    }
+                }
+                _ => return Err(invalid_data(line_number, "malformed record")),
+            }
+        }
+
+        match (source_root, destination_root) {
+            (Some(source_root), Some(destination_root)) => Ok(JournalState {
+                source_root,
+                destination_root,
+                entries,
+                verify_started,
+                delete_started,
+            }),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Journal is missing its source or destination folder",
+            )),
+        }
+    }
+
+    fn append(&self, line: String) -> io::Result<()> {
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| io::Error::other("Journal lock poisoned"))?;
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    }
+
+    /// Record a copy planned after the journal was created, before it starts
+    pub fn record_planned(&self, planned: &PlannedCopy) -> io::Result<()> {
+        self.append(planned_records(planned))
+    }
+
+    /// Record that a file reached a new state
+    pub fn record(&self, source: &Path, state: FileState) -> io::Result<()> {
+        self.append(format!("{}\t{}\n", state.as_str(), encode_path(source)))
+    }
+
+    /// Record the destinations a copy is about to create, before any of
+    /// them exists
+    pub fn record_claiming(&self, claiming: &CopiedFile) -> io::Result<()> {
+        self.record_destinations(claiming, FileState::Claiming)
+    }
+
+    /// Record that a copy is about to be written to the destinations given,
+    /// which this import has created
+    pub fn record_copying(&self, copying: &CopiedFile) -> io::Result<()> {
+        self.record_destinations(copying, FileState::Copying)
+    }
+
+    /// Record a completed copy together with the destinations it and its
+    /// companions ended up at
+    pub fn record_copy(&self, copied: &CopiedFile) -> io::Result<()> {
+        self.record_destinations(copied, FileState::Copied)
+    }
+
+    fn record_destinations(&self, copied: &CopiedFile, state: FileState) -> io::Result<()> {
+        self.append(format!(
+            "{}{}\t{}\t{}\n",
+            companion_records(&copied.source, &copied.companions),
+            state.as_str(),
+            encode_path(&copied.source),
+            encode_path(&copied.destination)
+        ))
+    }
+
+    /// Record that the user opted into a stage of the pipeline
+    pub fn record_stage(&self, stage: Stage) -> io::Result<()> {
+        self.append(format!("stage\t{}\n", stage.as_str()))
+    }
+
+    /// Mark the run as finished by removing the journal
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+    }
+}
+
+/// Remove what interrupted copies left at their destinations before they
+/// are copied again. Only destinations the journal records as created by
+/// this import are removed, together with the files a copy had created but
+/// not yet started to write, so files this import did not write are never
+/// touched.
+pub fn discard_partial_copies(state: &JournalState, progress: &dyn ProgressListener) {
+    let interrupted = state.interrupted_copies();
+    let claimed = state.claimed_copies();
+    let destinations = interrupted.iter().flat_map(copy_destinations).chain(
+        claimed
+            .iter()
+            .flat_map(copy_destinations)
+            .filter(|(source, destination)| is_unwritten_claim(source, destination)),
+    );
+    for (_, destination) in destinations {
+        match fs::remove_file(destination) {
+            Ok(()) => progress.message(format!(
+                "Removed incomplete copy: {}",
+                destination.display()
+            )),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => progress.warn(format!(
+                "Cannot remove incomplete copy '{}': {}",
+                destination.display(),
+                e
+            )),
+        }
+    }
+}
+
+/// The source and destination of a planned file and of each of its
+/// companions
+fn copy_destinations(planned: &PlannedCopy) -> impl Iterator<Item = (&Path, &Path)> {
+    std::iter::once((planned.source.as_path(), planned.destination.as_path())).chain(
+        planned
+            .companions
+            .iter()
+            .map(|companion| (companion.source.as_path(), companion.destination.as_path())),
+    )
+}
+
+/// Whether `destination` looks as a copy of `source` does right after it was
+/// created: an empty file, or a link to the same target as the original link
+fn is_unwritten_claim(source: &Path, destination: &Path) -> bool {
+    match fs::symlink_metadata(destination) {
+        Ok(metadata) if metadata.is_file() => metadata.len() == 0,
+        Ok(metadata) if metadata.is_symlink() => match fs::read_link(source) {
+            Ok(target) => fs::read_link(destination).is_ok_and(|copied| copied == target),
+            Err(_) => false,
+        },
+        _ => false,
+    }
+}
+
+/// Record a state change, warning instead of failing the file if the journal
+/// cannot be written
+pub fn record_or_warn(
+    journal: Option<&Journal>,
+    source: &Path,
+    state: FileState,
+    progress: &dyn ProgressListener,
+) {
+    if let Some(journal) = journal {
+        if let Err(e) = journal.record(source, state) {
+            progress.warn(format!(
+                "Cannot update journal for '{}': {}",
+                source.display(),
+                e
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn planned(source: &str, destination: &str) -> PlannedCopy {
+        PlannedCopy {
+            source: PathBuf::from(source),
+            destination: PathBuf::from(destination),
+            renamed_from: None,
+            companions: Vec::new(),
+            copy_links: false,
+            drop_motion: false,
+        }
+    }
+
+    #[test]
+    fn journal_round_trips_through_its_file() {
+        let dir = TempDir::new();
+        let mut with_companion = planned("src/b%\t.cr2", "dst/b%\t.cr2");
+        with_companion.companions.push(CompanionCopy {
+            source: PathBuf::from("src/b%\t.xmp"),
+            destination: PathBuf::from("dst/b%\t.xmp"),
+        });
+        with_companion.copy_links = true;
+        with_companion.drop_motion = true;
+        let plan = vec![
+            planned("src/a.jpg", "dst/a.jpg"),
+            with_companion,
+            planned("src/c\n.jpg", "dst/c\n.jpg"),
+        ];
+
+        let journal = Journal::create(Path::new("src"), dir.path(), &plan).unwrap();
+        journal
+            .record_copy(&CopiedFile {
+                source: PathBuf::from("src/a.jpg"),
+                destination: PathBuf::from("dst/a_1.jpg"),
+                companions: Vec::new(),
+            })
+            .unwrap();
+        journal
+            .record(Path::new("src/a.jpg"), FileState::Verified)
+            .unwrap();
+        journal
+            .record_copying(&CopiedFile {
+                source: PathBuf::from("src/b%\t.cr2"),
+                destination: PathBuf::from("dst/b%\t_1.cr2"),
+                companions: vec![CompanionCopy {
+                    source: PathBuf::from("src/b%\t.xmp"),
+                    destination: PathBuf::from("dst/b%\t_1.xmp"),
+                }],
+            })
+            .unwrap();
+        journal
+            .record_claiming(&CopiedFile {
+                source: PathBuf::from("src/c\n.jpg"),
+                destination: PathBuf::from("dst/c\n.jpg"),
+                companions: Vec::new(),
+            })
+            .unwrap();
+        journal.record_stage(Stage::Verify).unwrap();
+        drop(journal);
+
+        let (_, state) = Journal::open_existing(dir.path()).unwrap().unwrap();
+        assert_eq!(state.source_root, Path::new("src"));
+        assert_eq!(state.destination_root, dir.path());
+        assert!(state.verify_started);
+        assert!(!state.delete_started);
+
+        let [a, b, c] = &state.entries[..] else {
+            panic!("expected three entries, found {:?}", state.entries);
+        };
+        assert_eq!(a.state, FileState::Verified);
+        assert_eq!(a.destination, Path::new("dst/a_1.jpg"));
+        assert_eq!(a.renamed_from.as_deref(), Some(Path::new("dst/a.jpg")));
+
+        assert_eq!(b.state, FileState::Copying);
+        assert!(b.copy_links);
+        assert!(b.drop_motion);
+        assert!(!a.drop_motion);
+        assert_eq!(b.destination, Path::new("dst/b%\t_1.cr2"));
+        assert_eq!(
+            b.companions,
+            [CompanionCopy {
+                source: PathBuf::from("src/b%\t.xmp"),
+                destination: PathBuf::from("dst/b%\t_1.xmp"),
+            }]
+        );
+
+        assert_eq!(c.state, FileState::Claiming);
+        assert_eq!(c.source, Path::new("src/c\n.jpg"));
+
+        assert_eq!(state.verified_copies().len(), 1);
+        assert_eq!(state.interrupted_copies().len(), 1);
+        assert_eq!(state.claimed_copies().len(), 1);
+        assert_eq!(state.pending_copies().len(), 2);
+    }
+
+    #[test]
+    fn partly_written_last_line_is_ignored() {
+        let contents = format!(
+            "{}\nsource\tsrc\ndestination\tdst\nplanned\tsrc/a.jpg\tdst/a.jpg\ncopi",
+            JOURNAL_HEADER
+        );
+        let state = Journal::parse(&contents).unwrap();
+        assert_eq!(state.entries.len(), 1);
+        assert_eq!(state.entries[0].state, FileState::Planned);
+    }
+
+    #[test]
+    fn records_of_files_never_planned_are_rejected() {
+        let contents = format!(
+            "{}\nsource\tsrc\ndestination\tdst\ncopied\tsrc/a.jpg\tdst/a.jpg\n",
+            JOURNAL_HEADER
+        );
+        let error = Journal::parse(&contents).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn paths_that_are_not_utf8_are_kept_exactly() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"src/caf\xE9 %41.jpg"));
+        let encoded = encode_path(path);
+        assert_eq!(encoded, "src/caf%E9 %2541.jpg");
+        assert_eq!(decode_path(&encoded), path);
+    }
+}
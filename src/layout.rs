@@ -0,0 +1,289 @@
+//! Destination layouts.
+//!
+//! A layout decides which folder of the destination each copy goes to. By
+//! default the source folders are recreated; a flat layout puts every copy
+//! in the destination folder itself, and a date layout files copies in
+//! folders named after their capture time, such as `2024/05/17`.
+//! Together with an optional rename template and a folder per camera it
+//! makes up the [`CopyPlacement`] of an import.
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::metadata::{CaptureTime, MediaMetadata};
+use crate::rename::{safe_name, NameFields, RenameTemplate};
+
+/// Folder used by a date layout for files whose date cannot be read at all
+pub const UNKNOWN_DATE_FOLDER: &str = "unknown date";
+
+/// Default folder for files whose camera is not known, when copies are
+/// split by camera
+pub const UNKNOWN_CAMERA_FOLDER: &str = "unknown camera";
+
+/// Where copies go in the destination folder
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DestinationLayout {
+    /// Recreate the source folders
+    #[default]
+    Mirror,
+    /// Every copy in the destination folder itself
+    Flat,
+    /// Folders named by a date pattern, with `YYYY`, `MM` and `DD` standing
+    /// for the year, month and day the file was taken
+    Dated(String),
+}
+
+impl DestinationLayout {
+    /// Parse a layout as given on the command line: `mirror`, `flat` or a
+    /// date pattern such as `YYYY/MM/DD`
+    pub fn from_spec(spec: &str) -> Result<DestinationLayout, String> {
+        match spec {
+            "mirror" => return Ok(DestinationLayout::Mirror),
+            "flat" => return Ok(DestinationLayout::Flat),
+            _ => {}
+        }
+
+        if !["YYYY", "MM", "DD"]
+            .iter()
+            .any(|token| spec.contains(token))
+        {
+            return Err(format!(
+                "Unknown layout '{}': use 'mirror', 'flat' or a date pattern such as 'YYYY/MM/DD'",
+                spec
+            ));
+        }
+        if !stays_inside(Path::new(spec)) {
+            return Err(format!(
+                "Layout '{}' must be a relative path without '..'",
+                spec
+            ));
+        }
+        Ok(DestinationLayout::Dated(spec.to_string()))
+    }
+
+    /// Whether the layout uses the metadata of the files
+    pub fn uses_metadata(&self) -> bool {
+        matches!(self, DestinationLayout::Dated(_))
+    }
+
+    /// The folder a media file goes to, relative to the destination folder.
+    /// The original is at `relative_path` within the source folder.
+    pub fn relative_folder(&self, relative_path: &Path, metadata: &MediaMetadata) -> PathBuf {
+        match self {
+            DestinationLayout::Mirror => relative_path.parent().unwrap_or(Path::new("")).into(),
+            DestinationLayout::Flat => PathBuf::new(),
+            DestinationLayout::Dated(pattern) => match &metadata.capture_time {
+                Some(time) => format_date_pattern(pattern, time).into(),
+                None => UNKNOWN_DATE_FOLDER.into(),
+            },
+        }
+    }
+}
+
+/// Where copies go in the destination folder and what they are called
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopyPlacement {
+    /// The folders copies go to
+    pub layout: DestinationLayout,
+    /// The names of the copies, or `None` to keep the original names
+    pub rename: Option<RenameTemplate>,
+    /// Put the folders of the layout in a folder per camera, named after
+    /// its make, model and serial number
+    pub by_camera: bool,
+    /// Folder for files whose camera is not known, with `by_camera`
+    pub camera_fallback: String,
+}
+
+impl Default for CopyPlacement {
+    fn default() -> Self {
+        CopyPlacement {
+            layout: DestinationLayout::default(),
+            rename: None,
+            by_camera: false,
+            camera_fallback: UNKNOWN_CAMERA_FOLDER.to_string(),
+        }
+    }
+}
+
+impl CopyPlacement {
+    /// Set the folder for files of no known camera, as given on the command
+    /// line
+    pub fn set_camera_fallback(&mut self, folder: &str) -> Result<(), String> {
+        if !stays_inside(Path::new(folder)) {
+            return Err(format!(
+                "Camera fallback '{}' must be a relative path without '..'",
+                folder
+            ));
+        }
+        self.camera_fallback = folder.to_string();
+        Ok(())
+    }
+
+    /// Whether placing a file needs its metadata
+    pub fn uses_metadata(&self) -> bool {
+        self.by_camera
+            || self.layout.uses_metadata()
+            || self
+                .rename
+                .as_ref()
+                .is_some_and(|template| template.uses_metadata())
+    }
+
+    /// Whether placing a file needs the hash of its contents
+    pub fn uses_hash(&self) -> bool {
+        self.rename
+            .as_ref()
+            .is_some_and(|template| template.uses_hash())
+    }
+
+    /// Whether files are numbered in the order they are planned
+    pub fn uses_sequence(&self) -> bool {
+        self.rename
+            .as_ref()
+            .is_some_and(|template| template.uses_sequence())
+    }
+
+    /// Where a media file goes, relative to the destination folder, before
+    /// any conflict rename. Names taken from metadata are made safe, and a
+    /// result that would still leave the destination folder is an error.
+    pub fn relative_destination(&self, fields: &NameFields) -> io::Result<PathBuf> {
+        let mut folder = self
+            .layout
+            .relative_folder(fields.relative_path, fields.metadata);
+        if self.by_camera {
+            let camera = fields
+                .metadata
+                .camera_name()
+                .and_then(|camera| safe_name(&camera))
+                .unwrap_or_else(|| self.camera_fallback.clone());
+            folder = Path::new(&camera).join(folder);
+        }
+        let destination = match &self.rename {
+            Some(template) => folder.join(template.render(fields)),
+            None => folder.join(fields.relative_path.file_name().unwrap_or_default()),
+        };
+
+        if !stays_inside(&destination) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "The copy of '{}' would be placed outside the destination folder, at '{}'",
+                    fields.relative_path.display(),
+                    destination.display()
+                ),
+            ));
+        }
+        Ok(destination)
+    }
+}
+
+/// Whether a path is relative and stays inside the folder it is joined to
+fn stays_inside(path: &Path) -> bool {
+    !path.as_os_str().is_empty()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Fill in the date tokens of a pattern
+fn format_date_pattern(pattern: &str, time: &CaptureTime) -> String {
+    pattern
+        .replace("YYYY", &format!("{:04}", time.year))
+        .replace("MM", &format!("{:02}", time.month))
+        .replace("DD", &format!("{:02}", time.day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(capture_time: Option<&str>) -> MediaMetadata {
+        MediaMetadata {
+            capture_time: capture_time.and_then(CaptureTime::parse),
+            ..MediaMetadata::default()
+        }
+    }
+
+    #[test]
+    fn layouts_from_the_command_line() {
+        assert_eq!(
+            DestinationLayout::from_spec("flat"),
+            Ok(DestinationLayout::Flat)
+        );
+        assert_eq!(
+            DestinationLayout::from_spec("YYYY/YYYY-MM-DD"),
+            Ok(DestinationLayout::Dated("YYYY/YYYY-MM-DD".to_string()))
+        );
+        assert!(DestinationLayout::from_spec("by-date").is_err());
+        assert!(DestinationLayout::from_spec("../YYYY").is_err());
+        assert!(DestinationLayout::from_spec("/YYYY").is_err());
+    }
+
+    #[test]
+    fn dated_folders() {
+        let layout = DestinationLayout::from_spec("YYYY/MM/DD").unwrap();
+        let path = Path::new("DCIM/100/a.jpg");
+        assert_eq!(
+            layout.relative_folder(path, &metadata(Some("2024:05:07 10:00:00"))),
+            Path::new("2024/05/07")
+        );
+        assert_eq!(
+            layout.relative_folder(path, &metadata(None)),
+            Path::new(UNKNOWN_DATE_FOLDER)
+        );
+        assert_eq!(
+            DestinationLayout::Mirror.relative_folder(path, &metadata(None)),
+            Path::new("DCIM/100")
+        );
+        assert_eq!(
+            DestinationLayout::Flat.relative_folder(path, &metadata(None)),
+            Path::new("")
+        );
+    }
+
+    #[test]
+    fn folders_per_camera_with_a_fallback() {
+        let camera = |make: &str, model: &str| MediaMetadata {
+            camera_make: Some(make.to_string()),
+            camera_model: Some(model.to_string()),
+            camera_serial: Some("0123".to_string()),
+            ..metadata(Some("2024:05:07 10:00:00"))
+        };
+        let destination = |placement: &CopyPlacement, metadata: &MediaMetadata| {
+            placement.relative_destination(&NameFields {
+                relative_path: Path::new("DCIM/a.jpg"),
+                metadata,
+                hash: None,
+                sequence: 1,
+            })
+        };
+        let mut placement = CopyPlacement {
+            layout: DestinationLayout::from_spec("YYYY").unwrap(),
+            by_camera: true,
+            ..CopyPlacement::default()
+        };
+        assert!(placement.uses_metadata());
+
+        assert_eq!(
+            destination(&placement, &camera("Canon", "EOS R5")).unwrap(),
+            Path::new("Canon EOS R5 (0123)/2024/a.jpg")
+        );
+        assert_eq!(
+            destination(&placement, &camera("Odd", "A/B: C")).unwrap(),
+            Path::new("Odd A_B_ C (0123)/2024/a.jpg")
+        );
+        assert_eq!(
+            destination(&placement, &metadata(None)).unwrap(),
+            Path::new("unknown camera/unknown date/a.jpg")
+        );
+
+        placement.set_camera_fallback("misc/cameras").unwrap();
+        assert_eq!(
+            destination(&placement, &metadata(None)).unwrap(),
+            Path::new("misc/cameras/unknown date/a.jpg")
+        );
+        assert!(placement.set_camera_fallback("../cameras").is_err());
+        assert!(placement.set_camera_fallback("").is_err());
+        assert_eq!(placement.camera_fallback, "misc/cameras");
+    }
+}
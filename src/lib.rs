@@ -1,5 +1,29 @@
 // Module declarations
+pub mod atoms;
+pub mod cancel;
+pub mod cli;
+pub mod config;
+#[cfg(windows)]
 pub mod dialogs;
 pub mod directory;
+pub mod dry_run;
+pub mod exif;
 pub mod file_ops;
+pub mod filter;
+pub mod interaction;
+pub mod journal;
+pub mod layout;
+pub mod live_photo;
 pub mod media;
+pub mod metadata;
+pub mod pipeline;
+pub mod progress;
+pub mod rename;
+pub mod report;
+pub mod sniff;
+pub mod terminal;
+#[cfg(test)]
+pub mod test_support;
+pub mod verify;
+pub mod video;
+pub mod walk;
@@ -0,0 +1,450 @@
+//! Apple Live Photo and motion photo recognition.
+//!
+//! A Live Photo is a still image (HEIC or JPEG) and a short QuickTime movie
+//! taken together. Both carry the same content identifier: the still in its
+//! Apple MakerNote, the movie in its QuickTime metadata under
+//! `com.apple.quicktime.content.identifier`. This module reads that
+//! identifier so the two halves can be matched even when their names differ.
+//!
+//! Google and Samsung motion photos are a single JPEG file with the video
+//! stored after the still image. Google cameras give the length of the video
+//! in the XMP metadata of the still, Samsung cameras put it in a block named
+//! `MotionPhoto_Data` of a trailer at the end of the file. This module finds
+//! where the still ends, so it can be copied without its video.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::atoms::{quicktime_metadata_value, read_top_level_atom};
+
+/// Start of the MakerNote written by iPhones and iPads
+const MAKER_NOTE_SIGNATURE: &[u8] = b"Apple iOS\0";
+
+/// MakerNote tag holding the content identifier of a still
+const CONTENT_IDENTIFIER_TAG: u16 = 0x0011;
+
+/// QuickTime metadata key holding the content identifier of a movie
+const CONTENT_IDENTIFIER_KEY: &[u8] = b"com.apple.quicktime.content.identifier";
+
+/// Number of bytes of a still searched for the MakerNote. The EXIF block of
+/// a JPEG, and in practice of a HEIC, is well within this.
+const STILL_HEADER_LENGTH: u64 = 512 * 1024;
+
+/// Largest `moov` atom read from a movie. Live Photo movies are a few seconds
+/// long and their `moov` is far smaller.
+const MAX_MOVIE_HEADER_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// Name of the block of a Samsung trailer that holds the video
+const SAMSUNG_VIDEO_BLOCK: &[u8] = b"MotionPhoto_Data";
+
+/// Largest Samsung trailer directory read. It has a few entries of twelve
+/// bytes each.
+const MAX_SAMSUNG_DIRECTORY_LENGTH: u32 = 64 * 1024;
+
+/// Whether a (lowercase) extension is that of a Live Photo still
+pub fn is_live_photo_still(extension: &str) -> bool {
+    matches!(extension, "heic" | "heif" | "jpg" | "jpeg")
+}
+
+/// Whether a (lowercase) extension is that of a Live Photo movie
+pub fn is_live_photo_movie(extension: &str) -> bool {
+    matches!(extension, "mov" | "qt")
+}
+
+/// Read the Live Photo content identifier of a still or movie, if it has one
+pub fn read_content_identifier(path: &Path) -> io::Result<Option<String>> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    if is_live_photo_still(&extension) {
+        let mut header = Vec::new();
+        File::open(path)?
+            .take(STILL_HEADER_LENGTH)
+            .read_to_end(&mut header)?;
+        Ok(still_content_identifier(&header))
+    } else if is_live_photo_movie(&extension) {
+        movie_content_identifier(&mut File::open(path)?)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Find the content identifier in the Apple MakerNote of a still
+fn still_content_identifier(header: &[u8]) -> Option<String> {
+    let start = find(header, MAKER_NOTE_SIGNATURE)?;
+    let maker_note = &header[start..];
+
+    // The signature is followed by a version and the byte order, and
+    // offsets inside the MakerNote are relative to its start
+    let big_endian = match maker_note.get(12..14)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| -> Option<u16> {
+        let bytes = maker_note.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| -> Option<u32> {
+        let bytes = maker_note.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let entry_count = u16_at(14)? as usize;
+    for index in 0..entry_count {
+        let entry = 16 + index * 12;
+        if u16_at(entry)? != CONTENT_IDENTIFIER_TAG {
+            continue;
+        }
+        // An ASCII string, stored inline only if it fits in four bytes
+        let length = u32_at(entry + 4)? as usize;
+        let value = if length <= 4 {
+            maker_note.get(entry + 8..entry + 8 + length)?
+        } else {
+            let offset = u32_at(entry + 8)? as usize;
+            maker_note.get(offset..offset.checked_add(length)?)?
+        };
+        return identifier_from_bytes(value);
+    }
+
+    None
+}
+
+/// Find the content identifier in the QuickTime metadata of a movie
+fn movie_content_identifier<R: Read + Seek>(reader: &mut R) -> io::Result<Option<String>> {
+    let moov = read_top_level_atom(reader, b"moov", MAX_MOVIE_HEADER_LENGTH)?;
+    Ok(moov.and_then(|moov| {
+        quicktime_metadata_value(&moov, CONTENT_IDENTIFIER_KEY).and_then(identifier_from_bytes)
+    }))
+}
+
+/// The length of the still image at the start of a Google or Samsung motion
+/// photo, whose video follows it in the same file. `None` for any other file.
+pub fn motion_photo_still_length(path: &Path) -> io::Result<Option<u64>> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if !matches!(extension.as_str(), "jpg" | "jpeg") {
+        return Ok(None);
+    }
+
+    let mut file = File::open(path)?;
+    let file_length = file.metadata()?.len();
+    let mut header = Vec::new();
+    (&mut file)
+        .take(STILL_HEADER_LENGTH)
+        .read_to_end(&mut header)?;
+
+    if let Some(still_length) = google_still_length(&header, file_length) {
+        if starts_with_movie(&mut file, still_length)? {
+            return Ok(Some(still_length));
+        }
+    }
+    match samsung_still_length(&mut file, file_length) {
+        // Offsets past the end of the file are a trailer of some other kind
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        result => result,
+    }
+}
+
+/// Whether `copy` holds only the still of the motion photo at `original`,
+/// without its video
+pub fn is_motion_photo_still(original: &Path, copy: &Path) -> bool {
+    let (Ok(original_metadata), Ok(copy_metadata)) = (fs::metadata(original), fs::metadata(copy))
+    else {
+        return false;
+    };
+    copy_metadata.len() < original_metadata.len()
+        && motion_photo_still_length(original)
+            .is_ok_and(|still_length| still_length == Some(copy_metadata.len()))
+}
+
+/// Where the video of a Google motion photo starts, from the XMP metadata
+/// in the header of the still. Older files give the length of the video as
+/// `MicroVideoOffset`; newer ones list it as the `MotionPhoto` item of a
+/// container, which is the last thing in the file.
+fn google_still_length(header: &[u8], file_length: u64) -> Option<u64> {
+    let start = find(header, b"<x:xmpmeta")?;
+    let end = find(&header[start..], b"</x:xmpmeta>").map_or(header.len(), |end| start + end);
+    let xmp = std::str::from_utf8(&header[start..end]).ok()?;
+
+    let video_length = match xmp_value(xmp, "GCamera:MicroVideoOffset") {
+        Some(offset) => offset,
+        None => {
+            let item = xmp
+                .split('<')
+                .find(|element| xmp_value(element, ":Semantic") == Some("MotionPhoto"))?;
+            xmp_value(item, ":Length")?
+        }
+    };
+    let video_length: u64 = video_length.trim().parse().ok()?;
+    (video_length > 0)
+        .then(|| file_length.checked_sub(video_length))
+        .flatten()
+}
+
+/// The value of an XMP property written as an attribute, `name="value"`,
+/// or as an element, `<name>value</name>`
+fn xmp_value<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+    let rest = &xmp[xmp.find(name)? + name.len()..];
+    let rest = rest
+        .strip_prefix("=\"")
+        .or_else(|| rest.strip_prefix('>'))?;
+    Some(&rest[..rest.find(['"', '<'])?])
+}
+
+/// Where the blocks of a Samsung trailer start, if it has one holding a
+/// video. The trailer ends with its length and `SEFT`; it starts with
+/// `SEFH` and a directory whose entries give how far before it each block
+/// starts. A block starts with its name, after four bytes and its length.
+fn samsung_still_length<R: Read + Seek>(
+    reader: &mut R,
+    file_length: u64,
+) -> io::Result<Option<u64>> {
+    let Some(footer_start) = file_length.checked_sub(8) else {
+        return Ok(None);
+    };
+    let mut footer = [0u8; 8];
+    reader.seek(SeekFrom::Start(footer_start))?;
+    reader.read_exact(&mut footer)?;
+    let directory_length = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
+    if &footer[4..] != b"SEFT" || directory_length > MAX_SAMSUNG_DIRECTORY_LENGTH {
+        return Ok(None);
+    }
+    let Some(directory_start) = footer_start.checked_sub(directory_length as u64) else {
+        return Ok(None);
+    };
+
+    let mut directory = vec![0u8; directory_length as usize];
+    reader.seek(SeekFrom::Start(directory_start))?;
+    reader.read_exact(&mut directory)?;
+    let u32_at = |data: &[u8], offset: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(
+            data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+    if directory.get(..4) != Some(b"SEFH") {
+        return Ok(None);
+    }
+    let Some(entry_count) = u32_at(&directory, 8) else {
+        return Ok(None);
+    };
+
+    let mut first_block = directory_start;
+    let mut has_video = false;
+    for index in 0..entry_count as usize {
+        let entry = 12 + index * 12;
+        let Some(distance) = u32_at(&directory, entry + 4) else {
+            return Ok(None);
+        };
+        let Some(block_start) = directory_start.checked_sub(distance as u64) else {
+            return Ok(None);
+        };
+        first_block = first_block.min(block_start);
+
+        let mut block_header = [0u8; 8];
+        reader.seek(SeekFrom::Start(block_start))?;
+        reader.read_exact(&mut block_header)?;
+        let name_length = u32_at(&block_header, 4).unwrap_or_default() as usize;
+        if name_length != SAMSUNG_VIDEO_BLOCK.len() {
+            continue;
+        }
+        let mut name = vec![0u8; name_length];
+        reader.read_exact(&mut name)?;
+        if name == SAMSUNG_VIDEO_BLOCK {
+            let video_start = block_start + 8 + name_length as u64;
+            has_video = starts_with_movie(reader, video_start)?;
+        }
+    }
+
+    Ok(has_video.then_some(first_block))
+}
+
+/// Whether an ISO media movie starts at `offset`
+fn starts_with_movie<R: Read + Seek>(reader: &mut R, offset: u64) -> io::Result<bool> {
+    let mut header = [0u8; 8];
+    reader.seek(SeekFrom::Start(offset))?;
+    match reader.read_exact(&mut header) {
+        Ok(()) => Ok(&header[4..] == b"ftyp"),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// A content identifier stored as text, without any trailing NULs
+fn identifier_from_bytes(value: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(value).ok()?;
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atoms::find_atom;
+    use crate::test_support::{
+        atom, jpeg_with_xmp, live_photo_movie, live_photo_still, mp4_movie, TempDir,
+    };
+    use std::io::Cursor;
+
+    /// A Samsung trailer with a block holding `video`
+    fn samsung_trailer(video: &[u8]) -> Vec<u8> {
+        let mut block = b"\0\0\x30\x0a".to_vec();
+        block.extend_from_slice(&16u32.to_le_bytes());
+        block.extend_from_slice(b"MotionPhoto_Data");
+        block.extend_from_slice(video);
+
+        let mut directory = b"SEFH".to_vec();
+        directory.extend_from_slice(&106u32.to_le_bytes());
+        directory.extend_from_slice(&1u32.to_le_bytes());
+        directory.extend_from_slice(b"\0\0\x30\x0a");
+        directory.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        directory.extend_from_slice(&(block.len() as u32).to_le_bytes());
+
+        let footer = [&(directory.len() as u32).to_le_bytes()[..], b"SEFT"].concat();
+        [block, directory, footer].concat()
+    }
+
+    #[test]
+    fn still_identifier_in_either_byte_order() {
+        let identifier = "2E4D6A3B-0C5F-4A7E-9B1D-8F2C3E4A5B6C";
+        assert_eq!(
+            still_content_identifier(&live_photo_still(identifier, true)).as_deref(),
+            Some(identifier)
+        );
+        assert_eq!(
+            still_content_identifier(&live_photo_still(identifier, false)).as_deref(),
+            Some(identifier)
+        );
+        // Short enough to be stored in the entry itself
+        assert_eq!(
+            still_content_identifier(&live_photo_still("AB", true)).as_deref(),
+            Some("AB")
+        );
+
+        let still = live_photo_still(identifier, true);
+        assert_eq!(still_content_identifier(&still[..still.len() - 20]), None);
+        assert_eq!(
+            still_content_identifier(b"\xFF\xD8\xFF\xE0 no maker note"),
+            None
+        );
+    }
+
+    #[test]
+    fn movie_identifier_in_quicktime_metadata() {
+        let movie = live_photo_movie("ABC-123");
+        assert_eq!(
+            movie_content_identifier(&mut Cursor::new(&movie)).unwrap(),
+            Some("ABC-123".to_string())
+        );
+
+        // Also found in the metadata of the user data atom
+        let meta = find_atom(&movie, b"moov").unwrap();
+        let in_user_data = [
+            atom(b"ftyp", b"qt  \0\0\0\0qt  "),
+            atom(b"moov", &atom(b"udta", meta)),
+        ]
+        .concat();
+        assert_eq!(
+            movie_content_identifier(&mut Cursor::new(&in_user_data)).unwrap(),
+            Some("ABC-123".to_string())
+        );
+
+        let without = [
+            atom(b"ftyp", b"qt  "),
+            atom(b"moov", &atom(b"mvhd", &[0; 100])),
+        ]
+        .concat();
+        assert_eq!(
+            movie_content_identifier(&mut Cursor::new(&without)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn google_motion_photo_still_ends_where_the_video_starts() {
+        let dir = TempDir::new();
+        let video = mp4_movie();
+        let older = jpeg_with_xmp(&format!(
+            "<rdf:Description GCamera:MicroVideo=\"1\" GCamera:MicroVideoOffset=\"{}\"/>",
+            video.len()
+        ));
+        let newer = jpeg_with_xmp(&format!(
+            "<Container:Directory><rdf:Seq>\
+             <rdf:li><Container:Item Item:Mime=\"image/jpeg\" Item:Semantic=\"Primary\"/></rdf:li>\
+             <rdf:li><Container:Item Item:Mime=\"video/mp4\" Item:Semantic=\"MotionPhoto\" \
+             Item:Length=\"{}\"/></rdf:li></rdf:Seq></Container:Directory>",
+            video.len()
+        ));
+
+        for (name, still) in [("older.jpg", older), ("newer.JPG", newer)] {
+            let path = dir.write(name, [&still[..], &video].concat());
+            assert_eq!(
+                motion_photo_still_length(&path).unwrap(),
+                Some(still.len() as u64),
+                "{}",
+                name
+            );
+        }
+
+        // The length must point at the start of a movie
+        let still = jpeg_with_xmp("<rdf:Description GCamera:MicroVideoOffset=\"5\"/>");
+        let wrong = dir.write("wrong.jpg", [&still[..], &video].concat());
+        assert_eq!(motion_photo_still_length(&wrong).unwrap(), None);
+    }
+
+    #[test]
+    fn samsung_motion_photo_still_ends_where_the_trailer_starts() {
+        let dir = TempDir::new();
+        let still = jpeg_with_xmp("");
+        let path = dir.write(
+            "samsung.jpg",
+            [still.clone(), samsung_trailer(&mp4_movie())].concat(),
+        );
+        assert_eq!(
+            motion_photo_still_length(&path).unwrap(),
+            Some(still.len() as u64)
+        );
+
+        // Without a movie in its video block it is some other trailer
+        let other = [still.clone(), samsung_trailer(b"not a movie")].concat();
+        let other = dir.write("other.jpg", other);
+        assert_eq!(motion_photo_still_length(&other).unwrap(), None);
+    }
+
+    #[test]
+    fn other_files_are_not_motion_photos() {
+        let dir = TempDir::new();
+        let video = mp4_movie();
+        let xmp = format!(
+            "<rdf:Description GCamera:MicroVideoOffset=\"{}\"/>",
+            video.len()
+        );
+        let contents = [jpeg_with_xmp(&xmp), video].concat();
+
+        let plain = dir.write("plain.jpg", jpeg_with_xmp(""));
+        let other_format = dir.write("image.heic", &contents);
+        let short = dir.write("short.jpg", "SEFT");
+        for path in [plain, other_format, short] {
+            assert_eq!(motion_photo_still_length(&path).unwrap(), None);
+        }
+    }
+}
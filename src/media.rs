@@ -3,85 +3,390 @@
 //! This module provides functions for identifying media files (images and videos)
 //! and recursively collecting them from directory structures.
 
-use std::fs;
+use std::collections::{BTreeMap, HashMap};
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-pub fn collect_media_files(
-    current_dir: &PathBuf,
-    source_root: &PathBuf,
-    media_files: &mut Vec<PathBuf>,
-    exclude_path: Option<&PathBuf>,
-) -> io::Result<()> {
-    let entries = match fs::read_dir(current_dir) {
-        Ok(entries) => entries,
-        Err(e) => {
-            eprintln!(
-                "Warning: Cannot access directory '{}': {}",
-                current_dir.display(),
-                e
-            );
-            return Ok(()); // Continue processing other directories
+use crate::cancel::CancellationToken;
+use crate::filter::PathFilter;
+use crate::live_photo::{is_live_photo_movie, is_live_photo_still, read_content_identifier};
+use crate::progress::{ProgressEvent, ProgressListener};
+use crate::sniff::{has_known_signature, sniff_file};
+use crate::walk::{walk, SymlinkPolicy, WalkOptions, WalkedFile, WalkedFolder};
+
+/// The broad kind of a media file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MediaKind {
+    /// Photos and other raster images (JPEG, PNG, HEIC, TIFF, ...)
+    Image,
+    /// Camera RAW images (CR2, NEF, ARW, DNG, ...)
+    Raw,
+    /// Consumer video (MP4, MOV, MKV, ...)
+    Video,
+    /// Professional and cinema video (MXF, R3D, BRAW, ...)
+    ProVideo,
+    /// Vector images and icons (SVG, ICO)
+    Vector,
+}
+
+impl MediaKind {
+    /// Every kind, in the order they are listed in summaries
+    pub const ALL: [MediaKind; 5] = [
+        MediaKind::Image,
+        MediaKind::Raw,
+        MediaKind::Video,
+        MediaKind::ProVideo,
+        MediaKind::Vector,
+    ];
+
+    /// Name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            MediaKind::Image => "image",
+            MediaKind::Raw => "raw",
+            MediaKind::Video => "video",
+            MediaKind::ProVideo => "pro-video",
+            MediaKind::Vector => "vector",
         }
-    };
+    }
 
-    for entry in entries {
-        let entry = match entry {
-            Ok(entry) => entry,
-            Err(e) => {
-                eprintln!(
-                    "Warning: Cannot read directory entry in '{}': {}",
-                    current_dir.display(),
-                    e
-                );
-                continue; // Skip this entry and continue with others
-            }
+    /// Parse a kind name as given on the command line
+    pub fn from_name(name: &str) -> Option<MediaKind> {
+        MediaKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// How a number of files of this kind is described in summaries
+    pub fn describe_count(&self, count: usize) -> String {
+        let (singular, plural) = match self {
+            MediaKind::Image => ("image", "images"),
+            MediaKind::Raw => ("RAW image", "RAW images"),
+            MediaKind::Video => ("video", "videos"),
+            MediaKind::ProVideo => ("pro video", "pro videos"),
+            MediaKind::Vector => ("vector image or icon", "vector images and icons"),
         };
-        let path = entry.path();
+        format!("{} {}", count, if count == 1 { singular } else { plural })
+    }
+}
 
-        if path.is_dir() {
-            // Skip the destination directory if it's within the source to prevent infinite recursion
-            if let Some(exclude) = exclude_path {
-                if let (Ok(canonical_path), Ok(canonical_exclude)) =
-                    (path.canonicalize(), exclude.canonicalize())
-                {
-                    if canonical_path == canonical_exclude {
-                        println!("Skipping destination directory: {}", path.display());
-                        continue;
-                    }
-                }
-            }
+/// Which kinds of media file a scan collects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KindFilter {
+    allowed: Vec<MediaKind>,
+}
 
-            // Recursively process subdirectories
-            if let Err(e) = collect_media_files(&path, source_root, media_files, exclude_path) {
-                eprintln!(
-                    "Warning: Cannot access subdirectory '{}': {}",
-                    path.display(),
-                    e
-                );
-                // Continue processing other directories
-            }
-        } else if path.is_file() {
-            if let Some(extension) = path.extension() {
-                let ext = extension.to_string_lossy().to_lowercase();
-
-                // Check if it's an image or video file
-                if is_media_file(&ext) {
-                    // Calculate relative path from source root
-                    let relative_path = path
-                        .strip_prefix(source_root)
-                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-                    media_files.push(relative_path.to_path_buf());
-                }
-            }
+impl KindFilter {
+    /// Collect every kind
+    pub fn all() -> KindFilter {
+        KindFilter {
+            allowed: MediaKind::ALL.to_vec(),
         }
     }
 
-    Ok(())
+    /// Collect only the given kinds
+    pub fn only(kinds: &[MediaKind]) -> KindFilter {
+        KindFilter {
+            allowed: MediaKind::ALL
+                .into_iter()
+                .filter(|kind| kinds.contains(kind))
+                .collect(),
+        }
+    }
+
+    /// Stop collecting the given kinds
+    pub fn excluding(&self, kinds: &[MediaKind]) -> KindFilter {
+        KindFilter {
+            allowed: self
+                .allowed
+                .iter()
+                .copied()
+                .filter(|kind| !kinds.contains(kind))
+                .collect(),
+        }
+    }
+
+    pub fn allows(&self, kind: MediaKind) -> bool {
+        self.allowed.contains(&kind)
+    }
+
+    /// The kinds that are collected
+    pub fn kinds(&self) -> &[MediaKind] {
+        &self.allowed
+    }
+}
+
+impl Default for KindFilter {
+    fn default() -> KindFilter {
+        KindFilter::all()
+    }
+}
+
+/// A media file found by a scan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaFile {
+    /// Path relative to the source folder
+    pub path: PathBuf,
+    pub kind: MediaKind,
+    /// Size in bytes including its sidecars, or zero if it could not be read
+    pub size: u64,
+    /// Sidecar files that travel with this one, relative to the source folder
+    pub sidecars: Vec<PathBuf>,
+    /// Other media files kept together with this one, such as the JPEG of a
+    /// RAW+JPEG pair
+    pub companions: Vec<MediaFile>,
+    /// Whether symbolic links among this file and its sidecars are copied as
+    /// links rather than through to what they point at
+    pub copy_links: bool,
+    /// Whether motion photos among this file and its companions are copied
+    /// without their video
+    pub drop_motion: bool,
+}
+
+impl MediaFile {
+    /// Size in bytes of this file and its companions, with their sidecars
+    pub fn total_size(&self) -> u64 {
+        self.with_companions().map(|file| file.size).sum()
+    }
+
+    /// Take `other` on as a companion of this file, together with its own
+    /// companions
+    fn absorb(&mut self, mut other: MediaFile) {
+        let nested = std::mem::take(&mut other.companions);
+        self.companions.push(other);
+        self.companions.extend(nested);
+    }
+
+    /// This file followed by its companions
+    pub fn with_companions(&self) -> impl Iterator<Item = &MediaFile> {
+        std::iter::once(self).chain(self.companions.iter())
+    }
+
+    /// Paths of every file that travels with this one: its sidecars, its
+    /// companions and their sidecars
+    pub fn companion_paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.sidecars.iter().chain(
+            self.companions
+                .iter()
+                .flat_map(|companion| std::iter::once(&companion.path).chain(&companion.sidecars)),
+        )
+    }
+}
+
+/// Count files per kind, listing only the kinds that occur. Companions are
+/// counted as files of their own.
+pub fn count_by_kind(media_files: &[MediaFile]) -> Vec<(MediaKind, usize)> {
+    MediaKind::ALL
+        .into_iter()
+        .map(|kind| {
+            let count = media_files
+                .iter()
+                .flat_map(MediaFile::with_companions)
+                .filter(|file| file.kind == kind)
+                .count();
+            (kind, count)
+        })
+        .filter(|(_, count)| *count > 0)
+        .collect()
 }
 
-/// Determines if a file extension represents a media file (image or video).
+/// Describe per-kind counts, e.g. "3 images, 1 RAW image, 2 videos"
+pub fn describe_kind_counts(counts: &[(MediaKind, usize)]) -> String {
+    counts
+        .iter()
+        .map(|(kind, count)| kind.describe_count(*count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// How the scan decides whether a file is a media file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DetectionMode {
+    /// By file extension only, without opening the file
+    #[default]
+    Extension,
+    /// By the magic bytes at the start of the file, ignoring the extension
+    Content,
+    /// By content, falling back to the extension for formats whose content
+    /// cannot be recognised. A media extension on a file whose recognisable
+    /// content does not match is rejected.
+    Both,
+}
+
+impl DetectionMode {
+    /// Parse a mode name as given on the command line
+    pub fn from_name(name: &str) -> Option<DetectionMode> {
+        match name {
+            "extension" => Some(DetectionMode::Extension),
+            "content" => Some(DetectionMode::Content),
+            "both" => Some(DetectionMode::Both),
+            _ => None,
+        }
+    }
+}
+
+/// The extensions a scan recognises: the built-in table with any additions
+/// and removals from the configuration applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionTable {
+    kinds: BTreeMap<String, MediaKind>,
+}
+
+impl ExtensionTable {
+    /// The built-in table
+    pub fn builtin() -> ExtensionTable {
+        let kinds = BUILTIN_EXTENSIONS
+            .iter()
+            .flat_map(|(extensions, kind)| {
+                extensions
+                    .iter()
+                    .map(move |extension| (extension.to_string(), *kind))
+            })
+            .collect();
+        ExtensionTable { kinds }
+    }
+
+    /// Recognise an extension as the given kind, replacing any earlier kind
+    pub fn insert(&mut self, extension: &str, kind: MediaKind) {
+        self.kinds.insert(extension.to_lowercase(), kind);
+    }
+
+    /// Stop recognising an extension
+    pub fn remove(&mut self, extension: &str) {
+        self.kinds.remove(&extension.to_lowercase());
+    }
+
+    /// The kind a lowercase extension stands for
+    pub fn kind(&self, extension: &str) -> Option<MediaKind> {
+        self.kinds.get(extension).copied()
+    }
+
+    /// Every recognised extension with its kind, in alphabetical order
+    pub fn entries(&self) -> impl Iterator<Item = (&str, MediaKind)> {
+        self.kinds
+            .iter()
+            .map(|(extension, kind)| (extension.as_str(), *kind))
+    }
+}
+
+impl Default for ExtensionTable {
+    fn default() -> ExtensionTable {
+        ExtensionTable::builtin()
+    }
+}
+
+/// What a scan does with a RAW file and an image shot alongside it, such as
+/// `IMG_0001.CR3` and `IMG_0001.JPG`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PairMode {
+    /// Import both, kept together under the same name
+    #[default]
+    Both,
+    /// Import only the RAW file
+    Raw,
+    /// Import only the JPEG (or other image)
+    Jpeg,
+}
+
+impl PairMode {
+    /// Parse a mode name as given on the command line
+    pub fn from_name(name: &str) -> Option<PairMode> {
+        match name {
+            "both" => Some(PairMode::Both),
+            "raw" => Some(PairMode::Raw),
+            "jpeg" => Some(PairMode::Jpeg),
+            _ => None,
+        }
+    }
+}
+
+/// Settings that decide which files a scan collects
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanOptions {
+    pub detection: DetectionMode,
+    pub kinds: KindFilter,
+    pub extensions: ExtensionTable,
+    pub pairs: PairMode,
+    /// Leave the movie of a Live Photo, and the video of a motion photo, out
+    pub drop_motion: bool,
+    /// Include and exclude patterns for the walk
+    pub filter: PathFilter,
+    /// What the walk does with symbolic links
+    pub symlinks: SymlinkPolicy,
+}
+
+impl ScanOptions {
+    /// The settings for walking the source, leaving out `exclude_path`
+    pub fn walk_options<'a>(&'a self, exclude_path: Option<&'a Path>) -> WalkOptions<'a> {
+        WalkOptions {
+            symlinks: self.symlinks,
+            exclude_path,
+            filter: Some(&self.filter),
+            quiet: false,
+        }
+    }
+}
+
+/// Work out which kind of media file the file at `path` is, if any.
+///
+/// Errors are only returned when the file has to be opened and cannot be.
+pub fn detect_media_kind(path: &Path, options: &ScanOptions) -> io::Result<Option<MediaKind>> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    let by_extension = extension
+        .as_deref()
+        .and_then(|extension| options.extensions.kind(extension));
+
+    let by_content = match options.detection {
+        DetectionMode::Extension => return Ok(by_extension),
+        DetectionMode::Content | DetectionMode::Both => sniff_file(path)?,
+    };
+
+    match by_content {
+        Some(content_type) => Ok(Some(content_type.refine_kind(by_extension))),
+        // Unrecognised content only counts when the extension names a
+        // format the sniffer cannot check
+        None if options.detection == DetectionMode::Both
+            && !extension.as_deref().is_some_and(has_known_signature) =>
+        {
+            Ok(by_extension)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Collect the paths, relative to `source_root`, of the media files a scan
+/// finds, without sizes or grouping. Stops with an
+/// [`io::ErrorKind::Interrupted`] error once `cancel` is cancelled.
+pub fn collect_media_files(
+    source_root: &Path,
+    exclude_path: Option<&Path>,
+    options: &ScanOptions,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+) -> io::Result<Vec<PathBuf>> {
+    let folders = walk(
+        source_root,
+        options.walk_options(exclude_path),
+        cancel,
+        progress,
+        |folder| {
+            Ok(folder
+                .files
+                .into_iter()
+                .filter(|file| {
+                    options.filter.is_included(&file.relative_path)
+                        && wanted_media_kind(&file.path, options, progress).is_some()
+                })
+                .map(|file| file.relative_path)
+                .collect::<Vec<_>>())
+        },
+    )?;
+
+    Ok(folders.into_iter().flatten().collect())
+}
+
+/// Determines which kind of media file, if any, an extension represents.
 ///
 /// This function supports a comprehensive list of media file formats including:
 /// - Standard image formats (JPEG, PNG, GIF, BMP, TIFF, WebP, HEIC, etc.)
@@ -99,220 +404,671 @@ pub fn collect_media_files(
 /// - Adobe DNG (Digital Negative)
 /// - Professional video formats (R3D, BRAW, ProRes, etc.)
 /// - Standard video formats (MP4, MOV, AVI, MKV, etc.)
-pub fn is_media_file(extension: &str) -> bool {
-    match extension {
-        // Standard image formats
-        "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tiff" | "tif" | "webp" | "svg" | "ico"
-        | "heic" | "heif" => true,
-
-        // RAW formats (comprehensive list for major camera manufacturers)
-        // Generic RAW and Adobe DNG
-        "raw" | "dng" => true,
+pub fn media_kind(extension: &str) -> Option<MediaKind> {
+    BUILTIN_EXTENSIONS
+        .iter()
+        .find(|(extensions, _)| extensions.contains(&extension))
+        .map(|(_, kind)| *kind)
+}
 
-        // Canon RAW formats
-        "cr2" | "cr3" | "crw" | "1dx" | "1dc" => true,
+/// The built-in extension table behind [`media_kind`], in groups
+pub const BUILTIN_EXTENSIONS: &[(&[&str], MediaKind)] = &[
+    // Standard image formats
+    (
+        &[
+            "jpg", "jpeg", "png", "gif", "bmp", "tiff", "tif", "webp", "heic", "heif",
+        ],
+        MediaKind::Image,
+    ),
+    // Vector images and icons
+    (&["svg", "ico"], MediaKind::Vector),
+    // RAW formats (comprehensive list for major camera manufacturers)
+    // Generic RAW and Adobe DNG
+    (&["raw", "dng"], MediaKind::Raw),
+    // Canon RAW formats
+    (&["cr2", "cr3", "crw", "1dx", "1dc"], MediaKind::Raw),
+    // Nikon RAW formats
+    (&["nef", "nrw"], MediaKind::Raw),
+    // Sony RAW formats
+    (&["arw", "srf", "sr2"], MediaKind::Raw),
+    // Olympus RAW formats
+    (&["orf"], MediaKind::Raw),
+    // Panasonic RAW formats
+    (&["rw2"], MediaKind::Raw),
+    // Fujifilm RAW formats
+    (&["raf"], MediaKind::Raw),
+    // Pentax RAW formats
+    (&["ptx", "pef"], MediaKind::Raw),
+    // Leica RAW formats
+    (&["rwl", "dcs"], MediaKind::Raw),
+    // Sigma RAW formats
+    (&["x3f"], MediaKind::Raw),
+    // Mamiya RAW formats
+    (&["mef"], MediaKind::Raw),
+    // Phase One RAW formats
+    (&["iiq", "cap"], MediaKind::Raw),
+    // Hasselblad RAW formats
+    (&["3fr", "fff"], MediaKind::Raw),
+    // Kodak RAW formats
+    (&["dcr", "k25", "kdc"], MediaKind::Raw),
+    // Minolta/Konica Minolta RAW formats
+    (&["mrw"], MediaKind::Raw),
+    // Samsung RAW formats
+    (&["srw"], MediaKind::Raw),
+    // Epson RAW formats
+    (&["erf"], MediaKind::Raw),
+    // Other proprietary formats
+    (
+        &[
+            "bay", "bmq", "cs1", "dc2", "drf", "dsc", "dxo", "ia", "kc2", "mdc", "mos", "ndd",
+            "obm", "oti", "pxn", "qtk", "rdc", "rwz", "st4", "st5", "st6", "st7", "st8", "stx",
+        ],
+        MediaKind::Raw,
+    ),
+    // Other image formats: Kodak Photo CD, Sun raster and JPEG XR
+    (&["pcd", "ras", "wdp"], MediaKind::Image),
+    // Video formats
+    (
+        &[
+            "mp4", "avi", "mkv", "mov", "wmv", "flv", "webm", "m4v", "3gp", "3g2", "f4v", "asf",
+            "rm", "rmvb", "vob", "ogv", "drc", "mng", "qt", "yuv", "m2v", "m4p", "mpg", "mp2",
+            "mpeg", "mpe", "mpv", "m2ts", "mts", "ts", "mqv",
+        ],
+        MediaKind::Video,
+    ),
+    // Professional video formats
+    (
+        &["mxf", "r3d", "braw", "prores", "dnxhd", "cine"],
+        MediaKind::ProVideo,
+    ),
+];
 
-        // Nikon RAW formats
-        "nef" | "nrw" => true,
+/// Determines if a file extension represents a media file of any kind
+pub fn is_media_file(extension: &str) -> bool {
+    media_kind(extension).is_some()
+}
 
-        // Sony RAW formats
-        "arw" | "srf" | "sr2" => true,
+/// Extensions of sidecar files, which hold metadata, edits, thumbnails,
+/// proxies or subtitles for the media file they are named after
+pub const SIDECAR_EXTENSIONS: &[&str] = &["xmp", "thm", "aae", "lrv", "lrf", "srt", "json"];
 
-        // Olympus RAW formats
-        "orf" => true,
+/// Determines if a (lowercase) file extension represents a sidecar file
+pub fn is_sidecar_file(extension: &str) -> bool {
+    SIDECAR_EXTENSIONS.contains(&extension)
+}
 
-        // Panasonic RAW formats
-        "rw2" => true,
+/// Whether `sidecar` is named after `media`: `IMG_0001.xmp` goes with any
+/// `IMG_0001.*`, and `IMG_0001.CR3.xmp` with `IMG_0001.CR3` only
+fn is_sidecar_of(sidecar: &Path, media: &Path) -> bool {
+    let Some(sidecar_stem) = sidecar.file_stem() else {
+        return false;
+    };
+    let sidecar_stem = sidecar_stem.to_string_lossy();
+    [media.file_name(), media.file_stem()]
+        .into_iter()
+        .flatten()
+        .any(|name| name.to_string_lossy().eq_ignore_ascii_case(&sidecar_stem))
+}
 
-        // Fujifilm RAW formats
-        "raf" => true,
+/// Order in which media files sharing a stem claim a sidecar, lowest first.
+/// A sidecar named after the full file name belongs to that file; otherwise
+/// Apple edits (AAE) go to the still image, thumbnails, proxies and subtitles
+/// to the video, and XMP edits to the RAW file before the JPEG or HEIC shot
+/// alongside it.
+fn sidecar_claim_order(sidecar: &Path, media_file: &MediaFile) -> (bool, usize) {
+    let by_stem = same_stem(&media_file.path, sidecar);
+    let extension = sidecar
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let preferred: &[MediaKind] = match extension.as_str() {
+        "aae" => &[MediaKind::Image, MediaKind::Raw],
+        "thm" | "lrv" | "lrf" | "srt" => &[MediaKind::Video, MediaKind::ProVideo],
+        _ => &[MediaKind::Raw, MediaKind::Video, MediaKind::ProVideo],
+    };
+    let rank = preferred
+        .iter()
+        .position(|kind| *kind == media_file.kind)
+        .unwrap_or(preferred.len());
+    (by_stem, rank)
+}
 
-        // Pentax RAW formats
-        "ptx" | "pef" => true,
+/// Whether two files have the same name apart from the extension, ignoring
+/// case
+fn same_stem(a: &Path, b: &Path) -> bool {
+    a.file_stem().zip(b.file_stem()).is_some_and(|(a, b)| {
+        a.to_string_lossy()
+            .eq_ignore_ascii_case(&b.to_string_lossy())
+    })
+}
 
-        // Leica RAW formats
-        "rwl" | "dcs" => true,
+/// Collect media files and calculate total size in one pass, reporting each
+/// file found to `progress`. Folders are scanned in parallel. Stops with an
+/// [`io::ErrorKind::Interrupted`] error once `cancel` is cancelled.
+pub fn collect_media_files_with_size_and_progress(
+    source_root: &Path,
+    media_files: &mut Vec<MediaFile>,
+    total_size: &mut u64,
+    exclude_path: Option<&Path>,
+    options: &ScanOptions,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+) -> io::Result<()> {
+    let files_found = AtomicUsize::new(0);
+    let folders = walk(
+        source_root,
+        options.walk_options(exclude_path),
+        cancel,
+        progress,
+        |folder| {
+            Ok(collect_folder(
+                folder,
+                source_root,
+                options,
+                &files_found,
+                progress,
+            ))
+        },
+    )?;
 
-        // Sigma RAW formats
-        "x3f" => true,
+    for (found, size) in folders {
+        media_files.extend(found);
+        *total_size += size;
+    }
 
-        // Mamiya RAW formats
-        "mef" => true,
+    Ok(())
+}
 
-        // Phase One RAW formats
-        "iiq" | "cap" => true,
+/// Scan like [`collect_media_files_with_size_and_progress`], but hand the
+/// media files of each folder, with their total size, to `found` as soon as
+/// the folder has been scanned instead of collecting them. `found` is called
+/// from several threads at once, and an error from it stops the scan.
+pub fn scan_media_folders<F>(
+    source_root: &Path,
+    exclude_path: Option<&Path>,
+    options: &ScanOptions,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+    found: F,
+) -> io::Result<()>
+where
+    F: Fn(Vec<MediaFile>, u64) -> io::Result<()> + Sync,
+{
+    let files_found = AtomicUsize::new(0);
+    walk(
+        source_root,
+        options.walk_options(exclude_path),
+        cancel,
+        progress,
+        |folder| {
+            let (media_files, size) =
+                collect_folder(folder, source_root, options, &files_found, progress);
+            found(media_files, size)
+        },
+    )?;
 
-        // Hasselblad RAW formats
-        "3fr" | "fff" => true,
+    Ok(())
+}
 
-        // Kodak RAW formats
-        "dcr" | "k25" | "kdc" => true,
+/// What a scan finds, counted instead of collected
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanTotals {
+    /// Number of media files found, not counting companions. Each is one
+    /// copy of an import.
+    pub copy_count: usize,
+    /// Number of files of each kind, companions included
+    pub by_kind: BTreeMap<MediaKind, usize>,
+    pub total_size: u64,
+}
 
-        // Minolta/Konica Minolta RAW formats
-        "mrw" => true,
+impl ScanTotals {
+    /// Count the media files of one folder, and their total size
+    pub fn add(&mut self, media_files: &[MediaFile], size: u64) {
+        self.copy_count += media_files.len();
+        for (kind, count) in count_by_kind(media_files) {
+            *self.by_kind.entry(kind).or_default() += count;
+        }
+        self.total_size += size;
+    }
 
-        // Samsung RAW formats
-        "srw" => true,
+    /// Number of media files found, companions included
+    pub fn file_count(&self) -> usize {
+        self.by_kind.values().sum()
+    }
 
-        // Epson RAW formats
-        "erf" => true,
+    /// Files per kind, as listed by [`count_by_kind`]
+    pub fn kind_counts(&self) -> Vec<(MediaKind, usize)> {
+        self.by_kind
+            .iter()
+            .map(|(kind, count)| (*kind, *count))
+            .collect()
+    }
+}
 
-        // Other proprietary formats
-        "bay" | "bmq" | "cs1" | "dc2" | "drf" | "dsc" | "dxo" | "ia" | "kc2" | "mdc" | "mos"
-        | "mqv" | "ndd" | "obm" | "oti" | "pcd" | "pxn" | "qtk" | "ras" | "rdc" | "rwz" | "st4"
-        | "st5" | "st6" | "st7" | "st8" | "stx" | "wdp" => true,
+/// The media files of one folder, with their sidecars and companions
+/// attached, and their total size
+fn collect_folder(
+    folder: WalkedFolder,
+    source_root: &Path,
+    options: &ScanOptions,
+    files_found: &AtomicUsize,
+    progress: &dyn ProgressListener,
+) -> (Vec<MediaFile>, u64) {
+    let mut media_files = Vec::new();
+    let mut total_size = 0;
 
-        // Video formats
-        "mp4" | "avi" | "mkv" | "mov" | "wmv" | "flv" | "webm" | "m4v" | "3gp" | "3g2" | "f4v"
-        | "asf" | "rm" | "rmvb" | "vob" | "ogv" | "drc" | "mng" | "qt" | "yuv" | "m2v" | "m4p"
-        | "mpg" | "mp2" | "mpeg" | "mpe" | "mpv" | "m2ts" | "mts" | "ts" => true,
+    // Sidecar candidates waiting to be matched with the media files
+    let mut sidecars = Vec::new();
+    for file in folder.files {
+        if file
+            .path
+            .extension()
+            .is_some_and(|extension| is_sidecar_file(&extension.to_string_lossy().to_lowercase()))
+        {
+            sidecars.push(file);
+        } else if let Some(kind) = options
+            .filter
+            .is_included(&file.relative_path)
+            .then(|| wanted_media_kind(&file.path, options, progress))
+            .flatten()
+        {
+            push_media_file(
+                file,
+                kind,
+                options,
+                &mut media_files,
+                &mut total_size,
+                files_found,
+                progress,
+            );
+        }
+    }
 
-        // Professional video formats (removed duplicates)
-        "mxf" | "r3d" | "braw" | "prores" | "dnxhd" | "cine" => true,
+    // Sidecars are attached once the whole folder has been read, as they may
+    // be listed before the file they belong to
+    let found_here: Vec<usize> = (0..media_files.len()).collect();
+    for sidecar in sidecars {
+        let owner = found_here
+            .iter()
+            .copied()
+            .filter(|&index| is_sidecar_of(&sidecar.relative_path, &media_files[index].path))
+            .min_by_key(|&index| sidecar_claim_order(&sidecar.relative_path, &media_files[index]));
 
-        _ => false,
+        match owner {
+            Some(index) => {
+                let size = sidecar.metadata.len();
+                total_size += size;
+                media_files[index].size += size;
+                media_files[index].sidecars.push(sidecar.relative_path);
+            }
+            // Not named after any media file here, so it is only collected if
+            // it is a media file in its own right
+            None => {
+                if let Some(kind) = options
+                    .filter
+                    .is_included(&sidecar.relative_path)
+                    .then(|| wanted_media_kind(&sidecar.path, options, progress))
+                    .flatten()
+                {
+                    push_media_file(
+                        sidecar,
+                        kind,
+                        options,
+                        &mut media_files,
+                        &mut total_size,
+                        files_found,
+                        progress,
+                    );
+                }
+            }
+        }
     }
-}
 
-/// Collect media files and calculate total size in one pass with progress display
-pub fn collect_media_files_with_size_and_progress(
-    current_dir: &PathBuf,
-    source_root: &PathBuf,
-    media_files: &mut Vec<PathBuf>,
-    total_size: &mut u64,
-    exclude_path: Option<&PathBuf>,
-) -> io::Result<()> {
-    let result = collect_media_files_with_size_progress(
-        current_dir,
+    // Files taken on as companions or left out are removed at the end, so
+    // the indices stay valid until then
+    let mut removed = Vec::new();
+    group_live_photos(
+        &mut media_files,
+        &found_here,
         source_root,
-        media_files,
-        total_size,
-        exclude_path,
-        true,
+        options.drop_motion,
+        &mut total_size,
+        &mut removed,
+        progress,
     );
+    pair_raw_and_jpeg(
+        &mut media_files,
+        &found_here,
+        options.pairs,
+        &mut total_size,
+        &mut removed,
+    );
+    remove_indices(&mut media_files, removed);
 
-    // Print a newline after progress to move to next line
-    if !media_files.is_empty() {
-        println!(); // Move to next line after progress display
-    }
-
-    result
+    (media_files, total_size)
 }
 
-/// Collect media files and calculate total size in one pass with progress reporting
-fn collect_media_files_with_size_progress(
-    current_dir: &PathBuf,
-    source_root: &PathBuf,
-    media_files: &mut Vec<PathBuf>,
+/// Find the RAW+JPEG pairs among the media files found in one folder, given
+/// by index, and keep both halves of each pair together or drop one of them
+/// as `mode` asks. A RAW file pairs with an image of the same stem. Files to
+/// be removed from `media_files` are added to `removed`, and files already
+/// in it are left alone.
+fn pair_raw_and_jpeg(
+    media_files: &mut [MediaFile],
+    found_here: &[usize],
+    mode: PairMode,
     total_size: &mut u64,
-    exclude_path: Option<&PathBuf>,
-    show_progress: bool,
-) -> io::Result<()> {
-    let entries = match fs::read_dir(current_dir) {
-        Ok(entries) => entries,
-        Err(e) => {
-            eprintln!(
-                "Warning: Cannot access directory '{}': {}",
-                current_dir.display(),
-                e
-            );
-            return Ok(()); // Continue processing other directories
+    removed: &mut Vec<usize>,
+) {
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    for &raw in found_here {
+        if media_files[raw].kind != MediaKind::Raw || removed.contains(&raw) {
+            continue;
         }
-    };
+        let image = found_here.iter().copied().find(|&image| {
+            media_files[image].kind == MediaKind::Image
+                && !removed.contains(&image)
+                && same_stem(&media_files[raw].path, &media_files[image].path)
+                && !pairs.iter().any(|&(_, paired)| paired == image)
+        });
+        if let Some(image) = image {
+            pairs.push((raw, image));
+        }
+    }
 
-    for entry in entries {
-        let entry = match entry {
-            Ok(entry) => entry,
-            Err(e) => {
-                eprintln!(
-                    "Warning: Cannot read directory entry in '{}': {}",
-                    current_dir.display(),
-                    e
-                );
-                continue; // Skip this entry and continue with others
+    for (raw, image) in pairs {
+        match mode {
+            PairMode::Both => {
+                let companion = media_files[image].clone();
+                media_files[raw].absorb(companion);
+                removed.push(image);
             }
-        };
-        let path = entry.path();
+            PairMode::Raw => {
+                *total_size -= media_files[image].total_size();
+                removed.push(image);
+            }
+            PairMode::Jpeg => {
+                *total_size -= media_files[raw].total_size();
+                removed.push(raw);
+            }
+        }
+    }
+}
 
-        if path.is_dir() {
-            // Skip the destination directory if it's within the source to prevent infinite recursion
-            if let Some(exclude) = exclude_path {
-                if let (Ok(canonical_path), Ok(canonical_exclude)) =
-                    (path.canonicalize(), exclude.canonicalize())
-                {
-                    if canonical_path == canonical_exclude {
-                        println!("Skipping destination directory: {}", path.display());
-                        continue;
+/// Find the Live Photos among the media files found in one folder, given by
+/// index, and keep each still together with its movie, or leave the movie
+/// out if `drop_motion` is set. A still and a movie of the same stem are a
+/// Live Photo unless their content identifiers differ; stills and movies
+/// left over are matched by content identifier alone. Movies to be removed
+/// from `media_files` are added to `removed`.
+fn group_live_photos(
+    media_files: &mut [MediaFile],
+    found_here: &[usize],
+    source_root: &Path,
+    drop_motion: bool,
+    total_size: &mut u64,
+    removed: &mut Vec<usize>,
+    progress: &dyn ProgressListener,
+) {
+    let has_extension = |index: usize, matches: fn(&str) -> bool| {
+        media_files[index]
+            .path
+            .extension()
+            .is_some_and(|extension| matches(&extension.to_string_lossy().to_lowercase()))
+    };
+    let stills: Vec<usize> = found_here
+        .iter()
+        .copied()
+        .filter(|&index| {
+            media_files[index].kind == MediaKind::Image && has_extension(index, is_live_photo_still)
+        })
+        .collect();
+    let movies: Vec<usize> = found_here
+        .iter()
+        .copied()
+        .filter(|&index| {
+            media_files[index].kind == MediaKind::Video && has_extension(index, is_live_photo_movie)
+        })
+        .collect();
+    if stills.is_empty() || movies.is_empty() {
+        return;
+    }
+
+    // Identifiers are read lazily, as most files never need one
+    let mut identifiers: HashMap<usize, Option<String>> = HashMap::new();
+    let mut identifier = |index: usize| -> Option<String> {
+        identifiers
+            .entry(index)
+            .or_insert_with(|| {
+                let path = &media_files[index].path;
+                match read_content_identifier(&source_root.join(path)) {
+                    Ok(identifier) => identifier,
+                    Err(e) => {
+                        progress.warn(format!(
+                            "Cannot read the Live Photo identifier of '{}': {}",
+                            path.display(),
+                            e
+                        ));
+                        None
                     }
                 }
-            }
+            })
+            .clone()
+    };
 
-            // Recursively process subdirectories
-            if let Err(e) = collect_media_files_with_size_progress(
-                &path,
-                source_root,
-                media_files,
-                total_size,
-                exclude_path,
-                show_progress,
-            ) {
-                eprintln!(
-                    "Warning: Cannot access subdirectory '{}': {}",
-                    path.display(),
-                    e
-                );
-                // Continue processing other directories
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    for &still in &stills {
+        for &movie in &movies {
+            if pairs.iter().any(|&(_, paired)| paired == movie)
+                || !same_stem(&media_files[still].path, &media_files[movie].path)
+            {
+                continue;
             }
-        } else if path.is_file() {
-            if let Some(extension) = path.extension() {
-                let ext = extension.to_string_lossy().to_lowercase();
-
-                // Check if it's an image or video file
-                if is_media_file(&ext) {
-                    // Get file size
-                    match fs::metadata(&path) {
-                        Ok(metadata) => {
-                            *total_size += metadata.len();
-
-                            // Calculate relative path from source root
-                            let relative_path = path
-                                .strip_prefix(source_root)
-                                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-                            media_files.push(relative_path.to_path_buf());
-
-                            // Show progress if requested
-                            if show_progress {
-                                print!("\rFiles found: {}", media_files.len());
-                                use std::io::Write;
-                                std::io::stdout().flush().unwrap_or(());
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!(
-                                "Warning: Cannot get file size for '{}': {}",
-                                path.display(),
-                                e
-                            );
-                            // Still add the file to the list even if we can't get its size
-                            let relative_path = path
-                                .strip_prefix(source_root)
-                                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-                            media_files.push(relative_path.to_path_buf());
-
-                            // Show progress if requested
-                            if show_progress {
-                                print!("\rFiles found: {}", media_files.len());
-                                use std::io::Write;
-                                std::io::stdout().flush().unwrap_or(());
-                            }
-                        }
-                    }
-                }
+            match (identifier(still), identifier(movie)) {
+                (Some(a), Some(b)) if a != b => continue,
+                _ => {}
             }
+            pairs.push((still, movie));
+            break;
         }
     }
 
-    Ok(())
+    for &still in &stills {
+        if pairs.iter().any(|&(paired, _)| paired == still) {
+            continue;
+        }
+        let Some(still_identifier) = identifier(still) else {
+            continue;
+        };
+        let movie = movies.iter().copied().find(|&movie| {
+            !pairs.iter().any(|&(_, paired)| paired == movie)
+                && identifier(movie).as_ref() == Some(&still_identifier)
+        });
+        if let Some(movie) = movie {
+            pairs.push((still, movie));
+        }
+    }
+
+    for (still, movie) in pairs {
+        if drop_motion {
+            *total_size -= media_files[movie].total_size();
+        } else {
+            let companion = media_files[movie].clone();
+            media_files[still].absorb(companion);
+        }
+        removed.push(movie);
+    }
+}
+
+/// Remove the media files at the given indices
+fn remove_indices(media_files: &mut Vec<MediaFile>, mut indices: Vec<usize>) {
+    // Highest index first, so the indices still to be removed stay valid
+    indices.sort_unstable_by(|a, b| b.cmp(a));
+    for index in indices {
+        media_files.remove(index);
+    }
+}
+
+/// Add a media file found by a scan and report it to `progress`
+fn push_media_file(
+    file: WalkedFile,
+    kind: MediaKind,
+    options: &ScanOptions,
+    media_files: &mut Vec<MediaFile>,
+    total_size: &mut u64,
+    files_found: &AtomicUsize,
+    progress: &dyn ProgressListener,
+) {
+    let size = file.metadata.len();
+    *total_size += size;
+
+    media_files.push(MediaFile {
+        path: file.relative_path.clone(),
+        kind,
+        size,
+        sidecars: Vec::new(),
+        companions: Vec::new(),
+        copy_links: options.symlinks == SymlinkPolicy::Copy,
+        drop_motion: options.drop_motion,
+    });
+    progress.on_event(&ProgressEvent::FileDiscovered {
+        path: file.relative_path,
+        kind,
+        size,
+        files_found: files_found.fetch_add(1, Ordering::Relaxed) + 1,
+    });
+}
+
+/// The kind of a file that should be collected, warning if its content
+/// cannot be read
+fn wanted_media_kind(
+    path: &Path,
+    options: &ScanOptions,
+    progress: &dyn ProgressListener,
+) -> Option<MediaKind> {
+    match detect_media_kind(path, options) {
+        Ok(kind) => kind.filter(|kind| options.kinds.allows(*kind)),
+        Err(e) => {
+            progress.warn(format!(
+                "Cannot read '{}' to detect its type: {}",
+                path.display(),
+                e
+            ));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::CliOptions;
+    use crate::config::Config;
+    use crate::progress::NoProgress;
+    use crate::test_support::{live_photo_movie, live_photo_still, TempDir};
+
+    /// The media files a scan of `dir` with `options` finds
+    fn scan_with(dir: &TempDir, options: &ScanOptions) -> Vec<MediaFile> {
+        let mut media_files = Vec::new();
+        collect_media_files_with_size_and_progress(
+            dir.path(),
+            &mut media_files,
+            &mut 0,
+            None,
+            options,
+            &CancellationToken::new(),
+            &NoProgress,
+        )
+        .unwrap();
+        media_files
+    }
+
+    /// The media files in `dir`, with the paths of their companions
+    fn scan(dir: &TempDir, drop_motion: bool) -> Vec<(PathBuf, Vec<PathBuf>)> {
+        let mut options = CliOptions::default().scan_options(&Config::default());
+        options.drop_motion = drop_motion;
+        let mut found: Vec<_> = scan_with(dir, &options)
+            .into_iter()
+            .map(|file| {
+                let companions = file.companion_paths().cloned().collect();
+                (file.path, companions)
+            })
+            .collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn live_photos_are_matched_by_name_and_identifier() {
+        let dir = TempDir::new();
+        // Same name and identifier
+        dir.write("IMG_1.JPG", live_photo_still("one", true));
+        dir.write("IMG_1.MOV", live_photo_movie("one"));
+        // Same identifier only
+        dir.write("IMG_2.JPG", live_photo_still("two", false));
+        dir.write("exported.mov", live_photo_movie("two"));
+        // Same name, but different identifiers
+        dir.write("IMG_3.JPG", live_photo_still("three", true));
+        dir.write("IMG_3.MOV", live_photo_movie("other"));
+        // Same name, without identifiers
+        dir.write("IMG_4.JPG", "still");
+        dir.write("IMG_4.MOV", "movie");
+
+        let path = PathBuf::from;
+        assert_eq!(
+            scan(&dir, false),
+            [
+                (path("IMG_1.JPG"), vec![path("IMG_1.MOV")]),
+                (path("IMG_2.JPG"), vec![path("exported.mov")]),
+                (path("IMG_3.JPG"), vec![]),
+                (path("IMG_3.MOV"), vec![]),
+                (path("IMG_4.JPG"), vec![path("IMG_4.MOV")]),
+            ]
+        );
+        assert_eq!(
+            scan(&dir, true),
+            [
+                (path("IMG_1.JPG"), vec![]),
+                (path("IMG_2.JPG"), vec![]),
+                (path("IMG_3.JPG"), vec![]),
+                (path("IMG_3.MOV"), vec![]),
+                (path("IMG_4.JPG"), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn scans_collect_only_the_kinds_asked_for() {
+        use MediaKind::*;
+
+        let dir = TempDir::new();
+        for name in ["a.jpg", "b.cr2", "c.mp4", "d.mxf", "e.svg", "f.txt"] {
+            dir.write(name, name);
+        }
+        let found = |only_kinds: Option<Vec<MediaKind>>, skip_kinds, config: &str| {
+            let options = CliOptions {
+                only_kinds,
+                skip_kinds,
+                ..CliOptions::default()
+            };
+            let files = scan_with(&dir, &options.scan_options(&Config::parse(config).unwrap()));
+            count_by_kind(&files)
+        };
+        assert_eq!(
+            found(None, Vec::new(), ""),
+            [(Image, 1), (Raw, 1), (Video, 1), (ProVideo, 1), (Vector, 1)]
+        );
+        assert_eq!(
+            found(Some(vec![Raw, Video]), Vec::new(), ""),
+            [(Raw, 1), (Video, 1)]
+        );
+        assert_eq!(
+            found(None, vec![Vector], ""),
+            [(Image, 1), (Raw, 1), (Video, 1), (ProVideo, 1)]
+        );
+        assert_eq!(
+            found(Some(vec![Raw, Video]), Vec::new(), "[kinds]\nvideo = off"),
+            [(Raw, 1)]
+        );
+    }
 }
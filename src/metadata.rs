@@ -0,0 +1,255 @@
+//! Capture metadata of media files.
+//!
+//! This module works out when a media file was taken. The capture time is
+//! read from the file's own metadata where this module understands it, and
+//! the time the file was last modified stands in when it has none.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::exif::read_exif;
+use crate::video::read_video_metadata;
+
+/// A date and time of day as recorded by a camera, without a time zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CaptureTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl CaptureTime {
+    /// Parse an EXIF date and time, `YYYY:MM:DD HH:MM:SS`, or an ISO 8601
+    /// one, `YYYY-MM-DDTHH:MM:SS`, whose time zone is left out. Cameras
+    /// without a set clock write zeros or blanks, which give `None`.
+    pub fn parse(value: &str) -> Option<CaptureTime> {
+        let value = value.trim();
+        let number = |range: std::ops::Range<usize>| -> Option<u16> {
+            let digits = value.get(range)?;
+            digits
+                .bytes()
+                .all(|byte| byte.is_ascii_digit())
+                .then(|| digits.parse().ok())
+                .flatten()
+        };
+
+        let time = CaptureTime {
+            year: number(0..4)?,
+            month: number(5..7)? as u8,
+            day: number(8..10)? as u8,
+            // Some cameras leave the time of day out
+            hour: number(11..13).unwrap_or(0) as u8,
+            minute: number(14..16).unwrap_or(0) as u8,
+            second: number(17..19).unwrap_or(0) as u8,
+        };
+        time.is_valid().then_some(time)
+    }
+
+    /// The date and time, in UTC, of a point in time
+    pub fn from_system_time(time: SystemTime) -> Option<CaptureTime> {
+        let seconds = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        CaptureTime::from_unix_seconds(i64::try_from(seconds).ok()?)
+    }
+
+    /// The date and time, in UTC, a number of seconds after 1970-01-01
+    pub fn from_unix_seconds(seconds: i64) -> Option<CaptureTime> {
+        if seconds < 0 {
+            return None;
+        }
+        let (year, month, day) = civil_from_days(seconds / 86_400);
+        let seconds_of_day = seconds % 86_400;
+
+        let time = CaptureTime {
+            year: u16::try_from(year).ok()?,
+            month,
+            day,
+            hour: (seconds_of_day / 3600) as u8,
+            minute: (seconds_of_day / 60 % 60) as u8,
+            second: (seconds_of_day % 60) as u8,
+        };
+        time.is_valid().then_some(time)
+    }
+
+    fn is_valid(&self) -> bool {
+        (1..=9999).contains(&self.year)
+            && (1..=12).contains(&self.month)
+            && (1..=31).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 61
+    }
+}
+
+/// What a media file's metadata says about it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MediaMetadata {
+    /// When the picture or video was taken, if the file records it
+    pub capture_time: Option<CaptureTime>,
+    /// Maker of the camera that took it
+    pub camera_make: Option<String>,
+    /// Model of the camera that took it
+    pub camera_model: Option<String>,
+    /// Serial number of the camera that took it
+    pub camera_serial: Option<String>,
+}
+
+impl MediaMetadata {
+    /// The camera that took the file, as maker and model followed by the
+    /// serial number if known, such as `Canon EOS R5 (0123456789)`. Models
+    /// that already start with the maker's name are not given it twice.
+    pub fn camera_name(&self) -> Option<String> {
+        let mut name = match (self.camera_make.as_deref(), self.camera_model.as_deref()) {
+            (Some(make), Some(model)) => {
+                let brand = make.split_whitespace().next().unwrap_or(make);
+                let model_has_brand = model
+                    .get(..brand.len())
+                    .is_some_and(|start| start.eq_ignore_ascii_case(brand));
+                if model_has_brand {
+                    model.to_string()
+                } else {
+                    format!("{} {}", make, model)
+                }
+            }
+            (Some(name), None) | (None, Some(name)) => name.to_string(),
+            (None, None) => return None,
+        };
+        if let Some(serial) = &self.camera_serial {
+            name.push_str(&format!(" ({})", serial));
+        }
+        Some(name)
+    }
+}
+
+/// Read the metadata of the media file at `path`. Formats this module cannot
+/// read give empty metadata.
+pub fn read_metadata(path: &Path) -> io::Result<MediaMetadata> {
+    match read_exif(path)? {
+        Some(exif) => Ok(MediaMetadata {
+            capture_time: exif.capture_date_time().and_then(CaptureTime::parse),
+            camera_make: exif.make,
+            camera_model: exif.model,
+            camera_serial: exif.serial_number,
+        }),
+        None => read_video_metadata(path),
+    }
+}
+
+/// The metadata of the media file at `path` as an import places it: a file
+/// without a capture time counts as taken when it was last modified, and
+/// metadata that cannot be read counts as empty
+pub fn import_metadata(path: &Path) -> MediaMetadata {
+    let mut metadata = read_metadata(path).unwrap_or_default();
+    if metadata.capture_time.is_none() {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+        metadata.capture_time = modified.ok().and_then(CaptureTime::from_system_time);
+    }
+    metadata
+}
+
+/// The calendar date of a day counted from 1970-01-01, after Howard
+/// Hinnant's `civil_from_days`
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u8;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u8;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> CaptureTime {
+        CaptureTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    #[test]
+    fn parses_exif_and_iso_dates() {
+        assert_eq!(
+            CaptureTime::parse("2024:05:17 14:03:22"),
+            Some(time(2024, 5, 17, 14, 3, 22))
+        );
+        assert_eq!(
+            CaptureTime::parse("2024-05-17T14:03:22+0200"),
+            Some(time(2024, 5, 17, 14, 3, 22))
+        );
+        assert_eq!(
+            CaptureTime::parse(" 2024:05:17 "),
+            Some(time(2024, 5, 17, 0, 0, 0))
+        );
+        assert_eq!(CaptureTime::parse("0000:00:00 00:00:00"), None);
+        assert_eq!(CaptureTime::parse("    :  :     :  :  "), None);
+        assert_eq!(CaptureTime::parse("2024:13:01 00:00:00"), None);
+        assert_eq!(CaptureTime::parse("+024:05:17"), None);
+    }
+
+    #[test]
+    fn unix_seconds_to_utc() {
+        assert_eq!(
+            CaptureTime::from_unix_seconds(0),
+            Some(time(1970, 1, 1, 0, 0, 0))
+        );
+        assert_eq!(
+            CaptureTime::from_unix_seconds(951_782_400),
+            Some(time(2000, 2, 29, 0, 0, 0))
+        );
+        assert_eq!(
+            CaptureTime::from_unix_seconds(1_715_954_602),
+            Some(time(2024, 5, 17, 14, 3, 22))
+        );
+        assert_eq!(CaptureTime::from_unix_seconds(-1), None);
+    }
+
+    #[test]
+    fn camera_name_does_not_repeat_the_brand() {
+        let metadata = |make: Option<&str>, model: Option<&str>, serial: Option<&str>| {
+            MediaMetadata {
+                camera_make: make.map(str::to_string),
+                camera_model: model.map(str::to_string),
+                camera_serial: serial.map(str::to_string),
+                ..MediaMetadata::default()
+            }
+            .camera_name()
+        };
+        assert_eq!(
+            metadata(Some("Canon"), Some("Canon EOS R5"), Some("0123456789")).as_deref(),
+            Some("Canon EOS R5 (0123456789)")
+        );
+        assert_eq!(
+            metadata(Some("NIKON CORPORATION"), Some("NIKON Z 6"), None).as_deref(),
+            Some("NIKON Z 6")
+        );
+        assert_eq!(
+            metadata(Some("Apple"), Some("iPhone 15"), None).as_deref(),
+            Some("Apple iPhone 15")
+        );
+        assert_eq!(
+            metadata(None, Some("X100V"), None).as_deref(),
+            Some("X100V")
+        );
+        assert_eq!(metadata(None, None, Some("123")), None);
+    }
+}
@@ -0,0 +1,1234 @@
+//! The import pipeline.
+//!
+//! This module ties the scan, copy, verify and delete stages together into
+//! a complete run. All questions go through a [`UserInteraction`], so the
+//! same flow is used with dialogs, in the terminal and in scripted runs.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::cancel::{is_cancelled_error, CancellationToken};
+use crate::cli::CliOptions;
+use crate::config::Config;
+use crate::dry_run::{report_copy_plan, simulate_copy_plan};
+use crate::file_ops::{
+    collect_media_files_and_calculate_size, copy_planned_files, copy_queued_files,
+    count_media_files_and_calculate_size, delete_original_files, format_bytes,
+    get_available_disk_space, plan_copies, validate_folder_paths, CopiedFile, CopyPlanner,
+};
+use crate::interaction::{CopySummary, UserInteraction};
+use crate::journal::{discard_partial_copies, journal_path, Journal, JournalState, Stage};
+use crate::layout::CopyPlacement;
+use crate::media::{
+    count_by_kind, describe_kind_counts, scan_media_folders, MediaFile, MediaKind, ScanOptions,
+};
+use crate::progress::{ProgressEvent, ProgressListener};
+use crate::verify::verify_copies;
+
+/// Number of planned copies a streamed import lets the scan get ahead of
+/// the copying
+const COPY_QUEUE_LENGTH: usize = 256;
+
+/// The user interaction for a run, with answers given as options filled in
+struct Session<'a> {
+    ui: &'a dyn UserInteraction,
+    options: &'a CliOptions,
+    progress: &'a dyn ProgressListener,
+    cancel: &'a CancellationToken,
+}
+
+impl Session<'_> {
+    fn select_folders(&self) -> io::Result<Option<(PathBuf, PathBuf)>> {
+        if let (Some(source), Some(destination)) = (&self.options.source, &self.options.destination)
+        {
+            return Ok(Some((source.clone(), destination.clone())));
+        }
+
+        // Choose where to copy files from, then where to copy them to
+        self.progress.message("Select source folder:".to_string());
+
+        let source_path = match self.ui.select_folder("Select Source Folder")? {
+            Some(path) => path,
+            None => {
+                self.progress.message("No source selected.".to_string());
+                return Ok(None);
+            }
+        };
+
+        let dest_path = match self.ui.select_folder("Select Destination Folder")? {
+            Some(path) => path,
+            None => {
+                self.progress
+                    .message("No destination selected.".to_string());
+                return Ok(None);
+            }
+        };
+
+        Ok(Some((source_path, dest_path)))
+    }
+
+    fn confirm_copy(&self, summary: &CopySummary) -> io::Result<bool> {
+        if self.options.assume_yes {
+            return Ok(true);
+        }
+        self.ui.confirm_copy(summary)
+    }
+
+    /// Report what the scan found and ask whether to copy it
+    fn confirm_found(
+        &self,
+        dest_path: &Path,
+        file_count: usize,
+        by_kind: Vec<(MediaKind, usize)>,
+        total_size: u64,
+    ) -> bool {
+        let progress = self.progress;
+
+        let available_space = available_space(get_available_disk_space(dest_path), progress);
+
+        progress.message(format!(
+            "Found {} media files ({}), {} available on destination.",
+            file_count,
+            format_bytes(total_size),
+            format_bytes(available_space)
+        ));
+        progress.message(format!("  {}", describe_kind_counts(&by_kind)));
+
+        // Ask for confirmation with size and space information
+        let summary = CopySummary {
+            file_count,
+            by_kind,
+            total_size,
+            available_space,
+        };
+        let should_proceed = match self.confirm_copy(&summary) {
+            Ok(proceed) => proceed,
+            Err(e) => {
+                progress.error(format!("Cannot show confirmation dialog: {}", e));
+                return false;
+            }
+        };
+
+        if !should_proceed {
+            progress.message("Copy operation cancelled by user.".to_string());
+        }
+        should_proceed
+    }
+
+    fn confirm_resume(&self, remaining_count: usize, total_count: usize) -> io::Result<bool> {
+        match self.options.resume {
+            Some(resume) => Ok(resume),
+            None => self.ui.confirm_resume(remaining_count, total_count),
+        }
+    }
+
+    fn confirm_verify(&self, file_count: usize) -> io::Result<bool> {
+        match self.options.verify {
+            Some(verify) => Ok(verify),
+            None => self.ui.confirm_verify(file_count),
+        }
+    }
+
+    fn report_verification_failures(&self, failed_count: usize) -> io::Result<()> {
+        self.ui.report_verification_failures(failed_count)
+    }
+
+    fn confirm_delete(&self, file_count: usize) -> io::Result<bool> {
+        match self.options.delete {
+            Some(delete) => Ok(delete),
+            None => self.ui.confirm_delete(file_count),
+        }
+    }
+
+    fn notify_completion(&self) -> io::Result<()> {
+        self.ui.notify_completion()
+    }
+
+    /// Whether the run was cancelled and should stop before its next stage.
+    /// The journal is left in place so the import can be resumed.
+    fn stopped_by_cancel(&self, journal: Option<&Journal>) -> bool {
+        if !self.cancel.is_cancelled() {
+            return false;
+        }
+        if journal.is_some() {
+            self.progress
+                .message("Import stopped; run it again to resume.".to_string());
+        } else {
+            self.progress.message("Import stopped.".to_string());
+        }
+        true
+    }
+}
+
+/// Run the whole import: choose folders, scan, confirm, copy, verify and
+/// delete, asking the user through `ui` and reporting to `progress`.
+/// Questions already answered in `options` are not asked. Cancelling
+/// `cancel` stops the run after the files already in progress.
+pub fn run_image_mover(
+    ui: &dyn UserInteraction,
+    options: &CliOptions,
+    progress: &dyn ProgressListener,
+    cancel: &CancellationToken,
+) -> io::Result<()> {
+    let ui = Session {
+        ui,
+        options,
+        progress,
+        cancel,
+    };
+
+    let config = match Config::load(options.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            progress.error(e.to_string());
+            return Ok(());
+        }
+    };
+    let scan_options = options.scan_options(&config);
+
+    let (source_path, dest_path) = match ui.select_folders()? {
+        Some(folders) => folders,
+        None => return Ok(()),
+    };
+
+    progress.message(format!("Source: {:?}", source_path));
+    progress.message(format!("Destination: {:?}", dest_path));
+
+    // Check for invalid folder relationships
+    if let Err(e) = validate_folder_paths(&source_path, &dest_path, progress) {
+        progress.error(e.to_string());
+        return Ok(());
+    }
+
+    if options.dry_run {
+        return print_dry_run(
+            &source_path,
+            &dest_path,
+            &scan_options,
+            &options.placement,
+            cancel,
+            progress,
+        );
+    }
+
+    // Offer to pick up an earlier run that did not finish
+    match Journal::open_existing(&dest_path) {
+        Ok(Some((journal, state))) => {
+            if !same_folder(&state.source_root, &source_path) {
+                progress.error(format!(
+                    "The destination has an unfinished import from {:?}.",
+                    state.source_root
+                ));
+                progress.message(format!(
+                    "Select that source folder to resume it, or remove {:?} to discard it.",
+                    journal_path(&dest_path)
+                ));
+                return Ok(());
+            }
+
+            let remaining = state.entries.len() - state.deleted_count();
+            let should_resume = match ui.confirm_resume(remaining, state.entries.len()) {
+                Ok(resume) => resume,
+                Err(e) => {
+                    progress.error(format!("Cannot show resume prompt: {}", e));
+                    return Ok(());
+                }
+            };
+
+            if should_resume {
+                progress.message("Resuming unfinished import...".to_string());
+                return resume_import(&ui, &source_path, &dest_path, journal, state);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => {
+            progress.warn(format!("Cannot read the journal of an earlier run: {}", e));
+        }
+    }
+
+    if options.stream {
+        // A streamed scan plans files in whatever order its threads find
+        // them, which would number them differently on every run
+        if options.placement.uses_sequence() {
+            progress.message(
+                "Note: The rename template numbers files, so they are copied after the whole scan."
+                    .to_string(),
+            );
+        } else {
+            return stream_import(&ui, &source_path, &dest_path, &scan_options);
+        }
+    }
+
+    // Calculate total size and collect media files in one pass
+    let (media_files, total_size) = match collect_media_files_and_calculate_size(
+        &source_path,
+        Some(&dest_path),
+        &scan_options,
+        cancel,
+        progress,
+    ) {
+        Ok((files, size)) => (files, size),
+        Err(e) if is_cancelled_error(&e) => return Ok(()),
+        Err(e) => {
+            progress.error(format!("Cannot scan files and calculate size: {}", e));
+            return Ok(());
+        }
+    };
+
+    if total_size == 0 || media_files.is_empty() {
+        progress.message("No media files found in the source directory.".to_string());
+        return Ok(());
+    }
+
+    // Both halves of a RAW+JPEG pair count as media files
+    let file_count = media_files
+        .iter()
+        .flat_map(MediaFile::with_companions)
+        .count();
+
+    if !ui.confirm_found(
+        &dest_path,
+        file_count,
+        count_by_kind(&media_files),
+        total_size,
+    ) {
+        return Ok(());
+    }
+
+    let plan = match plan_copies(&source_path, &dest_path, &options.placement, &media_files) {
+        Ok(plan) => plan,
+        Err(e) => {
+            progress.error(format!("Cannot plan copy destinations: {}", e));
+            return Ok(());
+        }
+    };
+
+    // Record the plan so an interrupted run can be resumed
+    let journal = match Journal::create(&source_path, &dest_path, &plan) {
+        Ok(journal) => Some(journal),
+        Err(e) => {
+            progress.warn(format!(
+                "Cannot create journal, an interrupted run will not be resumable: {}",
+                e
+            ));
+            None
+        }
+    };
+
+    let copy_report =
+        match copy_planned_files(&dest_path, &plan, journal.as_ref(), cancel, progress) {
+            Ok(report) => report,
+            Err(e) => {
+                progress.error(format!("Cannot copy files: {}", e));
+                return Ok(());
+            }
+        };
+
+    copy_report.report_summary(progress);
+    if ui.stopped_by_cancel(journal.as_ref()) {
+        return Ok(());
+    }
+
+    finish_import(
+        &ui,
+        &source_path,
+        &dest_path,
+        journal,
+        ImportProgress {
+            unverified: copy_report.manifest(),
+            ..ImportProgress::default()
+        },
+    )
+}
+
+/// The space available on the destination drive, as found by
+/// `get_available_disk_space`
+fn available_space(space: io::Result<u64>, progress: &dyn ProgressListener) -> u64 {
+    match space {
+        Ok(space) => space,
+        Err(e) => {
+            progress.warn(format!("Could not determine available disk space: {}", e));
+            // Continue with operation but warn user
+            u64::MAX // Set to max so we don't show space warning
+        }
+    }
+}
+
+/// Scan and plan an import the same way a real run would, then report the plan
+fn print_dry_run(
+    source_path: &Path,
+    dest_path: &PathBuf,
+    scan_options: &ScanOptions,
+    placement: &CopyPlacement,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+) -> io::Result<()> {
+    if journal_path(dest_path).exists() {
+        progress.message(
+            "Note: The destination has an unfinished import that a real run would offer to resume."
+                .to_string(),
+        );
+    }
+
+    let (media_files, total_size) = match collect_media_files_and_calculate_size(
+        source_path,
+        Some(dest_path),
+        scan_options,
+        cancel,
+        progress,
+    ) {
+        Ok((files, size)) => (files, size),
+        Err(e) if is_cancelled_error(&e) => return Ok(()),
+        Err(e) => {
+            progress.error(format!("Cannot scan files and calculate size: {}", e));
+            return Ok(());
+        }
+    };
+
+    if media_files.is_empty() {
+        progress.message("No media files found in the source directory.".to_string());
+        return Ok(());
+    }
+    progress.message(format!(
+        "Found {}.",
+        describe_kind_counts(&count_by_kind(&media_files))
+    ));
+
+    match plan_copies(source_path, dest_path, placement, &media_files) {
+        Ok(plan) => {
+            let report = simulate_copy_plan(dest_path, &plan);
+            let file_count = media_files
+                .iter()
+                .flat_map(MediaFile::with_companions)
+                .count();
+            report_copy_plan(&report, file_count, total_size, progress);
+        }
+        Err(e) => progress.error(format!("Cannot plan copy destinations: {}", e)),
+    }
+
+    Ok(())
+}
+
+/// Scan and copy at the same time: files are planned as soon as the scan
+/// finds them and copied from a bounded queue. A first pass counts the files
+/// for the confirmation without keeping them.
+fn stream_import(
+    ui: &Session,
+    source_path: &PathBuf,
+    dest_path: &PathBuf,
+    scan_options: &ScanOptions,
+) -> io::Result<()> {
+    let progress = ui.progress;
+
+    let totals = match count_media_files_and_calculate_size(
+        source_path,
+        Some(dest_path),
+        scan_options,
+        ui.cancel,
+        progress,
+    ) {
+        Ok(totals) => totals,
+        Err(e) if is_cancelled_error(&e) => return Ok(()),
+        Err(e) => {
+            progress.error(format!("Cannot scan files and calculate size: {}", e));
+            return Ok(());
+        }
+    };
+
+    if totals.total_size == 0 || totals.copy_count == 0 {
+        progress.message("No media files found in the source directory.".to_string());
+        return Ok(());
+    }
+    if !ui.confirm_found(
+        dest_path,
+        totals.file_count(),
+        totals.kind_counts(),
+        totals.total_size,
+    ) {
+        return Ok(());
+    }
+
+    // Each copy is recorded as it is planned, so an interrupted run can
+    // resume the files found so far
+    let journal = match Journal::create(source_path, dest_path, &[]) {
+        Ok(journal) => Some(journal),
+        Err(e) => {
+            progress.warn(format!(
+                "Cannot create journal, an interrupted run will not be resumable: {}",
+                e
+            ));
+            None
+        }
+    };
+
+    let planner = CopyPlanner::new(source_path, dest_path, &ui.options.placement);
+    let scan_progress = StreamedScan(progress);
+    let (queue, planned) = mpsc::sync_channel(COPY_QUEUE_LENGTH);
+    let (scan_result, copy_result) = thread::scope(|scope| {
+        let scan = scope.spawn(|| {
+            // Dropped when the scan ends, which closes the queue
+            let queue = queue;
+            scan_media_folders(
+                source_path,
+                Some(dest_path),
+                scan_options,
+                ui.cancel,
+                &scan_progress,
+                |media_files, _| {
+                    for media_file in &media_files {
+                        let planned = planner.plan(media_file)?;
+                        if let Some(journal) = &journal {
+                            if let Err(e) = journal.record_planned(&planned) {
+                                progress.warn(format!(
+                                    "Cannot update journal for '{}': {}",
+                                    planned.source.display(),
+                                    e
+                                ));
+                            }
+                        }
+                        queue
+                            .send(planned)
+                            .map_err(|_| io::Error::other("the copy stage has stopped"))?;
+                    }
+                    Ok(())
+                },
+            )
+        });
+
+        let copy_result = copy_queued_files(
+            dest_path,
+            planned,
+            totals.copy_count,
+            journal.as_ref(),
+            ui.cancel,
+            progress,
+        );
+        let scan_result = scan
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        (scan_result, copy_result)
+    });
+
+    let copy_report = match copy_result {
+        Ok(report) => report,
+        Err(e) => {
+            progress.error(format!("Cannot copy files: {}", e));
+            return Ok(());
+        }
+    };
+
+    copy_report.report_summary(progress);
+    if ui.stopped_by_cancel(journal.as_ref()) {
+        return Ok(());
+    }
+    // The files copied so far are in the journal, so a new run can still
+    // verify them and delete their originals
+    if let Err(e) = scan_result {
+        progress.error(format!(
+            "The scan stopped early; files it had not reached were not copied: {}",
+            e
+        ));
+        return Ok(());
+    }
+
+    finish_import(
+        ui,
+        source_path,
+        dest_path,
+        journal,
+        ImportProgress {
+            unverified: copy_report.manifest(),
+            ..ImportProgress::default()
+        },
+    )
+}
+
+/// Passes on the events of the scan feeding a streamed copy, apart from the
+/// files found and skipped, which the counting pass has already reported
+struct StreamedScan<'a>(&'a dyn ProgressListener);
+
+impl ProgressListener for StreamedScan<'_> {
+    fn on_event(&self, event: &ProgressEvent) {
+        if !matches!(
+            event,
+            ProgressEvent::FileDiscovered { .. } | ProgressEvent::Skipped { .. }
+        ) {
+            self.0.on_event(event);
+        }
+    }
+}
+
+/// Where an import stands once copying is done
+#[derive(Default)]
+struct ImportProgress {
+    /// Copies that have not been verified
+    unverified: Vec<CopiedFile>,
+    /// Copies already confirmed identical to their originals
+    verified: Vec<CopiedFile>,
+    /// The user already chose to verify copies
+    verify_started: bool,
+    /// The user already chose to delete originals
+    delete_started: bool,
+}
+
+fn same_folder(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Continue an interrupted import from the state recorded in its journal
+fn resume_import(
+    ui: &Session,
+    source_path: &PathBuf,
+    dest_path: &PathBuf,
+    journal: Journal,
+    state: JournalState,
+) -> io::Result<()> {
+    let pending = state.pending_copies();
+    let mut unverified = state.unverified_copies();
+
+    if !pending.is_empty() {
+        discard_partial_copies(&state, ui.progress);
+        match copy_planned_files(dest_path, &pending, Some(&journal), ui.cancel, ui.progress) {
+            Ok(copy_report) => {
+                copy_report.report_summary(ui.progress);
+                unverified.extend(copy_report.manifest());
+            }
+            Err(e) => {
+                ui.progress.error(format!("Cannot copy files: {}", e));
+                return Ok(());
+            }
+        }
+    }
+
+    if ui.stopped_by_cancel(Some(&journal)) {
+        return Ok(());
+    }
+
+    let failed = state.failed_verifications();
+    if !failed.is_empty() {
+        ui.progress.warn(format!(
+            "{} copies failed verification in the earlier run; their originals will be kept.",
+            failed.len()
+        ));
+    }
+
+    finish_import(
+        ui,
+        source_path,
+        dest_path,
+        Some(journal),
+        ImportProgress {
+            unverified,
+            verified: state.verified_copies(),
+            verify_started: state.verify_started,
+            delete_started: state.delete_started,
+        },
+    )
+}
+
+/// Run the verification and deletion stages and close the journal
+fn finish_import(
+    ui: &Session,
+    source_path: &PathBuf,
+    dest_path: &Path,
+    journal: Option<Journal>,
+    progress: ImportProgress,
+) -> io::Result<()> {
+    let ImportProgress {
+        unverified,
+        mut verified,
+        verify_started,
+        delete_started,
+    } = progress;
+
+    let copied_count = unverified.len() + verified.len();
+    if copied_count == 0 {
+        close_journal(journal, ui.progress);
+        return Ok(());
+    }
+
+    // Optionally read every copy back and compare it with its original
+    let should_verify = if verify_started {
+        true
+    } else if unverified.is_empty() {
+        false
+    } else {
+        match ui.confirm_verify(unverified.len()) {
+            Ok(verify) => verify,
+            Err(e) => {
+                ui.progress
+                    .error(format!("Cannot show verification prompt: {}", e));
+                return Ok(());
+            }
+        }
+    };
+
+    let manifest = if should_verify {
+        if let Some(journal) = &journal {
+            if let Err(e) = journal.record_stage(Stage::Verify) {
+                ui.progress.warn(format!("Cannot update journal: {}", e));
+            }
+        }
+
+        let verification = verify_copies(&unverified, journal.as_ref(), ui.cancel, ui.progress);
+        if ui.stopped_by_cancel(journal.as_ref()) {
+            return Ok(());
+        }
+
+        if !verification.failed.is_empty() {
+            ui.progress.warn(format!(
+                "{} copies failed verification; their originals will be kept.",
+                verification.failed.len()
+            ));
+            if let Err(e) = ui.report_verification_failures(verification.failed.len()) {
+                ui.progress
+                    .error(format!("Cannot show verification failure dialog: {}", e));
+            }
+        }
+
+        verified.extend(verification.verified);
+        verified
+    } else {
+        verified.extend(unverified);
+        verified
+    };
+
+    // Ask user if they want to delete original files
+    let count = manifest.len();
+    if count == 0 {
+        close_journal(journal, ui.progress);
+        return Ok(());
+    }
+
+    let should_delete = if delete_started {
+        true
+    } else {
+        match ui.confirm_delete(count) {
+            Ok(delete) => delete,
+            Err(e) => {
+                ui.progress
+                    .error(format!("Cannot show deletion prompt: {}", e));
+                return Ok(());
+            }
+        }
+    };
+
+    if !should_delete {
+        ui.progress
+            .message("Original files kept as requested.".to_string());
+        close_journal(journal, ui.progress);
+        // Show completion dialog
+        if let Err(e) = ui.notify_completion() {
+            ui.progress
+                .error(format!("Cannot show completion dialog: {}", e));
+        }
+        return Ok(());
+    }
+
+    if let Some(journal) = &journal {
+        if let Err(e) = journal.record_stage(Stage::Delete) {
+            ui.progress.warn(format!("Cannot update journal: {}", e));
+        }
+    }
+
+    match delete_original_files(
+        source_path,
+        dest_path,
+        &manifest,
+        journal.as_ref(),
+        ui.cancel,
+        ui.progress,
+    ) {
+        Ok(delete_report) => {
+            delete_report.report_summary(ui.progress);
+            if ui.stopped_by_cancel(journal.as_ref()) {
+                return Ok(());
+            }
+            close_journal(journal, ui.progress);
+        }
+        Err(e) => {
+            ui.progress
+                .error(format!("Cannot delete original files: {}", e));
+        }
+    }
+
+    // Show completion dialog
+    if let Err(e) = ui.notify_completion() {
+        ui.progress
+            .error(format!("Cannot show completion dialog: {}", e));
+    }
+
+    Ok(())
+}
+
+/// Remove the journal of a run that has finished
+fn close_journal(journal: Option<Journal>, progress: &dyn ProgressListener) {
+    if let Some(journal) = journal {
+        if let Err(e) = journal.finish() {
+            progress.warn(format!("Cannot remove journal: {}", e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_ops::PlannedCopy;
+    use crate::interaction::{Prompt, ScriptedInteraction};
+    use crate::layout::DestinationLayout;
+    use crate::rename::RenameTemplate;
+    use crate::test_support::{jpeg_with_xmp, mp4_movie, RecordingProgress, TempDir};
+    use std::fs;
+
+    /// A source and an empty destination folder in a temporary folder
+    fn folders() -> (TempDir, PathBuf, PathBuf) {
+        let dir = TempDir::new();
+        let source = dir.path().join("src");
+        let destination = dir.path().join("dst");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&destination).unwrap();
+        (dir, source, destination)
+    }
+
+    /// Run an import with the answers of `script`, without any user
+    /// configuration
+    fn run(dir: &TempDir, script: &ScriptedInteraction) -> RecordingProgress {
+        run_with(dir, script, CliOptions::default())
+    }
+
+    fn run_with(
+        dir: &TempDir,
+        script: &ScriptedInteraction,
+        options: CliOptions,
+    ) -> RecordingProgress {
+        let options = CliOptions {
+            config: Some(dir.write("config.ini", "")),
+            ..options
+        };
+        let progress = RecordingProgress::default();
+        run_image_mover(script, &options, &progress, &CancellationToken::new()).unwrap();
+        progress
+    }
+
+    fn read(path: PathBuf) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn import_copies_verifies_and_deletes() {
+        let (dir, source, destination) = folders();
+        dir.write("src/a.jpg", "image a");
+        dir.write("src/a.xmp", "sidecar a");
+        dir.write("src/trip/b.png", "image b");
+        let script = ScriptedInteraction::approve_all(source.clone(), destination.clone());
+
+        let progress = run(&dir, &script);
+
+        assert_eq!(read(destination.join("a.jpg")), "image a");
+        assert_eq!(read(destination.join("a.xmp")), "sidecar a");
+        assert_eq!(read(destination.join("trip/b.png")), "image b");
+        assert_eq!(
+            fs::read_dir(&source).unwrap().count(),
+            0,
+            "{:?}",
+            progress.messages()
+        );
+        assert!(!journal_path(&destination).exists());
+
+        let prompts = script.prompts();
+        assert!(matches!(&prompts[2], Prompt::ConfirmCopy(summary) if summary.file_count == 2));
+        assert_eq!(
+            prompts[3..],
+            [
+                Prompt::ConfirmVerify(2),
+                Prompt::ConfirmDelete(2),
+                Prompt::Completion
+            ]
+        );
+    }
+
+    #[test]
+    fn space_shortfalls_are_found_against_the_free_space() {
+        let summary = |total_size, available_space| CopySummary {
+            file_count: 1,
+            by_kind: vec![(MediaKind::Image, 1)],
+            total_size,
+            available_space,
+        };
+        let progress = RecordingProgress::default();
+
+        let free = available_space(Ok(1000), &progress);
+        assert!(!summary(1000, free).exceeds_available_space());
+        assert!(summary(1001, free).exceeds_available_space());
+        assert!(progress.messages().is_empty());
+
+        let unknown = available_space(Err(io::Error::other("no statvfs")), &progress);
+        assert!(!summary(u64::MAX, unknown).exceeds_available_space());
+        assert_eq!(
+            progress.messages(),
+            ["Warning: Could not determine available disk space: no statvfs"]
+        );
+    }
+
+    #[test]
+    fn dry_run_reports_the_plan_and_writes_nothing() {
+        let (dir, source, destination) = folders();
+        dir.write("src/IMG_1.CR2", "raw");
+        dir.write("src/IMG_1.JPG", "image");
+        dir.write("src/IMG_1.xmp", "sidecar");
+        dir.write("src/trip/b.png", "image b");
+        let script = ScriptedInteraction::approve_all(source.clone(), destination.clone());
+        let options = CliOptions {
+            dry_run: true,
+            ..CliOptions::default()
+        };
+
+        let progress = run_with(&dir, &script, options);
+
+        assert_eq!(fs::read_dir(&destination).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(&source).unwrap().count(), 4);
+        let messages = progress.messages();
+        for line in [
+            format!("MKDIR   {}", destination.join("trip").display()),
+            format!(
+                "COPY    {} -> {}",
+                source.join("trip/b.png").display(),
+                destination.join("trip/b.png").display()
+            ),
+            format!(
+                "  +     {} -> {}",
+                source.join("IMG_1.JPG").display(),
+                destination.join("IMG_1.JPG").display()
+            ),
+            format!(
+                "DELETE  {} (if confirmed)",
+                source.join("IMG_1.CR2").display()
+            ),
+            "3 media files found (22 B).".to_string(),
+            "2 files would be copied, 0 of them renamed.".to_string(),
+        ] {
+            assert!(messages.contains(&line), "{}: {:?}", line, messages);
+        }
+        assert!(!script
+            .prompts()
+            .iter()
+            .any(|prompt| matches!(prompt, Prompt::ConfirmCopy(_))));
+    }
+
+    #[test]
+    fn stages_report_their_files_in_order() {
+        let (dir, source, destination) = folders();
+        dir.write("src/a.jpg", "image a");
+        dir.write("src/trip/b.png", "image b");
+        let script = ScriptedInteraction::approve_all(source.clone(), destination.clone());
+
+        let progress = run(&dir, &script);
+
+        // Files are handled in parallel, so only their counts come in order
+        let events: Vec<String> = progress
+            .events()
+            .iter()
+            .filter_map(|event| match event {
+                ProgressEvent::StageStarted { stage, .. } => Some(format!("start {:?}", stage)),
+                ProgressEvent::StageFinished {
+                    stage,
+                    succeeded,
+                    failed,
+                } => Some(format!("end {:?} {}/{}", stage, succeeded, failed)),
+                ProgressEvent::FileDiscovered { path, .. } => {
+                    Some(format!("found {}", path.display()))
+                }
+                ProgressEvent::FileCopied {
+                    completed, total, ..
+                } => Some(format!("copied {}/{}", completed, total)),
+                ProgressEvent::FileVerified {
+                    completed, total, ..
+                } => Some(format!("verified {}/{}", completed, total)),
+                ProgressEvent::FileDeleted {
+                    completed, total, ..
+                } => Some(format!("deleted {}/{}", completed, total)),
+                ProgressEvent::DirectoryRemoved(path) => {
+                    Some(format!("removed {}", path.display()))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "start Scan".to_string(),
+                format!("found {}", Path::new("a.jpg").display()),
+                format!("found {}", Path::new("trip/b.png").display()),
+                "end Scan 2/0".to_string(),
+                "start Copy".to_string(),
+                "copied 1/2".to_string(),
+                "copied 2/2".to_string(),
+                "end Copy 2/0".to_string(),
+                "start Verify".to_string(),
+                "verified 1/2".to_string(),
+                "verified 2/2".to_string(),
+                "end Verify 2/0".to_string(),
+                "start Delete".to_string(),
+                "deleted 1/2".to_string(),
+                "deleted 2/2".to_string(),
+                format!("removed {}", source.join("trip").display()),
+                "end Delete 2/0".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn streamed_import_copies_every_folder() {
+        let (dir, source, destination) = folders();
+        dir.write("src/a.jpg", "image a");
+        dir.write("src/a.xmp", "sidecar a");
+        for folder in ["one", "two", "two/three"] {
+            dir.write(&format!("src/{}/b.png", folder), folder);
+        }
+        let script = ScriptedInteraction::approve_all(source.clone(), destination.clone());
+        let options = CliOptions {
+            stream: true,
+            ..CliOptions::default()
+        };
+
+        let progress = run_with(&dir, &script, options);
+
+        assert_eq!(read(destination.join("a.jpg")), "image a");
+        assert_eq!(read(destination.join("a.xmp")), "sidecar a");
+        for folder in ["one", "two", "two/three"] {
+            assert_eq!(read(destination.join(folder).join("b.png")), folder);
+        }
+        assert_eq!(
+            fs::read_dir(&source).unwrap().count(),
+            0,
+            "{:?}",
+            progress.messages()
+        );
+        assert!(!journal_path(&destination).exists());
+        assert!(!progress
+            .messages()
+            .iter()
+            .any(|message| message.starts_with("Error")));
+        assert!(
+            matches!(&script.prompts()[2], Prompt::ConfirmCopy(summary) if summary.file_count == 4)
+        );
+    }
+
+    #[test]
+    fn sequence_numbers_do_not_depend_on_streaming() {
+        let (dir, source, destination) = folders();
+        for folder in ["a", "b", "c", "d"] {
+            dir.write(&format!("src/{}/1.jpg", folder), folder);
+            dir.write(&format!("src/{}/2.jpg", folder), folder);
+        }
+        let mut script = ScriptedInteraction::approve_all(source.clone(), destination.clone());
+        script.delete = false;
+        let options = CliOptions {
+            stream: true,
+            placement: CopyPlacement {
+                layout: DestinationLayout::Flat,
+                rename: Some(RenameTemplate::from_spec("{seq:02}_{orig_stem}.{ext}").unwrap()),
+                ..CopyPlacement::default()
+            },
+            ..CliOptions::default()
+        };
+
+        let progress = run_with(&dir, &script, options);
+
+        let mut names: Vec<String> = fs::read_dir(&destination)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                format!(
+                    "{} {}",
+                    path.file_name().unwrap().to_string_lossy(),
+                    read(path.clone())
+                )
+            })
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "01_1.jpg a",
+                "02_2.jpg a",
+                "03_1.jpg b",
+                "04_2.jpg b",
+                "05_1.jpg c",
+                "06_2.jpg c",
+                "07_1.jpg d",
+                "08_2.jpg d"
+            ],
+            "{:?}",
+            progress.messages()
+        );
+        assert!(progress
+            .messages()
+            .iter()
+            .any(|message| message.contains("copied after the whole scan")));
+    }
+
+    #[test]
+    fn declining_delete_keeps_the_originals() {
+        let (dir, source, destination) = folders();
+        dir.write("src/a.jpg", "image a");
+        let mut script = ScriptedInteraction::approve_all(source.clone(), destination.clone());
+        script.delete = false;
+
+        let progress = run(&dir, &script);
+
+        assert_eq!(read(source.join("a.jpg")), "image a");
+        assert_eq!(read(destination.join("a.jpg")), "image a");
+        assert!(!journal_path(&destination).exists());
+        assert!(progress
+            .messages()
+            .contains(&"Original files kept as requested.".to_string()));
+        assert_eq!(script.prompts().last(), Some(&Prompt::Completion));
+    }
+
+    #[test]
+    fn declining_the_copy_writes_nothing() {
+        let (dir, source, destination) = folders();
+        dir.write("src/a.jpg", "image a");
+        let mut script = ScriptedInteraction::approve_all(source.clone(), destination.clone());
+        script.copy = false;
+
+        run(&dir, &script);
+
+        assert_eq!(fs::read_dir(&destination).unwrap().count(), 0);
+        assert!(source.join("a.jpg").exists());
+    }
+
+    #[test]
+    fn resume_redoes_an_interrupted_copy_and_keeps_foreign_files() {
+        let (dir, source, destination) = folders();
+        dir.write("src/a.jpg", "image a");
+        dir.write("src/b.jpg", "image b");
+        let planned = |name: &str| PlannedCopy {
+            source: source.join(name),
+            destination: destination.join(name),
+            renamed_from: None,
+            companions: Vec::new(),
+            copy_links: false,
+            drop_motion: false,
+        };
+
+        // An earlier run planned both and stopped while writing b.jpg;
+        // a.jpg was since put in its place by something else
+        let journal =
+            Journal::create(&source, &destination, &[planned("a.jpg"), planned("b.jpg")]).unwrap();
+        journal
+            .record_copying(&CopiedFile {
+                source: source.join("b.jpg"),
+                destination: destination.join("b.jpg"),
+                companions: Vec::new(),
+            })
+            .unwrap();
+        drop(journal);
+        dir.write("dst/b.jpg", "ima");
+        dir.write("dst/a.jpg", "not ours");
+        let script = ScriptedInteraction::approve_all(source.clone(), destination.clone());
+
+        let progress = run(&dir, &script);
+
+        assert_eq!(read(destination.join("a.jpg")), "not ours");
+        assert_eq!(read(destination.join("a_1.jpg")), "image a");
+        assert_eq!(read(destination.join("b.jpg")), "image b");
+        assert_eq!(
+            fs::read_dir(&source).unwrap().count(),
+            0,
+            "{:?}",
+            progress.messages()
+        );
+        assert!(!journal_path(&destination).exists());
+        assert!(script.prompts().contains(&Prompt::ConfirmResume {
+            remaining_count: 2,
+            total_count: 2
+        }));
+    }
+
+    #[test]
+    fn resume_removes_files_claimed_but_never_written() {
+        let (dir, source, destination) = folders();
+        dir.write("src/a.jpg", "image a");
+        dir.write("src/b.jpg", "image b");
+        let planned = |name: &str| PlannedCopy {
+            source: source.join(name),
+            destination: destination.join(name),
+            renamed_from: None,
+            companions: Vec::new(),
+            copy_links: false,
+            drop_motion: false,
+        };
+        let claiming = |name: &str| CopiedFile {
+            source: source.join(name),
+            destination: destination.join(name),
+            companions: Vec::new(),
+        };
+
+        // An earlier run stopped right after creating a.jpg, and while about
+        // to create b.jpg, which something else created first
+        let journal =
+            Journal::create(&source, &destination, &[planned("a.jpg"), planned("b.jpg")]).unwrap();
+        journal.record_claiming(&claiming("a.jpg")).unwrap();
+        journal.record_claiming(&claiming("b.jpg")).unwrap();
+        drop(journal);
+        dir.write("dst/a.jpg", "");
+        dir.write("dst/b.jpg", "not ours");
+        let script = ScriptedInteraction::approve_all(source.clone(), destination.clone());
+
+        let progress = run(&dir, &script);
+
+        assert_eq!(
+            read(destination.join("a.jpg")),
+            "image a",
+            "{:?}",
+            progress.messages()
+        );
+        assert!(!destination.join("a_1.jpg").exists());
+        assert_eq!(read(destination.join("b.jpg")), "not ours");
+        assert_eq!(read(destination.join("b_1.jpg")), "image b");
+        assert!(!journal_path(&destination).exists());
+    }
+
+    #[test]
+    fn motion_photos_without_their_video_keep_the_original() {
+        let (dir, source, destination) = folders();
+        let video = mp4_movie();
+        let still = jpeg_with_xmp(&format!(
+            "<rdf:Description GCamera:MicroVideoOffset=\"{}\"/>",
+            video.len()
+        ));
+        dir.write("src/PXL_1.MP.jpg", [&still[..], &video].concat());
+        dir.write("src/plain.jpg", "image");
+        let script = ScriptedInteraction::approve_all(source.clone(), destination.clone());
+        let options = CliOptions {
+            drop_motion: true,
+            ..CliOptions::default()
+        };
+
+        let progress = run_with(&dir, &script, options);
+
+        assert_eq!(fs::read(destination.join("PXL_1.MP.jpg")).unwrap(), still);
+        assert_eq!(
+            fs::read(source.join("PXL_1.MP.jpg")).unwrap().len(),
+            still.len() + video.len()
+        );
+        assert!(!source.join("plain.jpg").exists());
+        // Both copies are verified, and only the plain image is deleted
+        assert!(
+            script.prompts().contains(&Prompt::ConfirmDelete(2)),
+            "{:?}",
+            progress.messages()
+        );
+        assert!(progress
+            .messages()
+            .iter()
+            .any(|message| message.starts_with("Deleted 1 original files")));
+    }
+}
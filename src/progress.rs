@@ -0,0 +1,196 @@
+//! Progress events emitted by the scan, copy, verify and delete stages.
+//!
+//! Library functions never print; they report what they are doing as
+//! [`ProgressEvent`]s to a [`ProgressListener`]. A front-end decides how to
+//! render them - [`ConsoleProgress`] prints them much like a log, and any
+//! closure taking a `&ProgressEvent` can be used as a listener.
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::media::MediaKind;
+
+/// A stage of the import pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Scan,
+    Copy,
+    Verify,
+    Delete,
+}
+
+/// Something that happened while an operation was running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// A stage began; the totals are zero when not known in advance
+    StageStarted {
+        stage: Stage,
+        total_files: usize,
+        total_bytes: u64,
+    },
+    /// A stage ended
+    StageFinished {
+        stage: Stage,
+        succeeded: usize,
+        failed: usize,
+    },
+    /// The scan found a media file (path relative to the source folder)
+    FileDiscovered {
+        path: PathBuf,
+        kind: MediaKind,
+        size: u64,
+        files_found: usize,
+    },
+    /// A file was copied
+    FileCopied {
+        source: PathBuf,
+        destination: PathBuf,
+        bytes: u64,
+        completed: usize,
+        total: usize,
+    },
+    /// A copy was confirmed identical to its original
+    FileVerified {
+        source: PathBuf,
+        destination: PathBuf,
+        completed: usize,
+        total: usize,
+    },
+    /// An original was deleted
+    FileDeleted {
+        source: PathBuf,
+        completed: usize,
+        total: usize,
+    },
+    /// A stage could not process a file
+    FileFailed {
+        stage: Stage,
+        path: PathBuf,
+        message: String,
+    },
+    /// A stage stopped starting new files because it was cancelled
+    Cancelled(Stage),
+    /// A file or folder was deliberately left alone
+    Skipped { path: PathBuf, reason: String },
+    /// An empty folder was removed from the source after deletion
+    DirectoryRemoved(PathBuf),
+    /// A general status message
+    Message(String),
+    /// A problem that does not stop the operation
+    Warning(String),
+    /// A problem that stops the current run
+    Error(String),
+}
+
+/// Receives progress events. Events may arrive from several threads at once.
+pub trait ProgressListener: Sync {
+    fn on_event(&self, event: &ProgressEvent);
+
+    /// Report a general status message
+    fn message(&self, message: String) {
+        self.on_event(&ProgressEvent::Message(message));
+    }
+
+    /// Report a problem that does not stop the operation
+    fn warn(&self, message: String) {
+        self.on_event(&ProgressEvent::Warning(message));
+    }
+
+    /// Report a problem that stops the current run
+    fn error(&self, message: String) {
+        self.on_event(&ProgressEvent::Error(message));
+    }
+}
+
+impl<F> ProgressListener for F
+where
+    F: Fn(&ProgressEvent) + Sync,
+{
+    fn on_event(&self, event: &ProgressEvent) {
+        self(event)
+    }
+}
+
+/// Ignores all progress events
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoProgress;
+
+impl ProgressListener for NoProgress {
+    fn on_event(&self, _event: &ProgressEvent) {}
+}
+
+/// Prints progress events to the console
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConsoleProgress;
+
+impl ProgressListener for ConsoleProgress {
+    fn on_event(&self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::StageStarted { stage, .. } => match stage {
+                Stage::Scan => println!("Scanning media files and calculating total size..."),
+                Stage::Copy => {}
+                Stage::Verify => println!("Verifying copied files..."),
+                Stage::Delete => println!("Deleting original files..."),
+            },
+            ProgressEvent::StageFinished {
+                stage, succeeded, ..
+            } => match stage {
+                // Move to the next line after the running file count
+                Stage::Scan if *succeeded > 0 => println!(),
+                Stage::Verify => println!("Verified {} files.", succeeded),
+                _ => {}
+            },
+            ProgressEvent::FileDiscovered { files_found, .. } => {
+                print!("\rFiles found: {}", files_found);
+                io::stdout().flush().unwrap_or(());
+            }
+            ProgressEvent::FileCopied {
+                source,
+                destination,
+                completed,
+                total,
+                ..
+            } => println!(
+                "({}/{}) Copied: {} -> {}",
+                completed,
+                total,
+                source.display(),
+                destination.display()
+            ),
+            ProgressEvent::FileVerified {
+                destination,
+                completed,
+                total,
+                ..
+            } => println!(
+                "({}/{}) Verified: {}",
+                completed,
+                total,
+                destination.display()
+            ),
+            ProgressEvent::FileDeleted {
+                source,
+                completed,
+                total,
+            } => println!("({}/{}) Deleted: {}", completed, total, source.display()),
+            ProgressEvent::FileFailed { path, message, .. } => {
+                eprintln!("Warning: '{}': {}", path.display(), message)
+            }
+            ProgressEvent::Cancelled(stage) => match stage {
+                Stage::Scan => eprintln!("Scan cancelled."),
+                Stage::Copy => eprintln!("Copy cancelled; files not yet started were left alone."),
+                Stage::Verify => eprintln!("Verification cancelled."),
+                Stage::Delete => eprintln!("Deletion cancelled; remaining originals were kept."),
+            },
+            ProgressEvent::Skipped { path, reason } => {
+                println!("Skipping {}: {}", path.display(), reason)
+            }
+            ProgressEvent::DirectoryRemoved(path) => {
+                println!("Removed empty directory: {}", path.display())
+            }
+            ProgressEvent::Message(message) => println!("{}", message),
+            ProgressEvent::Warning(message) => eprintln!("Warning: {}", message),
+            ProgressEvent::Error(message) => eprintln!("Error: {}", message),
+        }
+    }
+}
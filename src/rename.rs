@@ -0,0 +1,410 @@
+//! Rename templates.
+//!
+//! A template gives the name of every copy, such as
+//! `{date:%Y%m%d_%H%M%S}_{camera_model}_{seq:04}.{ext_lower}`. Text outside
+//! braces is kept as it is and `{{` and `}}` stand for single braces; the
+//! fields are:
+//!
+//! - `{date:FORMAT}`: the capture time, with `%Y`, `%y`, `%m`, `%d`, `%H`,
+//!   `%M`, `%S` and `%%` in `FORMAT`; `{date}` is `{date:%Y%m%d_%H%M%S}`
+//! - `{camera_model}`: the camera model recorded in the file
+//! - `{orig_stem}`: the original name without its extension
+//! - `{ext}` and `{ext_lower}`: the original extension, as it is or in
+//!   lowercase
+//! - `{seq}` and `{seq:04}`: the position of the file in the import,
+//!   counting from 1, optionally padded with zeros to a width
+//! - `{hash8}`: the first 8 hex digits of the SHA-256 of the contents
+//!
+//! Fields a file has no value for are filled in with `unknown`.
+
+use std::path::Path;
+
+use crate::metadata::{CaptureTime, MediaMetadata};
+
+/// Date format of a `{date}` field without one
+const DEFAULT_DATE_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+/// Stands in for a field a file has no value for
+const UNKNOWN_VALUE: &str = "unknown";
+
+/// What a template can say about a media file
+pub struct NameFields<'a> {
+    /// Path of the original within the source folder
+    pub relative_path: &'a Path,
+    /// Metadata read from the original
+    pub metadata: &'a MediaMetadata,
+    /// SHA-256 of the contents, if the template needs it and it was read
+    pub hash: Option<[u8; 32]>,
+    /// Position of the file in the import, counting from 1
+    pub sequence: u64,
+}
+
+/// The widest {seq:WIDTH}, as many digits as the largest sequence number has
+const MAX_SEQUENCE_WIDTH: usize = 20;
+
+/// A parsed rename template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Text(String),
+    Date(String),
+    CameraModel,
+    OriginalStem,
+    Extension,
+    ExtensionLower,
+    Sequence(usize),
+    Hash8,
+}
+
+impl RenameTemplate {
+    /// Parse a template as given on the command line
+    pub fn from_spec(spec: &str) -> Result<RenameTemplate, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = spec.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => {
+                                return Err(format!("Unclosed '{{' in rename template '{}'", spec))
+                            }
+                        }
+                    }
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(parse_field(&field)?);
+                }
+                '}' => return Err(format!("Unmatched '}}' in rename template '{}'", spec)),
+                '/' | '\\' => {
+                    return Err(format!(
+                        "Rename template '{}' must be a file name, not a path",
+                        spec
+                    ))
+                }
+                _ => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+
+        if parts.is_empty() {
+            return Err("The rename template is empty".to_string());
+        }
+        Ok(RenameTemplate { parts })
+    }
+
+    /// Whether the template uses the metadata of the files
+    pub fn uses_metadata(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, TemplatePart::Date(_) | TemplatePart::CameraModel))
+    }
+
+    /// Whether the template uses the hash of the file contents
+    pub fn uses_hash(&self) -> bool {
+        self.parts.contains(&TemplatePart::Hash8)
+    }
+
+    /// Whether the template numbers the files in the order they are planned
+    pub fn uses_sequence(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, TemplatePart::Sequence(_)))
+    }
+
+    /// The name of a copy
+    pub fn render(&self, fields: &NameFields) -> String {
+        let path = fields.relative_path;
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+
+        let mut name = String::new();
+        for part in &self.parts {
+            let value = match part {
+                TemplatePart::Text(text) => {
+                    name.push_str(text);
+                    continue;
+                }
+                TemplatePart::Date(format) => match &fields.metadata.capture_time {
+                    Some(time) => format_date(format, time),
+                    None => UNKNOWN_VALUE.to_string(),
+                },
+                TemplatePart::CameraModel => fields
+                    .metadata
+                    .camera_model
+                    .clone()
+                    .unwrap_or_else(|| UNKNOWN_VALUE.to_string()),
+                TemplatePart::OriginalStem => stem.to_string(),
+                TemplatePart::Extension => extension.to_string(),
+                TemplatePart::ExtensionLower => extension.to_lowercase(),
+                TemplatePart::Sequence(width) => format!("{:0width$}", fields.sequence),
+                TemplatePart::Hash8 => match fields.hash {
+                    Some(hash) => hash[..4].iter().map(|b| format!("{:02x}", b)).collect(),
+                    None => UNKNOWN_VALUE.to_string(),
+                },
+            };
+            name.push_str(&file_name_safe(&value));
+        }
+
+        // An original without an extension leaves a trailing dot
+        safe_name(&name).unwrap_or_else(|| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+}
+
+/// Parse the inside of a `{...}` field
+fn parse_field(field: &str) -> Result<TemplatePart, String> {
+    let (name, argument) = match field.split_once(':') {
+        Some((name, argument)) => (name, Some(argument)),
+        None => (field, None),
+    };
+
+    let part = match (name, argument) {
+        ("date", None) => TemplatePart::Date(DEFAULT_DATE_FORMAT.to_string()),
+        ("date", Some(format)) => {
+            check_date_format(format)?;
+            TemplatePart::Date(format.to_string())
+        }
+        ("camera_model", None) => TemplatePart::CameraModel,
+        ("orig_stem", None) => TemplatePart::OriginalStem,
+        ("ext", None) => TemplatePart::Extension,
+        ("ext_lower", None) => TemplatePart::ExtensionLower,
+        ("seq", None) => TemplatePart::Sequence(0),
+        ("seq", Some(width)) => match width.parse() {
+            Ok(width) if width <= MAX_SEQUENCE_WIDTH => TemplatePart::Sequence(width),
+            Ok(_) => {
+                return Err(format!(
+                    "The width of {{seq:...}} can be at most {}",
+                    MAX_SEQUENCE_WIDTH
+                ))
+            }
+            Err(_) => return Err(format!("Invalid width '{}' in {{seq:...}}", width)),
+        },
+        ("hash8", None) => TemplatePart::Hash8,
+        ("camera_model" | "orig_stem" | "ext" | "ext_lower" | "hash8", Some(_)) => {
+            return Err(format!("The field {{{}}} takes no format", name))
+        }
+        _ => {
+            return Err(format!(
+                "Unknown field {{{}}} in rename template; use date, camera_model, orig_stem, \
+                 ext, ext_lower, seq or hash8",
+                field
+            ))
+        }
+    };
+    Ok(part)
+}
+
+/// Check that a date format only uses the directives [`format_date`] knows
+fn check_date_format(format: &str) -> Result<(), String> {
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            match chars.next() {
+                Some('Y' | 'y' | 'm' | 'd' | 'H' | 'M' | 'S' | '%') => {}
+                Some(other) => {
+                    return Err(format!("Unknown directive '%{}' in date format", other))
+                }
+                None => return Err("Date format ends with '%'".to_string()),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fill in a date format, which has been checked by [`check_date_format`]
+fn format_date(format: &str, time: &CaptureTime) -> String {
+    let mut formatted = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => formatted.push_str(&format!("{:04}", time.year)),
+            Some('y') => formatted.push_str(&format!("{:02}", time.year % 100)),
+            Some('m') => formatted.push_str(&format!("{:02}", time.month)),
+            Some('d') => formatted.push_str(&format!("{:02}", time.day)),
+            Some('H') => formatted.push_str(&format!("{:02}", time.hour)),
+            Some('M') => formatted.push_str(&format!("{:02}", time.minute)),
+            Some('S') => formatted.push_str(&format!("{:02}", time.second)),
+            Some(other) => formatted.push(other),
+            None => {}
+        }
+    }
+    formatted
+}
+
+/// A value as a whole file or folder name: made safe by [`file_name_safe`],
+/// without the trailing dots and spaces Windows drops, and `None` if that
+/// leaves nothing, or only `.` or `..`
+pub fn safe_name(value: &str) -> Option<String> {
+    let name = file_name_safe(value);
+    let name = name.trim_end_matches(['.', ' ']);
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// A field value with the characters that are not allowed in file names
+/// replaced
+pub fn file_name_safe(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(spec: &str, path: &str, metadata: &MediaMetadata, sequence: u64) -> String {
+        let template = RenameTemplate::from_spec(spec).unwrap();
+        let mut hash = [0xFF; 32];
+        hash[..4].copy_from_slice(&[0xAB, 0xCD, 0x01, 0x23]);
+        template.render(&NameFields {
+            relative_path: Path::new(path),
+            metadata,
+            hash: Some(hash),
+            sequence,
+        })
+    }
+
+    fn canon() -> MediaMetadata {
+        MediaMetadata {
+            capture_time: CaptureTime::parse("2024:05:07 09:03:02"),
+            camera_model: Some("Canon EOS R5".to_string()),
+            ..MediaMetadata::default()
+        }
+    }
+
+    #[test]
+    fn fields_are_filled_in() {
+        assert_eq!(
+            render(
+                "{date:%Y%m%d_%H%M%S}_{camera_model}_{seq:04}.{ext_lower}",
+                "DCIM/IMG_0001.JPG",
+                &canon(),
+                7
+            ),
+            "20240507_090302_Canon EOS R5_0007.jpg"
+        );
+        assert_eq!(
+            render("{date}-{orig_stem}.{ext}", "IMG_0001.JPG", &canon(), 1),
+            "20240507_090302-IMG_0001.JPG"
+        );
+        assert_eq!(
+            render(
+                "{date:%y-%m-%d %%}_{hash8}_{seq}.{ext}",
+                "a.png",
+                &canon(),
+                12
+            ),
+            "24-05-07 %_abcd0123_12.png"
+        );
+        assert_eq!(
+            render("{{{orig_stem}}}.{ext}", "a.png", &canon(), 1),
+            "{a}.png"
+        );
+    }
+
+    #[test]
+    fn missing_values_and_unsafe_characters() {
+        let metadata = MediaMetadata {
+            camera_model: Some("E-M1 Mark II: <pro>".to_string()),
+            ..MediaMetadata::default()
+        };
+        assert_eq!(
+            render("{date}_{camera_model}.{ext}", "P1.ORF", &metadata, 1),
+            "unknown_E-M1 Mark II_ _pro_.ORF"
+        );
+
+        // An original without an extension leaves no trailing dot
+        assert_eq!(render("{orig_stem}.{ext}", "README", &canon(), 1), "README");
+        // Nothing left of the name keeps the original one
+        assert_eq!(render("{ext}", "dir/README", &canon(), 1), "README");
+        let dots = MediaMetadata {
+            camera_model: Some("..".to_string()),
+            ..MediaMetadata::default()
+        };
+        assert_eq!(render("{camera_model}", "a.jpg", &dots, 1), "a.jpg");
+    }
+
+    #[test]
+    fn what_a_template_needs() {
+        let template = RenameTemplate::from_spec("{orig_stem}_{seq}.{ext}").unwrap();
+        assert!(!template.uses_metadata());
+        assert!(!template.uses_hash());
+        let template = RenameTemplate::from_spec("{camera_model}_{hash8}").unwrap();
+        assert!(template.uses_metadata());
+        assert!(template.uses_hash());
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        for (spec, message) in [
+            ("", "The rename template is empty"),
+            ("{date", "Unclosed '{' in rename template '{date'"),
+            ("a}b", "Unmatched '}' in rename template 'a}b'"),
+            ("{seq:wide}", "Invalid width 'wide' in {seq:...}"),
+            ("{seq:21}", "The width of {seq:...} can be at most 20"),
+            ("{seq:1000000}", "The width of {seq:...} can be at most 20"),
+            ("{ext:upper}", "The field {ext} takes no format"),
+            ("{date:%Q}", "Unknown directive '%Q' in date format"),
+            ("{date:%Y%}", "Date format ends with '%'"),
+            (
+                "{date}/{orig_stem}",
+                "Rename template '{date}/{orig_stem}' must be a file name, not a path",
+            ),
+        ] {
+            assert_eq!(
+                RenameTemplate::from_spec(spec),
+                Err(message.to_string()),
+                "{}",
+                spec
+            );
+        }
+        assert!(RenameTemplate::from_spec("{size}")
+            .unwrap_err()
+            .starts_with("Unknown field {size}"));
+    }
+
+    #[test]
+    fn safe_names() {
+        assert_eq!(safe_name(" Canon EOS R5 ").as_deref(), Some("Canon EOS R5"));
+        assert_eq!(safe_name("a/b\\c").as_deref(), Some("a_b_c"));
+        assert_eq!(safe_name("Foo. ."), Some("Foo".to_string()));
+        assert_eq!(safe_name(".."), None);
+        assert_eq!(safe_name("."), None);
+        assert_eq!(safe_name("   "), None);
+    }
+}
@@ -0,0 +1,337 @@
+//! Structured reports of copy and delete operations.
+//!
+//! This module describes what happened to every file an operation touched,
+//! so callers can act on failures, renames and skipped files instead of
+//! relying on a bare success count.
+
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::cancel::CANCELLED;
+use crate::file_ops::{format_bytes, CompanionCopy, CopiedFile};
+use crate::progress::ProgressListener;
+
+/// The kind of operation a report describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Copy,
+    Delete,
+}
+
+/// What happened to a single file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    /// The operation succeeded
+    Done,
+    /// The operation was attempted and failed
+    Failed {
+        kind: io::ErrorKind,
+        message: String,
+    },
+    /// The file was deliberately left alone
+    Skipped { reason: String },
+}
+
+/// The outcome of an operation on one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOutcome {
+    /// Full path of the original file
+    pub source: PathBuf,
+    /// Where the file was (or was going to be) copied to
+    pub destination: Option<PathBuf>,
+    /// The name the copy would have had if it had not been renamed to avoid
+    /// a conflict
+    pub renamed_from: Option<PathBuf>,
+    /// Companion files copied or deleted along with this one
+    pub companions: Vec<CompanionCopy>,
+    pub status: FileStatus,
+    /// Bytes written for this file
+    pub bytes: u64,
+    /// Time spent on this file
+    pub elapsed: Duration,
+}
+
+impl FileOutcome {
+    /// A failed outcome carrying the error's kind and message
+    pub fn failed(source: PathBuf, destination: Option<PathBuf>, error: &io::Error) -> FileOutcome {
+        FileOutcome {
+            source,
+            destination,
+            renamed_from: None,
+            companions: Vec::new(),
+            status: FileStatus::Failed {
+                kind: error.kind(),
+                message: error.to_string(),
+            },
+            bytes: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// A skipped outcome with the reason it was skipped
+    pub fn skipped(source: PathBuf, destination: Option<PathBuf>, reason: &str) -> FileOutcome {
+        FileOutcome {
+            source,
+            destination,
+            renamed_from: None,
+            companions: Vec::new(),
+            status: FileStatus::Skipped {
+                reason: reason.to_string(),
+            },
+            bytes: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.status == FileStatus::Done
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self.status, FileStatus::Failed { .. })
+    }
+
+    pub fn is_skipped(&self) -> bool {
+        matches!(self.status, FileStatus::Skipped { .. })
+    }
+}
+
+/// Everything that happened during one copy or delete operation
+#[derive(Debug, Clone)]
+pub struct OperationReport {
+    pub operation: Operation,
+    /// One outcome per file, in the order the files were given
+    pub outcomes: Vec<FileOutcome>,
+    /// Wall-clock time of the whole operation
+    pub elapsed: Duration,
+    /// The operation was cancelled; files it did not start are skipped with
+    /// the reason [`CANCELLED`]
+    pub cancelled: bool,
+}
+
+impl OperationReport {
+    pub fn new(operation: Operation) -> OperationReport {
+        OperationReport {
+            operation,
+            outcomes: Vec::new(),
+            elapsed: Duration::ZERO,
+            cancelled: false,
+        }
+    }
+
+    /// Files the operation succeeded on
+    pub fn succeeded(&self) -> impl Iterator<Item = &FileOutcome> {
+        self.outcomes.iter().filter(|outcome| outcome.is_done())
+    }
+
+    /// Files the operation failed on
+    pub fn failed(&self) -> impl Iterator<Item = &FileOutcome> {
+        self.outcomes.iter().filter(|outcome| outcome.is_failed())
+    }
+
+    /// Files that were deliberately left alone
+    pub fn skipped(&self) -> impl Iterator<Item = &FileOutcome> {
+        self.outcomes.iter().filter(|outcome| outcome.is_skipped())
+    }
+
+    /// Successful copies that were renamed to avoid a conflict
+    pub fn renamed(&self) -> impl Iterator<Item = &FileOutcome> {
+        self.succeeded()
+            .filter(|outcome| outcome.renamed_from.is_some())
+    }
+
+    pub fn success_count(&self) -> usize {
+        self.succeeded().count()
+    }
+
+    pub fn failure_count(&self) -> usize {
+        self.failed().count()
+    }
+
+    pub fn skipped_count(&self) -> usize {
+        self.skipped().count()
+    }
+
+    /// Files that were not started because the operation was cancelled
+    pub fn not_started(&self) -> impl Iterator<Item = &FileOutcome> {
+        self.skipped().filter(|outcome| {
+            matches!(&outcome.status, FileStatus::Skipped { reason } if reason == CANCELLED)
+        })
+    }
+
+    /// Total bytes written by the operation
+    pub fn bytes_transferred(&self) -> u64 {
+        self.outcomes.iter().map(|outcome| outcome.bytes).sum()
+    }
+
+    /// The successful copies, for verification and deletion
+    pub fn manifest(&self) -> Vec<CopiedFile> {
+        self.succeeded()
+            .filter_map(|outcome| {
+                outcome.destination.as_ref().map(|destination| CopiedFile {
+                    source: outcome.source.clone(),
+                    destination: destination.clone(),
+                    companions: outcome.companions.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Report a short summary followed by any renames, skips and failures
+    pub fn report_summary(&self, progress: &dyn ProgressListener) {
+        progress.message(match self.operation {
+            Operation::Copy => format!(
+                "Copied {} files ({}) in {:.1?}.",
+                self.success_count(),
+                format_bytes(self.bytes_transferred()),
+                self.elapsed
+            ),
+            Operation::Delete => format!(
+                "Deleted {} original files in {:.1?}.",
+                self.success_count(),
+                self.elapsed
+            ),
+        });
+
+        if self.cancelled {
+            progress.warn(format!(
+                "Cancelled; {} files were not started.",
+                self.not_started().count()
+            ));
+        }
+
+        for outcome in self.renamed() {
+            if let (Some(renamed_from), Some(destination)) =
+                (&outcome.renamed_from, &outcome.destination)
+            {
+                progress.message(format!(
+                    "  Renamed: {} -> {} ({} was taken)",
+                    outcome.source.display(),
+                    destination.display(),
+                    renamed_from.display()
+                ));
+            }
+        }
+
+        for outcome in &self.outcomes {
+            match &outcome.status {
+                FileStatus::Done => {}
+                // Already counted above rather than listed one by one
+                FileStatus::Skipped { reason } if reason == CANCELLED => {}
+                FileStatus::Skipped { reason } => progress.message(format!(
+                    "  Skipped: {} ({})",
+                    outcome.source.display(),
+                    reason
+                )),
+                FileStatus::Failed { kind, message } => progress.warn(format!(
+                    "Failed: {} ({:?}: {})",
+                    outcome.source.display(),
+                    kind,
+                    message
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::RecordingProgress;
+
+    fn done(name: &str, bytes: u64) -> FileOutcome {
+        FileOutcome {
+            source: PathBuf::from("src").join(name),
+            destination: Some(PathBuf::from("dst").join(name)),
+            renamed_from: None,
+            companions: Vec::new(),
+            status: FileStatus::Done,
+            bytes,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    fn copy_report() -> OperationReport {
+        let sidecar = CompanionCopy {
+            source: PathBuf::from("src/a.xmp"),
+            destination: PathBuf::from("dst/a.xmp"),
+        };
+        let renamed = FileOutcome {
+            destination: Some(PathBuf::from("dst/b_1.jpg")),
+            renamed_from: Some(PathBuf::from("dst/b.jpg")),
+            ..done("b.jpg", 20)
+        };
+        let error = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+
+        let mut report = OperationReport::new(Operation::Copy);
+        report.outcomes = vec![
+            FileOutcome {
+                companions: vec![sidecar],
+                ..done("a.jpg", 10)
+            },
+            renamed,
+            FileOutcome::failed(PathBuf::from("src/c.jpg"), None, &error),
+            FileOutcome::skipped(PathBuf::from("src/d.jpg"), None, "symbolic link"),
+            FileOutcome::skipped(PathBuf::from("src/e.jpg"), None, CANCELLED),
+        ];
+        report.cancelled = true;
+        report
+    }
+
+    #[test]
+    fn outcomes_are_counted_by_status() {
+        let report = copy_report();
+
+        assert_eq!(report.success_count(), 2);
+        assert_eq!(report.failure_count(), 1);
+        assert_eq!(report.skipped_count(), 2);
+        assert_eq!(report.not_started().count(), 1);
+        assert_eq!(report.renamed().count(), 1);
+        assert_eq!(report.bytes_transferred(), 30);
+        assert_eq!(
+            report.failed().next().unwrap().status,
+            FileStatus::Failed {
+                kind: io::ErrorKind::PermissionDenied,
+                message: "denied".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn the_manifest_lists_successful_copies_with_their_companions() {
+        let manifest = copy_report().manifest();
+
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest[0].source, PathBuf::from("src/a.jpg"));
+        assert_eq!(manifest[0].companions.len(), 1);
+        assert_eq!(manifest[1].destination, PathBuf::from("dst/b_1.jpg"));
+    }
+
+    #[test]
+    fn the_summary_lists_renames_skips_and_failures() {
+        let progress = RecordingProgress::default();
+
+        copy_report().report_summary(&progress);
+
+        let path = |path: &str| PathBuf::from(path).display().to_string();
+        assert_eq!(
+            progress.messages(),
+            [
+                "Copied 2 files (30 B) in 0.0ns.".to_string(),
+                "Warning: Cancelled; 1 files were not started.".to_string(),
+                format!(
+                    "  Renamed: {} -> {} ({} was taken)",
+                    path("src/b.jpg"),
+                    path("dst/b_1.jpg"),
+                    path("dst/b.jpg")
+                ),
+                format!(
+                    "Warning: Failed: {} (PermissionDenied: denied)",
+                    path("src/c.jpg")
+                ),
+                format!("  Skipped: {} (symbolic link)", path("src/d.jpg")),
+            ]
+        );
+    }
+}
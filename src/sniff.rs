@@ -0,0 +1,427 @@
+//! Media detection by file content.
+//!
+//! This module reads the first bytes of a file and recognises the common
+//! image, RAW and video containers by their magic numbers, so files with a
+//! missing or misleading extension are still classified correctly.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::media::MediaKind;
+
+/// Number of bytes read from the start of a file for sniffing. Enough for
+/// three MPEG transport stream packets and an SVG root element after a
+/// typical XML prolog.
+pub const SNIFF_LENGTH: usize = 1024;
+
+/// A file format recognised from its content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    Jpeg,
+    Png,
+    Gif,
+    Bmp,
+    WebP,
+    /// TIFF, which is also the container of most camera RAW formats
+    /// (NEF, ARW, DNG, PEF and others)
+    Tiff,
+    Heif,
+    Avif,
+    Mng,
+    Ico,
+    Svg,
+    CanonCr2,
+    CanonCr3,
+    CanonCrw,
+    OlympusOrf,
+    PanasonicRw2,
+    FujifilmRaf,
+    MinoltaMrw,
+    SigmaX3f,
+    /// MP4, M4V, 3GP and other ISO base media files
+    Mp4,
+    QuickTime,
+    Avi,
+    /// Matroska and WebM
+    Matroska,
+    MpegTransportStream,
+    MpegProgramStream,
+    Asf,
+    Flv,
+    OggVideo,
+    RealMedia,
+    Mxf,
+    RedRaw,
+}
+
+impl ContentType {
+    /// The kind of media file this format holds
+    pub fn kind(&self) -> MediaKind {
+        match self {
+            ContentType::Jpeg
+            | ContentType::Png
+            | ContentType::Gif
+            | ContentType::Bmp
+            | ContentType::WebP
+            | ContentType::Tiff
+            | ContentType::Heif
+            | ContentType::Avif => MediaKind::Image,
+            ContentType::Ico | ContentType::Svg => MediaKind::Vector,
+            ContentType::CanonCr2
+            | ContentType::CanonCr3
+            | ContentType::CanonCrw
+            | ContentType::OlympusOrf
+            | ContentType::PanasonicRw2
+            | ContentType::FujifilmRaf
+            | ContentType::MinoltaMrw
+            | ContentType::SigmaX3f => MediaKind::Raw,
+            ContentType::Mng
+            | ContentType::Mp4
+            | ContentType::QuickTime
+            | ContentType::Avi
+            | ContentType::Matroska
+            | ContentType::MpegTransportStream
+            | ContentType::MpegProgramStream
+            | ContentType::Asf
+            | ContentType::Flv
+            | ContentType::OggVideo
+            | ContentType::RealMedia => MediaKind::Video,
+            ContentType::Mxf | ContentType::RedRaw => MediaKind::ProVideo,
+        }
+    }
+
+    /// The kind of a file with this content, taking the kind its extension
+    /// names into account. TIFF and QuickTime are containers for several
+    /// kinds - a NEF is a TIFF and a BRAW a QuickTime movie - so for those the
+    /// extension decides within the same family.
+    pub fn refine_kind(&self, extension_kind: Option<MediaKind>) -> MediaKind {
+        match (self, extension_kind) {
+            (ContentType::Tiff, Some(MediaKind::Raw)) => MediaKind::Raw,
+            (ContentType::Mp4 | ContentType::QuickTime, Some(MediaKind::ProVideo)) => {
+                MediaKind::ProVideo
+            }
+            _ => self.kind(),
+        }
+    }
+
+    /// Short lowercase name of the format
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContentType::Jpeg => "jpeg",
+            ContentType::Png => "png",
+            ContentType::Gif => "gif",
+            ContentType::Bmp => "bmp",
+            ContentType::WebP => "webp",
+            ContentType::Tiff => "tiff",
+            ContentType::Heif => "heif",
+            ContentType::Avif => "avif",
+            ContentType::Mng => "mng",
+            ContentType::Ico => "ico",
+            ContentType::Svg => "svg",
+            ContentType::CanonCr2 => "cr2",
+            ContentType::CanonCr3 => "cr3",
+            ContentType::CanonCrw => "crw",
+            ContentType::OlympusOrf => "orf",
+            ContentType::PanasonicRw2 => "rw2",
+            ContentType::FujifilmRaf => "raf",
+            ContentType::MinoltaMrw => "mrw",
+            ContentType::SigmaX3f => "x3f",
+            ContentType::Mp4 => "mp4",
+            ContentType::QuickTime => "mov",
+            ContentType::Avi => "avi",
+            ContentType::Matroska => "mkv",
+            ContentType::MpegTransportStream => "ts",
+            ContentType::MpegProgramStream => "mpg",
+            ContentType::Asf => "asf",
+            ContentType::Flv => "flv",
+            ContentType::OggVideo => "ogv",
+            ContentType::RealMedia => "rm",
+            ContentType::Mxf => "mxf",
+            ContentType::RedRaw => "r3d",
+        }
+    }
+}
+
+/// Read the start of a file and recognise its format
+pub fn sniff_file(path: &Path) -> io::Result<Option<ContentType>> {
+    let file = File::open(path)?;
+    let mut header = Vec::with_capacity(SNIFF_LENGTH);
+    file.take(SNIFF_LENGTH as u64).read_to_end(&mut header)?;
+    Ok(sniff_content(&header))
+}
+
+/// Recognise a media format from the first bytes of a file
+pub fn sniff_content(header: &[u8]) -> Option<ContentType> {
+    // Formats with a fixed signature at the very start
+    const SIGNATURES: &[(&[u8], ContentType)] = &[
+        (b"\xFF\xD8\xFF", ContentType::Jpeg),
+        (b"\x89PNG\r\n\x1A\n", ContentType::Png),
+        (b"\x8AMNG\r\n\x1A\n", ContentType::Mng),
+        (b"GIF87a", ContentType::Gif),
+        (b"GIF89a", ContentType::Gif),
+        (b"II\x1A\x00\x00\x00HEAPCCDR", ContentType::CanonCrw),
+        (b"IIRO", ContentType::OlympusOrf),
+        (b"IIRS", ContentType::OlympusOrf),
+        (b"IIU\x00", ContentType::PanasonicRw2),
+        (b"FUJIFILMCCD-RAW", ContentType::FujifilmRaf),
+        (b"\x00MRM", ContentType::MinoltaMrw),
+        (b"FOVb", ContentType::SigmaX3f),
+        (b"\x1A\x45\xDF\xA3", ContentType::Matroska),
+        (
+            b"\x30\x26\xB2\x75\x8E\x66\xCF\x11\xA6\xD9\x00\xAA\x00\x62\xCE\x6C",
+            ContentType::Asf,
+        ),
+        (b"FLV\x01", ContentType::Flv),
+        (b".RMF", ContentType::RealMedia),
+        (b"\x06\x0E\x2B\x34\x02\x05\x01\x01", ContentType::Mxf),
+        (b"\x00\x00\x01\xBA", ContentType::MpegProgramStream),
+        (b"\x00\x00\x01\xB3", ContentType::MpegProgramStream),
+    ];
+
+    if let Some((_, content_type)) = SIGNATURES
+        .iter()
+        .find(|(signature, _)| header.starts_with(signature))
+    {
+        return Some(*content_type);
+    }
+
+    if header.starts_with(b"II*\x00") || header.starts_with(b"MM\x00*") {
+        // CR2 is the one TIFF-based RAW that marks itself in the header
+        return if header.get(8..10) == Some(b"CR") {
+            Some(ContentType::CanonCr2)
+        } else {
+            Some(ContentType::Tiff)
+        };
+    }
+
+    if header.starts_with(b"RIFF") {
+        return match header.get(8..12) {
+            Some(b"WEBP") => Some(ContentType::WebP),
+            Some(b"AVI ") => Some(ContentType::Avi),
+            _ => None,
+        };
+    }
+
+    if header.starts_with(b"OggS") {
+        // Ogg also carries audio; only a Theora stream makes it a video
+        return contains(header, b"\x80theora").then_some(ContentType::OggVideo);
+    }
+
+    if header.get(4..8) == Some(b"RED1") || header.get(4..8) == Some(b"RED2") {
+        return Some(ContentType::RedRaw);
+    }
+
+    if let Some(content_type) = sniff_iso_media(header) {
+        return Some(content_type);
+    }
+
+    if is_bmp(header) {
+        return Some(ContentType::Bmp);
+    }
+
+    if is_ico(header) {
+        return Some(ContentType::Ico);
+    }
+
+    if is_transport_stream(header) {
+        return Some(ContentType::MpegTransportStream);
+    }
+
+    if is_svg(header) {
+        return Some(ContentType::Svg);
+    }
+
+    None
+}
+
+/// Recognise ISO base media (MP4, HEIF, CR3) and classic QuickTime files
+fn sniff_iso_media(header: &[u8]) -> Option<ContentType> {
+    let box_type = header.get(4..8)?;
+
+    if box_type == b"ftyp" {
+        let major_brand = header.get(8..12)?;
+        return match major_brand {
+            b"heic" | b"heix" | b"hevc" | b"hevx" | b"heim" | b"heis" | b"mif1" | b"msf1" => {
+                Some(ContentType::Heif)
+            }
+            b"avif" | b"avis" => Some(ContentType::Avif),
+            b"crx " => Some(ContentType::CanonCr3),
+            b"qt  " => Some(ContentType::QuickTime),
+            // Audio-only MPEG-4 files
+            b"M4A " | b"M4B " | b"M4P " | b"F4A " | b"F4B " => None,
+            _ => Some(ContentType::Mp4),
+        };
+    }
+
+    // QuickTime files written before ftyp was introduced start with one of
+    // these atoms instead
+    match box_type {
+        b"moov" | b"mdat" | b"wide" | b"pnot" | b"skip" | b"free" => Some(ContentType::QuickTime),
+        _ => None,
+    }
+}
+
+/// A BMP file header followed by one of the known DIB header sizes
+fn is_bmp(header: &[u8]) -> bool {
+    header.starts_with(b"BM")
+        && matches!(
+            header.get(14..18),
+            Some([12 | 40 | 52 | 56 | 64 | 108 | 124, 0, 0, 0])
+        )
+}
+
+/// An ICONDIR header with at least one image
+fn is_ico(header: &[u8]) -> bool {
+    header.starts_with(b"\x00\x00\x01\x00")
+        && matches!(header.get(4..6), Some(count) if count != [0, 0])
+}
+
+/// Three consecutive 188-byte transport packets, or 192-byte M2TS packets
+/// with their 4-byte timestamp prefix
+fn is_transport_stream(header: &[u8]) -> bool {
+    let synced = |offset: usize, packet: usize| {
+        (0..3).all(|index| header.get(offset + index * packet) == Some(&0x47))
+    };
+    synced(0, 188) || synced(4, 192)
+}
+
+/// An XML or SVG document whose root element is `<svg`
+fn is_svg(header: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(header);
+    let text = text.trim_start_matches('\u{FEFF}').trim_start();
+    (text.starts_with("<?xml") || text.starts_with("<!--") || text.starts_with("<svg"))
+        && text.contains("<svg")
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+/// Whether files with this (lowercase) extension have a signature that
+/// [`sniff_content`] recognises. Such files can be checked by content; for
+/// the others only the extension is available.
+pub fn has_known_signature(extension: &str) -> bool {
+    matches!(
+        extension,
+        "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tiff" | "tif" | "webp" | "svg" | "ico"
+            | "heic" | "heif" | "avif" | "mng"
+            // TIFF-based RAW formats
+            | "dng" | "nef" | "nrw" | "arw" | "srf" | "sr2" | "pef" | "ptx" | "3fr" | "fff"
+            | "iiq" | "dcr" | "kdc" | "k25" | "srw" | "mef" | "mos" | "rwl" | "erf"
+            // RAW formats with their own signature
+            | "cr2" | "cr3" | "crw" | "orf" | "rw2" | "raf" | "mrw" | "x3f" | "r3d"
+            // Video containers
+            | "mp4" | "m4v" | "m4p" | "mov" | "qt" | "3gp" | "3g2" | "f4v" | "avi" | "mkv"
+            | "webm" | "ts" | "m2ts" | "mts" | "mpg" | "mpeg" | "mpe" | "vob" | "m2v" | "wmv"
+            | "asf" | "flv" | "ogv" | "rm" | "rmvb" | "mxf"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An ISO base media file starting with an `ftyp` box of `brand`
+    fn ftyp(brand: &[u8; 4]) -> Vec<u8> {
+        [&[0, 0, 0, 0x18][..], b"ftyp", brand, b"\0\0\0\0isom"].concat()
+    }
+
+    #[test]
+    fn fixed_signatures() {
+        for (header, expected) in [
+            (&b"\xFF\xD8\xFF\xE1....Exif"[..], ContentType::Jpeg),
+            (b"\x89PNG\r\n\x1A\n\0\0\0\x0DIHDR", ContentType::Png),
+            (b"GIF89a\x01\x00", ContentType::Gif),
+            (b"II*\x00\x10\x00\x00\x00CR\x02\x00", ContentType::CanonCr2),
+            (b"II*\x00\x08\x00\x00\x00\x10\x00", ContentType::Tiff),
+            (b"MM\x00*\x00\x00\x00\x08", ContentType::Tiff),
+            (b"IIU\x00\x18\x00\x00\x00", ContentType::PanasonicRw2),
+            (b"FUJIFILMCCD-RAW 0201", ContentType::FujifilmRaf),
+            (b"RIFF\x24\x00\x00\x00WEBPVP8 ", ContentType::WebP),
+            (b"RIFF\x24\x00\x00\x00AVI LIST", ContentType::Avi),
+            (
+                b"\x1A\x45\xDF\xA3\x9F\x42\x86\x81\x01",
+                ContentType::Matroska,
+            ),
+            (b"\x00\x00\x01\xBA\x44\x00", ContentType::MpegProgramStream),
+            (b"\x00\x00\x00\x10RED2\x00\x00", ContentType::RedRaw),
+        ] {
+            assert_eq!(sniff_content(header), Some(expected), "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn iso_media_by_brand() {
+        assert_eq!(sniff_content(&ftyp(b"heic")), Some(ContentType::Heif));
+        assert_eq!(sniff_content(&ftyp(b"avif")), Some(ContentType::Avif));
+        assert_eq!(sniff_content(&ftyp(b"crx ")), Some(ContentType::CanonCr3));
+        assert_eq!(sniff_content(&ftyp(b"qt  ")), Some(ContentType::QuickTime));
+        assert_eq!(sniff_content(&ftyp(b"isom")), Some(ContentType::Mp4));
+        assert_eq!(sniff_content(&ftyp(b"M4A ")), None);
+        assert_eq!(
+            sniff_content(b"\x00\x00\x00\x08wide\x00\x00\x00\x00mdat"),
+            Some(ContentType::QuickTime)
+        );
+    }
+
+    #[test]
+    fn structural_checks() {
+        let mut bmp = b"BM\x36\x00\x00\x00\x00\x00\x00\x00\x36\x00\x00\x00".to_vec();
+        bmp.extend_from_slice(&[40, 0, 0, 0]);
+        assert_eq!(sniff_content(&bmp), Some(ContentType::Bmp));
+        assert_eq!(sniff_content(b"BM plain text"), None);
+
+        assert_eq!(
+            sniff_content(b"\x00\x00\x01\x00\x01\x00\x10\x10"),
+            Some(ContentType::Ico)
+        );
+        assert_eq!(sniff_content(b"\x00\x00\x01\x00\x00\x00"), None);
+
+        let mut ts = vec![0u8; 188 * 3];
+        for packet in ts.chunks_mut(188) {
+            packet[0] = 0x47;
+        }
+        assert_eq!(sniff_content(&ts), Some(ContentType::MpegTransportStream));
+        ts[188] = 0;
+        assert_eq!(sniff_content(&ts), None);
+
+        let svg = "\u{FEFF}<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>";
+        assert_eq!(sniff_content(svg.as_bytes()), Some(ContentType::Svg));
+        assert_eq!(sniff_content(b"<?xml version=\"1.0\"?><html/>"), None);
+
+        let mut ogg = b"OggS\x00\x02".to_vec();
+        assert_eq!(sniff_content(&ogg), None);
+        ogg.extend_from_slice(b"\x80theora");
+        assert_eq!(sniff_content(&ogg), Some(ContentType::OggVideo));
+    }
+
+    #[test]
+    fn unknown_and_short_headers() {
+        assert_eq!(sniff_content(b""), None);
+        assert_eq!(sniff_content(b"\xFF\xD8"), None);
+        assert_eq!(sniff_content(b"plain text, nothing else"), None);
+    }
+
+    #[test]
+    fn containers_defer_to_the_extension_within_their_family() {
+        assert_eq!(
+            ContentType::Tiff.refine_kind(Some(MediaKind::Raw)),
+            MediaKind::Raw
+        );
+        assert_eq!(
+            ContentType::Tiff.refine_kind(Some(MediaKind::Video)),
+            MediaKind::Image
+        );
+        assert_eq!(
+            ContentType::QuickTime.refine_kind(Some(MediaKind::ProVideo)),
+            MediaKind::ProVideo
+        );
+        assert_eq!(
+            ContentType::Jpeg.refine_kind(Some(MediaKind::Raw)),
+            MediaKind::Image
+        );
+    }
+}
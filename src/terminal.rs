@@ -0,0 +1,127 @@
+//! Terminal prompts for headless runs.
+//!
+//! This module asks questions on standard input and provides the
+//! [`TerminalInteraction`] front-end. When input is not a terminal, for
+//! example in a script, yes/no questions are not asked and the given default
+//! is used instead.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+
+use crate::file_ops::format_bytes;
+use crate::interaction::{CopySummary, UserInteraction};
+
+/// Ask a yes/no question, returning `default` for an empty answer or when
+/// there is no terminal to ask on
+pub fn prompt_yes_no(question: &str, default: bool) -> io::Result<bool> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        println!(
+            "{} {}",
+            question,
+            if default {
+                "yes (default)"
+            } else {
+                "no (default)"
+            }
+        );
+        return Ok(default);
+    }
+
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    loop {
+        print!("{} {} ", question, hint);
+        io::stdout().flush()?;
+
+        let mut answer = String::new();
+        if stdin.lock().read_line(&mut answer)? == 0 {
+            // End of input counts as the default answer
+            println!();
+            return Ok(default);
+        }
+
+        match answer.trim().to_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer yes or no."),
+        }
+    }
+}
+
+/// Read one line of input after showing a prompt, returning `None` at end of input
+pub fn prompt_line(question: &str) -> io::Result<Option<String>> {
+    print!("{} ", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        println!();
+        return Ok(None);
+    }
+
+    Ok(Some(answer.trim().to_string()))
+}
+
+/// Asks the user through prompts in the terminal
+#[derive(Debug, Default)]
+pub struct TerminalInteraction;
+
+impl UserInteraction for TerminalInteraction {
+    fn select_folder(&self, title: &str) -> io::Result<Option<PathBuf>> {
+        match prompt_line(&format!("{}:", title))? {
+            Some(path) if !path.is_empty() => Ok(Some(PathBuf::from(path))),
+            _ => Ok(None),
+        }
+    }
+
+    fn confirm_copy(&self, summary: &CopySummary) -> io::Result<bool> {
+        println!(
+            "Ready to copy {} media files ({}), {} available on destination.",
+            summary.file_count,
+            format_bytes(summary.total_size),
+            format_bytes(summary.available_space)
+        );
+        println!("  {}", summary.describe_kinds());
+        if summary.exceeds_available_space() {
+            println!("WARNING: Not enough disk space available!");
+        }
+
+        prompt_yes_no("Proceed with the copy operation?", false)
+    }
+
+    fn confirm_resume(&self, remaining_count: usize, total_count: usize) -> io::Result<bool> {
+        println!(
+            "The destination contains an unfinished import of {} files from this source, {} of which still need attention.",
+            total_count, remaining_count
+        );
+        prompt_yes_no("Resume it where it stopped?", true)
+    }
+
+    fn confirm_verify(&self, file_count: usize) -> io::Result<bool> {
+        prompt_yes_no(
+            &format!("Verify the {} copied files by content hash?", file_count),
+            true,
+        )
+    }
+
+    fn report_verification_failures(&self, _failed_count: usize) -> io::Result<()> {
+        // The failures are already listed in the console output
+        Ok(())
+    }
+
+    fn confirm_delete(&self, file_count: usize) -> io::Result<bool> {
+        prompt_yes_no(
+            &format!(
+                "Delete the {} original files from the source folder? This cannot be undone.",
+                file_count
+            ),
+            false,
+        )
+    }
+
+    fn notify_completion(&self) -> io::Result<()> {
+        println!("Done! All operations completed successfully.");
+        Ok(())
+    }
+}
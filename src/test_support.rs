@@ -0,0 +1,187 @@
+//! Helpers shared by the unit tests.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::progress::{ProgressEvent, ProgressListener};
+
+/// A folder in the system temporary folder, removed with everything in it
+/// when dropped
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "image_mover_test_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write a file at `relative` inside the folder, creating its parents
+    pub fn write(&self, relative: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Default for TempDir {
+    fn default() -> Self {
+        TempDir::new()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A progress listener that keeps every event, and every message, warning
+/// and skipped entry as text
+#[derive(Default)]
+pub struct RecordingProgress {
+    pub events: Mutex<Vec<ProgressEvent>>,
+    pub messages: Mutex<Vec<String>>,
+}
+
+impl RecordingProgress {
+    pub fn events(&self) -> Vec<ProgressEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    pub fn messages(&self) -> Vec<String> {
+        self.messages.lock().unwrap().clone()
+    }
+}
+
+impl ProgressListener for RecordingProgress {
+    fn on_event(&self, event: &ProgressEvent) {
+        self.events.lock().unwrap().push(event.clone());
+        let text = match event {
+            ProgressEvent::Message(message) => message.clone(),
+            ProgressEvent::Warning(message) => format!("Warning: {}", message),
+            ProgressEvent::Error(message) => format!("Error: {}", message),
+            ProgressEvent::Skipped { path, reason } => {
+                format!("Skipped: {} ({})", path.display(), reason)
+            }
+            _ => return,
+        };
+        self.messages.lock().unwrap().push(text);
+    }
+}
+
+/// An ISO media atom of `atom_type` around `body`
+pub fn atom(atom_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    [
+        &((body.len() + 8) as u32).to_be_bytes()[..],
+        atom_type,
+        body,
+    ]
+    .concat()
+}
+
+/// The start of a JPEG whose Apple MakerNote gives `identifier` as its Live
+/// Photo content identifier
+pub fn live_photo_still(identifier: &str, big_endian: bool) -> Vec<u8> {
+    let u16_bytes = |value: u16| {
+        if big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    };
+    let u32_bytes = |value: u32| {
+        if big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    };
+
+    let mut value = identifier.as_bytes().to_vec();
+    value.push(0);
+    let mut maker_note = b"Apple iOS\0\0\x01".to_vec();
+    maker_note.extend_from_slice(if big_endian { b"MM" } else { b"II" });
+    maker_note.extend(u16_bytes(1));
+    maker_note.extend(u16_bytes(0x0011));
+    maker_note.extend(u16_bytes(2));
+    maker_note.extend(u32_bytes(value.len() as u32));
+    if value.len() <= 4 {
+        value.resize(4, 0);
+        maker_note.extend(value);
+    } else {
+        maker_note.extend(u32_bytes(28));
+        maker_note.extend(value);
+    }
+
+    [
+        &b"\xFF\xD8\xFF\xE1\0\0Exif\0\0"[..],
+        &maker_note,
+        b"\xFF\xD9",
+    ]
+    .concat()
+}
+
+/// A QuickTime movie whose metadata gives `identifier` as its Live Photo
+/// content identifier
+pub fn live_photo_movie(identifier: &str) -> Vec<u8> {
+    let key = b"com.apple.quicktime.content.identifier";
+    let mut keys = [0, 0, 0, 0, 0, 0, 0, 1].to_vec();
+    keys.extend_from_slice(&((key.len() + 8) as u32).to_be_bytes());
+    keys.extend_from_slice(b"mdta");
+    keys.extend_from_slice(key);
+    let data = atom(
+        b"data",
+        &[&[0, 0, 0, 1, 0, 0, 0, 0][..], identifier.as_bytes()].concat(),
+    );
+    let meta = atom(
+        b"meta",
+        &[
+            atom(b"hdlr", &[0; 24]),
+            atom(b"keys", &keys),
+            atom(b"ilst", &atom(&1u32.to_be_bytes(), &data)),
+        ]
+        .concat(),
+    );
+    [
+        atom(b"ftyp", b"qt  \0\0\0\0qt  "),
+        atom(b"mdat", b"frames"),
+        atom(b"moov", &meta),
+    ]
+    .concat()
+}
+
+/// A JPEG still with an XMP segment holding `xmp`
+pub fn jpeg_with_xmp(xmp: &str) -> Vec<u8> {
+    let packet = format!(
+        "http://ns.adobe.com/xap/1.0/\0<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">{}</x:xmpmeta>",
+        xmp
+    );
+    [
+        &b"\xFF\xD8\xFF\xE1"[..],
+        &((packet.len() + 2) as u16).to_be_bytes(),
+        packet.as_bytes(),
+        b"\xFF\xDAscan data\xFF\xD9",
+    ]
+    .concat()
+}
+
+/// A short MP4 movie
+pub fn mp4_movie() -> Vec<u8> {
+    [atom(b"ftyp", b"mp42\0\0\0\0isom"), atom(b"mdat", b"frames")].concat()
+}
@@ -0,0 +1,257 @@
+//! Verification of copied media files.
+//!
+//! This module re-reads each copied file and compares its SHA-256 hash with
+//! the original, so that an original is only offered for deletion once its
+//! copy is known to be byte-for-byte identical. A motion photo copied
+//! without its video is compared with the still it starts with.
+
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cancel::CancellationToken;
+use crate::file_ops::CopiedFile;
+use crate::journal::{record_or_warn, FileState, Journal};
+use crate::live_photo::is_motion_photo_still;
+use crate::progress::{ProgressEvent, ProgressListener, Stage};
+
+/// Size of the buffer used when streaming files through the hasher
+const HASH_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// A copied file whose destination could not be confirmed as identical
+#[derive(Debug)]
+pub struct VerificationFailure {
+    pub file: CopiedFile,
+    pub reason: String,
+}
+
+/// Outcome of verifying a copy manifest
+#[derive(Debug, Default)]
+pub struct VerificationResult {
+    /// Copies whose content matches the original
+    pub verified: Vec<CopiedFile>,
+    /// Copies that differ from the original or could not be read back
+    pub failed: Vec<VerificationFailure>,
+}
+
+/// Calculate the SHA-256 hash of a file's contents
+pub fn hash_file(path: &Path) -> io::Result<[u8; 32]> {
+    hash_reader(File::open(path)?)
+}
+
+/// Calculate the SHA-256 hash of everything read from `reader`
+fn hash_reader(mut reader: impl Read) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+/// Compare the source and destination of a single copy and its companions
+fn verify_copy(copied: &CopiedFile) -> Result<(), String> {
+    compare_files(&copied.source, &copied.destination)?;
+    for companion in &copied.companions {
+        compare_files(&companion.source, &companion.destination).map_err(|reason| {
+            format!(
+                "companion '{}': {}",
+                companion.destination.display(),
+                reason
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Compare an original with its copy by content hash, or a link copied as a
+/// link by its target
+fn compare_files(source: &Path, destination: &Path) -> Result<(), String> {
+    if destination.is_symlink() {
+        let source_target =
+            fs::read_link(source).map_err(|e| format!("cannot read original link: {}", e))?;
+        let dest_target =
+            fs::read_link(destination).map_err(|e| format!("cannot read copied link: {}", e))?;
+        return if source_target == dest_target {
+            Ok(())
+        } else {
+            Err("link target does not match the original".to_string())
+        };
+    }
+
+    // A motion photo copied without its video matches the still it starts with
+    let source_hash = if is_motion_photo_still(source, destination) {
+        let length = fs::metadata(destination)
+            .map_err(|e| format!("cannot read copy: {}", e))?
+            .len();
+        File::open(source).and_then(|file| hash_reader(file.take(length)))
+    } else {
+        hash_file(source)
+    }
+    .map_err(|e| format!("cannot read original: {}", e))?;
+    let dest_hash = hash_file(destination).map_err(|e| format!("cannot read copy: {}", e))?;
+
+    if source_hash == dest_hash {
+        Ok(())
+    } else {
+        Err("content hash does not match the original".to_string())
+    }
+}
+
+/// Verify every entry of a copy manifest by content hash.
+///
+/// Entries are checked in parallel. Anything that does not match, or cannot
+/// be read back, is reported and returned in `failed`; only entries in
+/// `verified` should be passed on for deletion. Each result is recorded in the
+/// journal if one is given. Once `cancel` is cancelled no new checks are
+/// started, and the entries left unchecked are in neither list.
+pub fn verify_copies(
+    manifest: &[CopiedFile],
+    journal: Option<&Journal>,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+) -> VerificationResult {
+    let checked_count = AtomicUsize::new(0);
+
+    progress.on_event(&ProgressEvent::StageStarted {
+        stage: Stage::Verify,
+        total_files: manifest.len(),
+        total_bytes: 0,
+    });
+
+    let results: Vec<(CopiedFile, Result<(), String>)> = manifest
+        .par_iter()
+        .filter(|_| !cancel.is_cancelled())
+        .map(|copied| {
+            let result = verify_copy(copied);
+            let count = checked_count.fetch_add(1, Ordering::Relaxed) + 1;
+
+            let state = if result.is_ok() {
+                FileState::Verified
+            } else {
+                FileState::VerifyFailed
+            };
+            record_or_warn(journal, &copied.source, state, progress);
+
+            match &result {
+                Ok(()) => progress.on_event(&ProgressEvent::FileVerified {
+                    source: copied.source.clone(),
+                    destination: copied.destination.clone(),
+                    completed: count,
+                    total: manifest.len(),
+                }),
+                Err(reason) => progress.on_event(&ProgressEvent::FileFailed {
+                    stage: Stage::Verify,
+                    path: copied.destination.clone(),
+                    message: format!("Verification failed: {}", reason),
+                }),
+            }
+
+            (copied.clone(), result)
+        })
+        .collect();
+
+    if cancel.is_cancelled() {
+        progress.on_event(&ProgressEvent::Cancelled(Stage::Verify));
+    }
+
+    let mut outcome = VerificationResult::default();
+    for (file, result) in results {
+        match result {
+            Ok(()) => outcome.verified.push(file),
+            Err(reason) => outcome.failed.push(VerificationFailure { file, reason }),
+        }
+    }
+
+    progress.on_event(&ProgressEvent::StageFinished {
+        stage: Stage::Verify,
+        succeeded: outcome.verified.len(),
+        failed: outcome.failed.len(),
+    });
+
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_ops::CompanionCopy;
+    use crate::progress::NoProgress;
+    use crate::test_support::TempDir;
+
+    fn copied(dir: &TempDir, name: &str, original: &str, copy: &str) -> CopiedFile {
+        CopiedFile {
+            source: dir.write(&format!("src/{}", name), original),
+            destination: dir.write(&format!("dst/{}", name), copy),
+            companions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn copies_that_differ_from_their_original_fail() {
+        let dir = TempDir::new();
+        let same = copied(&dir, "same.jpg", "contents", "contents");
+        let changed = copied(&dir, "changed.jpg", "contents", "Contents");
+        let mut gone = copied(&dir, "gone.jpg", "contents", "contents");
+        gone.destination = dir.path().join("dst/missing.jpg");
+
+        let result = verify_copies(
+            &[same.clone(), changed.clone(), gone.clone()],
+            None,
+            &CancellationToken::new(),
+            &NoProgress,
+        );
+
+        assert_eq!(result.verified, [same]);
+        let mut failed: Vec<_> = result
+            .failed
+            .iter()
+            .map(|failure| (failure.file.clone(), failure.reason.as_str()))
+            .collect();
+        failed.sort_by(|a, b| a.0.source.cmp(&b.0.source));
+        assert_eq!(failed.len(), 2);
+        assert_eq!(failed[0].0, changed);
+        assert_eq!(failed[0].1, "content hash does not match the original");
+        assert_eq!(failed[1].0, gone);
+        assert!(failed[1].1.starts_with("cannot read copy"));
+    }
+
+    #[test]
+    fn a_changed_companion_fails_its_file() {
+        let dir = TempDir::new();
+        let mut file = copied(&dir, "a.jpg", "image", "image");
+        file.companions.push(CompanionCopy {
+            source: dir.write("src/a.xmp", "<x/>"),
+            destination: dir.write("dst/a.xmp", "<y/>"),
+        });
+
+        let result = verify_copies(&[file], None, &CancellationToken::new(), &NoProgress);
+
+        assert!(result.verified.is_empty());
+        assert!(result.failed[0].reason.starts_with("companion '"));
+    }
+
+    #[test]
+    fn hash_is_the_sha256_of_the_contents() {
+        let dir = TempDir::new();
+        let path = dir.write("abc", "abc");
+        let hex: String = hash_file(&path)
+            .unwrap()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        assert_eq!(
+            hex,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,370 @@
+//! Capture metadata of video files.
+//!
+//! MP4, QuickTime and the other ISO base media formats (M4V, 3GP, F4V, ...)
+//! record when a movie was made in their `mvhd` and `tkhd` atoms, and Apple
+//! devices also store the local capture time under the QuickTime metadata
+//! key `com.apple.quicktime.creationdate`, next to the make and model of the
+//! device. Matroska and WebM files may record
+//! it as the `DateUTC` element of their segment information. Files are told
+//! apart by their contents, so the extension does not matter; other video
+//! formats give no metadata here.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::atoms::{atoms, find_atom, quicktime_metadata_value, read_top_level_atom};
+use crate::metadata::{CaptureTime, MediaMetadata};
+
+/// Largest `moov` atom read from a movie
+const MAX_MOVIE_HEADER_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// Number of bytes at the start of a Matroska file searched for the segment
+/// information, which muxers write before the first cluster
+const MATROSKA_HEADER_LENGTH: u64 = 1024 * 1024;
+
+/// QuickTime metadata key holding the local capture time of a movie
+const CREATION_DATE_KEY: &[u8] = b"com.apple.quicktime.creationdate";
+/// QuickTime metadata key holding the maker of the recording device
+const MAKE_KEY: &[u8] = b"com.apple.quicktime.make";
+/// QuickTime metadata key holding the model of the recording device
+const MODEL_KEY: &[u8] = b"com.apple.quicktime.model";
+
+/// Seconds from 1904-01-01, the epoch of `mvhd` and `tkhd` times, to
+/// 1970-01-01
+const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// Seconds from 1970-01-01 to 2001-01-01, the epoch of Matroska dates
+const MATROSKA_EPOCH_OFFSET: i64 = 978_307_200;
+
+/// Types of atoms an ISO base media file may start with
+const FIRST_ATOM_TYPES: &[&[u8; 4]] = &[b"ftyp", b"moov", b"mdat", b"wide", b"free", b"skip"];
+
+/// EBML ID of the header every Matroska file starts with
+const EBML_HEADER_ID: u32 = 0x1A45_DFA3;
+/// EBML ID of the segment holding the rest of a Matroska file
+const SEGMENT_ID: u32 = 0x1853_8067;
+/// EBML ID of the segment information
+const INFO_ID: u32 = 0x1549_A966;
+/// EBML ID of the creation date in the segment information
+const DATE_UTC_ID: u32 = 0x4461;
+/// EBML ID of a cluster of frames; the metadata is all before the first
+const CLUSTER_ID: u32 = 0x1F43_B675;
+
+/// Read the metadata of the video at `path`. Videos this module cannot read
+/// give empty metadata.
+pub fn read_video_metadata(path: &Path) -> io::Result<MediaMetadata> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; 8];
+    let read = file.read(&mut header)?;
+    let header = &header[..read];
+    file.seek(SeekFrom::Start(0))?;
+
+    if header.starts_with(&EBML_HEADER_ID.to_be_bytes()) {
+        let mut data = Vec::new();
+        file.take(MATROSKA_HEADER_LENGTH).read_to_end(&mut data)?;
+        Ok(MediaMetadata {
+            capture_time: matroska_capture_time(&data),
+            ..MediaMetadata::default()
+        })
+    } else if header.get(4..8).is_some_and(|atom_type| {
+        FIRST_ATOM_TYPES
+            .iter()
+            .any(|wanted| &wanted[..] == atom_type)
+    }) {
+        let moov = read_top_level_atom(&mut file, b"moov", MAX_MOVIE_HEADER_LENGTH)?;
+        Ok(moov.map(|moov| movie_metadata(&moov)).unwrap_or_default())
+    } else {
+        Ok(MediaMetadata::default())
+    }
+}
+
+/// The metadata of a movie from its `moov` atom
+fn movie_metadata(moov: &[u8]) -> MediaMetadata {
+    let text = |key: &[u8]| {
+        let value = quicktime_metadata_value(moov, key)?;
+        let text = std::str::from_utf8(value)
+            .ok()?
+            .trim_end_matches('\0')
+            .trim();
+        (!text.is_empty()).then(|| text.to_string())
+    };
+    MediaMetadata {
+        capture_time: movie_capture_time(moov),
+        camera_make: text(MAKE_KEY),
+        camera_model: text(MODEL_KEY),
+        camera_serial: None,
+    }
+}
+
+/// The capture time of a movie from its `moov` atom: the QuickTime creation
+/// date if there is one, as it is in local time like EXIF dates, or else the
+/// creation time of the movie or of its first track that has one
+fn movie_capture_time(moov: &[u8]) -> Option<CaptureTime> {
+    let creation_date = quicktime_metadata_value(moov, CREATION_DATE_KEY)
+        .and_then(|value| std::str::from_utf8(value).ok())
+        .and_then(CaptureTime::parse);
+    if creation_date.is_some() {
+        return creation_date;
+    }
+
+    let movie_header = find_atom(moov, b"mvhd").and_then(header_creation_time);
+    movie_header.or_else(|| {
+        atoms(moov)
+            .into_iter()
+            .filter(|(atom_type, _)| atom_type == b"trak")
+            .filter_map(|(_, track)| find_atom(track, b"tkhd"))
+            .find_map(header_creation_time)
+    })
+}
+
+/// The creation time in an `mvhd` or `tkhd` atom. Both start with a version
+/// and flags, then the time in seconds since 1904 as 32 bits, or as 64 bits
+/// in version 1. Zero means it was never set.
+fn header_creation_time(header: &[u8]) -> Option<CaptureTime> {
+    let seconds = match header.first()? {
+        0 => u32::from_be_bytes(header.get(4..8)?.try_into().ok()?) as u64,
+        1 => u64::from_be_bytes(header.get(4..12)?.try_into().ok()?),
+        _ => return None,
+    };
+    if seconds == 0 {
+        return None;
+    }
+    CaptureTime::from_unix_seconds(i64::try_from(seconds).ok()? - QUICKTIME_EPOCH_OFFSET)
+}
+
+/// The `DateUTC` of a Matroska file, in nanoseconds since 2001, from the
+/// segment information
+fn matroska_capture_time(data: &[u8]) -> Option<CaptureTime> {
+    let (_, segment) = ebml_elements(data)
+        .into_iter()
+        .find(|(id, _)| *id == SEGMENT_ID)?;
+    let (_, info) = ebml_elements(segment)
+        .into_iter()
+        .take_while(|(id, _)| *id != CLUSTER_ID)
+        .find(|(id, _)| *id == INFO_ID)?;
+    let (_, date) = ebml_elements(info)
+        .into_iter()
+        .find(|(id, _)| *id == DATE_UTC_ID)?;
+
+    let nanoseconds = i64::from_be_bytes(date.try_into().ok()?);
+    let seconds = nanoseconds.div_euclid(1_000_000_000);
+    CaptureTime::from_unix_seconds(MATROSKA_EPOCH_OFFSET.checked_add(seconds)?)
+}
+
+/// The child elements of an EBML element body, as ID and body. Bodies are
+/// cut short at the end of `data`, which may be only the start of a file,
+/// and an element of unknown size runs to the end of it.
+fn ebml_elements(data: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let Some((id, id_length)) = read_vint(&data[offset..], true) else {
+            break;
+        };
+        let Some((size, size_length)) = read_vint(&data[offset + id_length..], false) else {
+            break;
+        };
+        let body_start = offset + id_length + size_length;
+        let unknown_size = size == (1 << (7 * size_length)) - 1;
+        let end = if unknown_size {
+            data.len()
+        } else {
+            body_start.saturating_add(size as usize).min(data.len())
+        };
+
+        found.push((id as u32, &data[body_start.min(end)..end]));
+        offset = end;
+    }
+
+    found
+}
+
+/// Read an EBML variable-length integer: its length is given by the leading
+/// zero bits of the first byte, followed by a marker bit. IDs keep the
+/// marker, sizes do not.
+fn read_vint(data: &[u8], keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let length = first.leading_zeros() as usize + 1;
+    if length > 8 {
+        return None;
+    }
+    let bytes = data.get(..length)?;
+
+    let mut value = if keep_marker {
+        first as u64
+    } else {
+        (first & (0xFF_u16 >> length) as u8) as u64
+    };
+    for &byte in &bytes[1..] {
+        value = value << 8 | byte as u64;
+    }
+    Some((value, length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    /// 2024-05-17 14:03:22 UTC
+    const UNIX_SECONDS: i64 = 1_715_954_602;
+
+    fn expected_time() -> Option<CaptureTime> {
+        CaptureTime::parse("2024-05-17T14:03:22")
+    }
+
+    fn atom(atom_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        [
+            &((body.len() + 8) as u32).to_be_bytes()[..],
+            atom_type,
+            body,
+        ]
+        .concat()
+    }
+
+    /// A QuickTime `meta` atom holding `entries` as key and text value
+    fn quicktime_meta(entries: &[(&[u8], &str)]) -> Vec<u8> {
+        let mut keys = [0, 0, 0, 0].to_vec();
+        keys.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        let mut items = Vec::new();
+        for (index, (key, value)) in entries.iter().enumerate() {
+            keys.extend_from_slice(&((key.len() + 8) as u32).to_be_bytes());
+            keys.extend_from_slice(b"mdta");
+            keys.extend_from_slice(key);
+            let data = atom(
+                b"data",
+                &[&[0, 0, 0, 1, 0, 0, 0, 0][..], value.as_bytes()].concat(),
+            );
+            items.extend(atom(&(index as u32 + 1).to_be_bytes(), &data));
+        }
+        atom(
+            b"meta",
+            &[
+                atom(b"hdlr", &[0; 24]),
+                atom(b"keys", &keys),
+                atom(b"ilst", &items),
+            ]
+            .concat(),
+        )
+    }
+
+    /// A movie header of `version` 0 or 1 created at `seconds` since 1904
+    fn header(atom_type: &[u8; 4], version: u8, seconds: u64) -> Vec<u8> {
+        let mut body = vec![version, 0, 0, 0];
+        match version {
+            0 => body.extend_from_slice(&(seconds as u32).to_be_bytes()),
+            _ => body.extend_from_slice(&seconds.to_be_bytes()),
+        }
+        body.extend_from_slice(&[0; 16]);
+        atom(atom_type, &body)
+    }
+
+    fn movie(moov: &[u8]) -> Vec<u8> {
+        [
+            atom(b"ftyp", b"qt  \0\0\0\0qt  "),
+            atom(b"moov", moov),
+            atom(b"mdat", b"frames"),
+        ]
+        .concat()
+    }
+
+    fn metadata_of(file: &[u8]) -> MediaMetadata {
+        let dir = TempDir::new();
+        read_video_metadata(&dir.write("video", file)).unwrap()
+    }
+
+    fn quicktime_seconds() -> u64 {
+        (UNIX_SECONDS + QUICKTIME_EPOCH_OFFSET) as u64
+    }
+
+    #[test]
+    fn apple_creation_date_make_and_model() {
+        let moov = [
+            header(b"mvhd", 0, quicktime_seconds() + 3600),
+            quicktime_meta(&[
+                (MAKE_KEY, "Apple"),
+                (MODEL_KEY, "iPhone 15 Pro"),
+                (CREATION_DATE_KEY, "2024-05-17T14:03:22+0200"),
+            ]),
+        ]
+        .concat();
+
+        assert_eq!(
+            metadata_of(&movie(&moov)),
+            MediaMetadata {
+                capture_time: expected_time(),
+                camera_make: Some("Apple".to_string()),
+                camera_model: Some("iPhone 15 Pro".to_string()),
+                camera_serial: None,
+            }
+        );
+    }
+
+    #[test]
+    fn movie_and_track_header_times() {
+        let moov = header(b"mvhd", 0, quicktime_seconds());
+        assert_eq!(metadata_of(&movie(&moov)).capture_time, expected_time());
+
+        // An unset movie time falls back to the first track with one
+        let moov = [
+            header(b"mvhd", 0, 0),
+            atom(b"trak", &header(b"tkhd", 0, 0)),
+            atom(b"trak", &header(b"tkhd", 1, quicktime_seconds())),
+        ]
+        .concat();
+        assert_eq!(metadata_of(&movie(&moov)).capture_time, expected_time());
+
+        let moov = header(b"mvhd", 0, 0);
+        assert_eq!(metadata_of(&movie(&moov)).capture_time, None);
+    }
+
+    /// An EBML element with a one-byte size
+    fn element(id: &[u8], body: &[u8]) -> Vec<u8> {
+        [id, &[0x80 | body.len() as u8], body].concat()
+    }
+
+    fn matroska(segment_children: &[u8]) -> Vec<u8> {
+        let header = element(
+            &EBML_HEADER_ID.to_be_bytes(),
+            &element(&[0x42, 0x82], b"webm"),
+        );
+        // A segment of unknown size, as written while recording
+        let segment = [
+            &SEGMENT_ID.to_be_bytes()[..],
+            &[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+            segment_children,
+        ]
+        .concat();
+        [header, segment].concat()
+    }
+
+    fn date_utc() -> Vec<u8> {
+        let nanoseconds = (UNIX_SECONDS - MATROSKA_EPOCH_OFFSET) * 1_000_000_000;
+        element(&[0x44, 0x61], &nanoseconds.to_be_bytes())
+    }
+
+    #[test]
+    fn matroska_date_utc() {
+        let info = element(&INFO_ID.to_be_bytes(), &date_utc());
+        let file = matroska(&[element(&[0x11, 0x4D, 0x9B, 0x74], &[]), info].concat());
+        assert_eq!(metadata_of(&file).capture_time, expected_time());
+
+        // Segment information after the first cluster is not looked at
+        let cluster = element(&CLUSTER_ID.to_be_bytes(), &[0xE7, 0x81, 0x00]);
+        let info = element(&INFO_ID.to_be_bytes(), &date_utc());
+        let file = matroska(&[cluster, info].concat());
+        assert_eq!(metadata_of(&file).capture_time, None);
+    }
+
+    #[test]
+    fn other_files_give_no_metadata() {
+        assert_eq!(metadata_of(b"RIFF\0\0\0\0AVI "), MediaMetadata::default());
+        assert_eq!(metadata_of(b""), MediaMetadata::default());
+        // A movie cut off inside its header
+        let mut file = movie(&header(b"mvhd", 0, quicktime_seconds()));
+        file.truncate(30);
+        assert_eq!(metadata_of(&file), MediaMetadata::default());
+    }
+}
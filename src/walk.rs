@@ -0,0 +1,607 @@
+//! Parallel walk of the source tree.
+//!
+//! This module walks a folder tree, reading sibling folders in parallel, and
+//! hands the files of each folder, with their metadata, to a visitor. It
+//! decides what the walk does with each directory entry it meets: folders
+//! are descended, regular files passed on, symbolic links handled as the
+//! [`SymlinkPolicy`] asks, and sockets, FIFOs and device nodes skipped.
+//! Entry types are taken from the directory listing itself, so nothing is
+//! followed unless the policy says so. The destination folder and anything
+//! excluded by patterns are left out here, once for every scan.
+
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fs::{self, FileType};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::cancel::CancellationToken;
+use crate::filter::PathFilter;
+use crate::progress::{ProgressEvent, ProgressListener};
+
+/// What the walk does with symbolic links
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Leave links alone and report them as skipped
+    #[default]
+    Skip,
+    /// Follow links to files and folders. A folder reached a second time,
+    /// through a link cycle or a second link, is not walked again. Deleting
+    /// a file found inside a linked folder deletes the real file, but
+    /// deleting a file reached through a link to it only removes the link.
+    Follow,
+    /// Copy links to files as links, with the same target. Links to folders
+    /// are not followed.
+    Copy,
+}
+
+impl SymlinkPolicy {
+    /// Parse a policy name as given on the command line
+    pub fn from_name(name: &str) -> Option<SymlinkPolicy> {
+        match name {
+            "skip" => Some(SymlinkPolicy::Skip),
+            "follow" => Some(SymlinkPolicy::Follow),
+            "copy" => Some(SymlinkPolicy::Copy),
+            _ => None,
+        }
+    }
+}
+
+/// What the walk does with one directory entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalkEntry {
+    /// A folder to descend into
+    Directory,
+    /// A regular file, or a link to a file that is followed
+    File,
+    /// A link to a file, to be copied as a link
+    FileLink,
+    /// Left alone, for the given reason
+    Skipped(String),
+}
+
+/// Identity of a folder, the same however it was reached
+#[cfg(unix)]
+type DirectoryId = (u64, u64);
+#[cfg(not(unix))]
+type DirectoryId = PathBuf;
+
+#[cfg(unix)]
+fn directory_id(path: &Path) -> io::Result<DirectoryId> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn directory_id(path: &Path) -> io::Result<DirectoryId> {
+    path.canonicalize()
+}
+
+/// Settings for a walk of the source tree
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WalkOptions<'a> {
+    /// What the walk does with symbolic links
+    pub symlinks: SymlinkPolicy,
+    /// A folder left out of the walk, such as a destination inside the source
+    pub exclude_path: Option<&'a Path>,
+    /// Patterns and ignore files leaving files and folders out
+    pub filter: Option<&'a PathFilter>,
+    /// Leave skipped entries unreported, for a walk that is not the scan
+    pub quiet: bool,
+}
+
+/// A file met by the walk
+#[derive(Debug, Clone)]
+pub struct WalkedFile {
+    /// Full path of the file
+    pub path: PathBuf,
+    /// Path relative to the root of the walk
+    pub relative_path: PathBuf,
+    /// Metadata of the file, or of the link itself if it is copied as a link
+    pub metadata: fs::Metadata,
+    /// Whether this is a link to be copied as a link
+    pub is_link: bool,
+}
+
+/// The files found directly in one folder
+#[derive(Debug, Clone)]
+pub struct WalkedFolder {
+    /// Full path of the folder
+    pub path: PathBuf,
+    /// Path relative to the root of the walk, empty for the root itself
+    pub relative_path: PathBuf,
+    pub files: Vec<WalkedFile>,
+}
+
+/// Walk the tree under `root`, calling `visit` on every folder with the files
+/// found directly in it.
+///
+/// Sibling folders are walked in parallel, and the results come back in walk
+/// order: each folder before the folders inside it. Folders that cannot be
+/// read are reported to `progress` and left out. Stops with an
+/// [`io::ErrorKind::Interrupted`] error once `cancel` is cancelled, and with
+/// the first error returned by `visit`.
+pub fn walk<T, F>(
+    root: &Path,
+    options: WalkOptions,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressListener,
+    visit: F,
+) -> io::Result<Vec<T>>
+where
+    T: Send,
+    F: Fn(WalkedFolder) -> io::Result<T> + Sync,
+{
+    let walker = Walker {
+        root,
+        options,
+        // The destination is recognised by identity, worked out once
+        excluded: options
+            .exclude_path
+            .and_then(|exclude_path| directory_id(exclude_path).ok()),
+        visited: Mutex::default(),
+        cancel,
+        progress,
+        visit: &visit,
+    };
+    if let Ok(root_id) = directory_id(root) {
+        walker.lock_visited().insert(root_id);
+    }
+    walker.walk_folder(root.to_path_buf())
+}
+
+/// The state shared by the threads of one walk
+struct Walker<'a, F> {
+    root: &'a Path,
+    options: WalkOptions<'a>,
+    excluded: Option<DirectoryId>,
+    /// Folders entered so far, so none is walked twice
+    visited: Mutex<HashSet<DirectoryId>>,
+    cancel: &'a CancellationToken,
+    progress: &'a dyn ProgressListener,
+    visit: &'a F,
+}
+
+impl<T, F> Walker<'_, F>
+where
+    T: Send,
+    F: Fn(WalkedFolder) -> io::Result<T> + Sync,
+{
+    fn walk_folder(&self, path: PathBuf) -> io::Result<Vec<T>> {
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.progress.warn(format!(
+                    "Cannot access directory '{}': {}",
+                    path.display(),
+                    e
+                ));
+                return Ok(Vec::new()); // Continue processing other directories
+            }
+        };
+
+        let mut files = Vec::new();
+        let mut folders = Vec::new();
+        for entry in entries {
+            self.cancel.check()?;
+
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.progress.warn(format!(
+                        "Cannot read directory entry in '{}': {}",
+                        path.display(),
+                        e
+                    ));
+                    continue; // Skip this entry and continue with others
+                }
+            };
+            let entry_path = entry.path();
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(e) => {
+                    self.progress.warn(format!(
+                        "Cannot read the type of '{}': {}",
+                        entry_path.display(),
+                        e
+                    ));
+                    continue;
+                }
+            };
+
+            match classify_entry(&entry_path, file_type, self.options.symlinks) {
+                WalkEntry::Skipped(reason) => self.skip(entry_path, reason),
+                WalkEntry::Directory => {
+                    if self.enter(&entry_path) {
+                        folders.push(entry_path);
+                    }
+                }
+                walk_entry => {
+                    let is_link = walk_entry == WalkEntry::FileLink;
+                    if let Some(file) = self.file(entry, entry_path, file_type, is_link) {
+                        files.push(file);
+                    }
+                }
+            }
+        }
+
+        // Sorted, so files come in the same order on every run and on every
+        // file system; sequence numbers in rename templates depend on it
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        folders.sort();
+
+        let folder = WalkedFolder {
+            relative_path: self.relative_path(&path).to_path_buf(),
+            path,
+            files,
+        };
+        let (own, nested) = rayon::join(
+            || (self.visit)(folder),
+            || {
+                folders
+                    .into_par_iter()
+                    .map(|folder| self.walk_folder(folder))
+                    .collect::<io::Result<Vec<_>>>()
+            },
+        );
+
+        let mut results = vec![own?];
+        results.extend(nested?.into_iter().flatten());
+        Ok(results)
+    }
+
+    /// Whether to walk the folder at `path`, reporting it if not
+    fn enter(&self, path: &Path) -> bool {
+        let id = match directory_id(path) {
+            Ok(id) => id,
+            Err(e) => {
+                self.skip(path.to_path_buf(), format!("cannot identify folder: {}", e));
+                return false;
+            }
+        };
+
+        // Skip the destination directory if it's within the source to prevent infinite recursion
+        if self.excluded.as_ref() == Some(&id) {
+            self.skip(path.to_path_buf(), "destination directory".to_string());
+            return false;
+        }
+        if self.is_filtered(path, true) {
+            self.skip(path.to_path_buf(), "excluded by pattern".to_string());
+            return false;
+        }
+        if self
+            .options
+            .filter
+            .is_some_and(|filter| !filter.may_include_below(self.relative_path(path)))
+        {
+            self.skip(
+                path.to_path_buf(),
+                "no include pattern matches inside".to_string(),
+            );
+            return false;
+        }
+        if !self.lock_visited().insert(id) {
+            self.skip(path.to_path_buf(), "folder already visited".to_string());
+            return false;
+        }
+        true
+    }
+
+    /// The file at `path`, unless it is filtered out or cannot be read
+    fn file(
+        &self,
+        entry: fs::DirEntry,
+        path: PathBuf,
+        file_type: FileType,
+        is_link: bool,
+    ) -> Option<WalkedFile> {
+        if self.is_filtered(&path, false) {
+            return None;
+        }
+
+        // The listing has the metadata of links themselves, so a followed
+        // link is looked up again
+        let metadata = if file_type.is_symlink() && !is_link {
+            fs::metadata(&path)
+        } else {
+            entry.metadata()
+        };
+        match metadata {
+            Ok(metadata) => Some(WalkedFile {
+                relative_path: self.relative_path(&path).to_path_buf(),
+                path,
+                metadata,
+                is_link,
+            }),
+            Err(e) => {
+                self.progress
+                    .warn(format!("Cannot read '{}': {}", path.display(), e));
+                None
+            }
+        }
+    }
+
+    /// Whether the exclude patterns or an ignore file leave `path` out
+    fn is_filtered(&self, path: &Path, is_dir: bool) -> bool {
+        self.options.filter.is_some_and(|filter| {
+            filter.is_excluded(self.root, self.relative_path(path), is_dir, self.progress)
+        })
+    }
+
+    fn relative_path<'p>(&self, path: &'p Path) -> &'p Path {
+        path.strip_prefix(self.root).unwrap_or(path)
+    }
+
+    fn skip(&self, path: PathBuf, reason: String) {
+        if self.options.quiet {
+            return;
+        }
+        self.progress
+            .on_event(&ProgressEvent::Skipped { path, reason });
+    }
+
+    fn lock_visited(&self) -> std::sync::MutexGuard<'_, HashSet<DirectoryId>> {
+        match self.visited.lock() {
+            Ok(visited) => visited,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// Decide what to do with the entry at `path`, whose type as listed (not
+/// followed) is `file_type`
+pub fn classify_entry(path: &Path, file_type: FileType, policy: SymlinkPolicy) -> WalkEntry {
+    if file_type.is_dir() {
+        return WalkEntry::Directory;
+    }
+    if file_type.is_file() {
+        return WalkEntry::File;
+    }
+    if !file_type.is_symlink() {
+        return WalkEntry::Skipped(describe_special_file(file_type).to_string());
+    }
+
+    if policy == SymlinkPolicy::Skip {
+        return WalkEntry::Skipped("symbolic link".to_string());
+    }
+    let target = match fs::metadata(path) {
+        Ok(target) => target,
+        Err(e) => return WalkEntry::Skipped(format!("broken symbolic link: {}", e)),
+    };
+
+    match policy {
+        SymlinkPolicy::Follow if target.is_dir() => WalkEntry::Directory,
+        SymlinkPolicy::Follow if target.is_file() => WalkEntry::File,
+        SymlinkPolicy::Copy if target.is_dir() => {
+            WalkEntry::Skipped("symbolic link to a folder".to_string())
+        }
+        SymlinkPolicy::Copy if target.is_file() => WalkEntry::FileLink,
+        _ => WalkEntry::Skipped(format!(
+            "symbolic link to a {}",
+            describe_special_file(target.file_type())
+        )),
+    }
+}
+
+/// What kind of file something that is neither a file, folder nor link is
+#[cfg(unix)]
+fn describe_special_file(file_type: FileType) -> &'static str {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_socket() {
+        "socket"
+    } else if file_type.is_fifo() {
+        "FIFO"
+    } else if file_type.is_block_device() {
+        "block device"
+    } else if file_type.is_char_device() {
+        "character device"
+    } else {
+        "special file"
+    }
+}
+
+#[cfg(not(unix))]
+fn describe_special_file(_file_type: FileType) -> &'static str {
+    "special file"
+}
+
+/// Recreate the link at `source` at `destination`, pointing at the same
+/// target
+pub fn copy_link(source: &Path, destination: &Path) -> io::Result<()> {
+    let target = fs::read_link(source)?;
+
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(&target, destination);
+
+    #[cfg(windows)]
+    return std::os::windows::fs::symlink_file(&target, destination);
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (target, destination);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Symbolic links are not supported on this platform",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+    use crate::test_support::{RecordingProgress, TempDir};
+
+    #[test]
+    fn an_error_from_the_visitor_in_a_subfolder_stops_the_walk() {
+        let dir = TempDir::new();
+        dir.write("a.jpg", "a");
+        dir.write("inner/deeper/b.jpg", "b");
+        let progress = RecordingProgress::default();
+
+        let result = walk(
+            dir.path(),
+            WalkOptions::default(),
+            &CancellationToken::new(),
+            &progress,
+            |folder| {
+                if folder.relative_path.ends_with("deeper") {
+                    return Err(io::Error::other("visitor failed"));
+                }
+                Ok(folder.files.len())
+            },
+        );
+
+        assert_eq!(result.unwrap_err().to_string(), "visitor failed");
+        assert!(progress.messages().is_empty());
+    }
+
+    #[test]
+    fn folders_excluded_with_everything_inside_are_not_entered() {
+        let dir = TempDir::new();
+        dir.write("a.jpg", "a");
+        dir.write("DCIM/@eaDir/a.jpg/SYNOPHOTO_THUMB_M.jpg", "thumbnail");
+        dir.write("DCIM/b.jpg", "b");
+        let filter = PathFilter::new(&[], &["**/@eaDir/**".to_string()]).unwrap();
+        let options = WalkOptions {
+            filter: Some(&filter),
+            ..WalkOptions::default()
+        };
+
+        let folders = walk(
+            dir.path(),
+            options,
+            &CancellationToken::new(),
+            &NoProgress,
+            |folder| Ok(folder.relative_path),
+        )
+        .unwrap();
+
+        assert_eq!(folders, [PathBuf::new(), PathBuf::from("DCIM")]);
+    }
+
+    /// Walk a source folder holding a file, a link to a file elsewhere, a
+    /// link to a folder elsewhere, a link back to itself and a FIFO,
+    /// returning the files found and what was reported, with paths relative
+    /// to the source folder
+    #[cfg(unix)]
+    fn walk_links(policy: SymlinkPolicy) -> (Vec<(PathBuf, bool)>, Vec<String>) {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new();
+        let source = dir.path().join("source");
+        dir.write("source/own.jpg", "own");
+        symlink(
+            dir.write("elsewhere/linked.jpg", "linked"),
+            source.join("file_link.jpg"),
+        )
+        .unwrap();
+        dir.write("elsewhere/folder/inside.jpg", "inside");
+        symlink(
+            dir.path().join("elsewhere/folder"),
+            source.join("folder_link"),
+        )
+        .unwrap();
+        symlink(&source, source.join("cycle")).unwrap();
+        let fifo = CString::new(source.join("pipe").as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+        let progress = RecordingProgress::default();
+
+        let mut files: Vec<(PathBuf, bool)> = walk(
+            &source,
+            WalkOptions {
+                symlinks: policy,
+                ..WalkOptions::default()
+            },
+            &CancellationToken::new(),
+            &progress,
+            |folder| {
+                Ok(folder
+                    .files
+                    .into_iter()
+                    .map(|file| (file.relative_path, file.is_link))
+                    .collect::<Vec<_>>())
+            },
+        )
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect();
+        files.sort();
+        let prefix = format!("{}/", source.display());
+        let mut messages: Vec<String> = progress
+            .messages()
+            .iter()
+            .map(|message| message.replace(&prefix, ""))
+            .collect();
+        messages.sort();
+        (files, messages)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skipped_links_are_reported_and_not_walked() {
+        let (files, messages) = walk_links(SymlinkPolicy::Skip);
+
+        assert_eq!(files, [(PathBuf::from("own.jpg"), false)]);
+        assert_eq!(
+            messages,
+            [
+                "Skipped: cycle (symbolic link)",
+                "Skipped: file_link.jpg (symbolic link)",
+                "Skipped: folder_link (symbolic link)",
+                "Skipped: pipe (FIFO)",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn followed_links_are_walked_once() {
+        let (files, messages) = walk_links(SymlinkPolicy::Follow);
+
+        assert_eq!(
+            files,
+            [
+                (PathBuf::from("file_link.jpg"), false),
+                (PathBuf::from("folder_link/inside.jpg"), false),
+                (PathBuf::from("own.jpg"), false),
+            ]
+        );
+        assert_eq!(
+            messages,
+            [
+                "Skipped: cycle (folder already visited)",
+                "Skipped: pipe (FIFO)",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copied_links_to_files_are_kept_as_links() {
+        let (files, messages) = walk_links(SymlinkPolicy::Copy);
+
+        assert_eq!(
+            files,
+            [
+                (PathBuf::from("file_link.jpg"), true),
+                (PathBuf::from("own.jpg"), false),
+            ]
+        );
+        assert_eq!(
+            messages,
+            [
+                "Skipped: cycle (symbolic link to a folder)",
+                "Skipped: folder_link (symbolic link to a folder)",
+                "Skipped: pipe (FIFO)",
+            ]
+        );
+    }
+}